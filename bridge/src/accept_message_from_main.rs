@@ -18,6 +18,7 @@ use contracts;
 use error::{self, ResultExt};
 use futures::{Async, Future, Poll};
 use helpers::{self, AsyncCall, AsyncTransaction};
+use relay_log::{Chain, Direction, RelayContext};
 use relay_stream::LogToFuture;
 use side_contract::SideContract;
 use main_contract::MainContract;
@@ -33,7 +34,9 @@ pub struct LogToAcceptMessageFromMain<T> {
 impl<T: Transport> LogToFuture for LogToAcceptMessageFromMain<T> {
     type Future = AcceptMessageFromMain<T>;
 
-    fn log_to_future(&self, log: &Log) -> Self::Future {
+    const DIRECTION: Direction = Direction::MainToSide;
+
+    fn log_to_future(&self, log: &Log) -> Result<Self::Future, error::Error> {
         AcceptMessageFromMain::new(log, self.side.clone(), self.main.clone())
     }
 }
@@ -47,46 +50,58 @@ enum State<T: Transport> {
     AwaitTxSent(AsyncTransaction<T>),
 }
 
+/// `Future` that is responsible for calling `sideContract.acceptMessage`
+/// for a single `mainContract.RelayMessage` event.
+/// these get created by the `accept_message_from_main` `RelayStream` that's part
+/// of the `Bridge`.
 pub struct AcceptMessageFromMain<T: Transport> {
     state: State<T>,
     main_tx_hash: H256,
     sender: Address,
     recipient: Address,
     side: SideContract<T>,
+    context: RelayContext,
 }
 
 impl<T: Transport> AcceptMessageFromMain<T> {
-    pub fn new(raw_log: &Log, side: SideContract<T>, main: MainContract<T>) -> Self {
+    /// fails if `raw_log` is not a mined `mainContract.RelayMessage` event
+    pub fn new(raw_log: &Log, side: SideContract<T>, main: MainContract<T>) -> Result<Self, error::Error> {
         let main_tx_hash = raw_log
             .transaction_hash
-            .expect("`log` must be mined and contain `transaction_hash`. q.e.d.");
-
+            .ok_or_else(|| "AcceptMessageFromMain: `log` is not mined and has no `transaction_hash`")?;
 
         let log = helpers::parse_log(contracts::main::events::relay_message::parse_log, raw_log)
-            .expect("`log` must be for a relay message. q.e.d.");
+            .chain_err(|| "AcceptMessageFromMain: `log` is not a valid `RelayMessage` event")?;
 
         let sender = log.sender;
         let recipient = log.recipient;
 
-        info!("{:?} - step 1/4 - fetch message using message_id", main_tx_hash);
+        let mut context = RelayContext::new(Direction::MainToSide, main_tx_hash);
+        context.message_id = Some(log.message_id);
+        context.step("1/3", Chain::Main, "fetch message using message_id");
         let future = main.relayed_message_by_id(log.message_id);
         let state = State::AwaitMessage(future);
 
-        AcceptMessageFromMain {
+        Ok(AcceptMessageFromMain {
             state,
             main_tx_hash,
             sender,
             recipient,
             side,
-        }
+            context,
+        })
     }
-}
 
-impl<T: Transport> Future for AcceptMessageFromMain<T> {
-    type Item = Option<H256>;
-    type Error = error::Error;
+    /// the step the relay is currently in and the chain it's waiting for
+    pub fn step(&self) -> (&'static str, Chain) {
+        match self.state {
+            State::AwaitMessage(_) => ("1/3", Chain::Main),
+            State::AwaitAlreadyAccepted { .. } => ("2/3", Chain::Side),
+            State::AwaitTxSent(_) => ("3/3", Chain::Side),
+        }
+    }
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+    fn poll_relay(&mut self) -> Poll<Option<H256>, error::Error> {
         loop {
             let next_state = match self.state {
                 State::AwaitMessage(ref mut future) => {
@@ -96,7 +111,7 @@ impl<T: Transport> Future for AcceptMessageFromMain<T> {
                             .chain_err(|| "AcceptMessageFromMain: failed to fetch the message")
                     );
 
-                    info!("{:?} - 2/4 - checking if the message is already signed", self.main_tx_hash);
+                    self.context.step("2/3", Chain::Side, "checking if the message is already accepted");
                     State::AwaitAlreadyAccepted {
                         message: message.clone(),
                         future: self.side.is_message_accepted_from_main(
@@ -114,11 +129,11 @@ impl<T: Transport> Future for AcceptMessageFromMain<T> {
                             .chain_err(|| "AcceptMessageFromMain: failed to check if already accepted")
                     );
                     if has_already_accepted {
-                        info!("{:?} - DONE - already accepted", self.main_tx_hash);
+                        self.context.done(Chain::Side, None, "already accepted");
                         return Ok(Async::Ready(None));
                     }
 
-                    info!("{:?} - 3/4 - accepting the message", self.main_tx_hash);
+                    self.context.step("3/3", Chain::Side, "accepting the message");
                     State::AwaitTxSent(self.side.accept_message_from_main(
                         self.main_tx_hash,
                         message.clone(),
@@ -136,7 +151,7 @@ impl<T: Transport> Future for AcceptMessageFromMain<T> {
                                 main_tx_hash
                             ))
                     );
-                    info!("{:?} - DONE - accepted", self.main_tx_hash);
+                    self.context.done(Chain::Side, Some(side_tx_hash), "accepted");
                     return Ok(Async::Ready(Some(side_tx_hash)));
                 },
             };
@@ -145,6 +160,19 @@ impl<T: Transport> Future for AcceptMessageFromMain<T> {
     }
 }
 
+impl<T: Transport> Future for AcceptMessageFromMain<T> {
+    type Item = Option<H256>;
+    type Error = error::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.poll_relay().map_err(|err| {
+            let (step, chain) = self.step();
+            self.context.failed(step, chain, &err);
+            err
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -260,7 +288,7 @@ mod tests {
             sign_side_to_main_gas_price: 0.into(),
         };
 
-        let future = AcceptMessageFromMain::new(&raw_log, side_contract, main_contract);
+        let future = AcceptMessageFromMain::new(&raw_log, side_contract, main_contract).unwrap();
 
         let mut event_loop = Core::new().unwrap();
         let result = event_loop.run(future).unwrap();
@@ -364,7 +392,7 @@ mod tests {
             sign_side_to_main_gas_price: 0.into(),
         };
 
-        let future = AcceptMessageFromMain::new(&raw_log, side_contract, main_contract);
+        let future = AcceptMessageFromMain::new(&raw_log, side_contract, main_contract).unwrap();
 
         let mut event_loop = Core::new().unwrap();
         let result = event_loop.run(future).unwrap();
@@ -0,0 +1,386 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! optional JSON-RPC API to inspect and steer a running bridge node.
+//!
+//! served over http on `admin.addr`. if `admin.token` is set every request
+//! must carry `Authorization: Bearer <token>`. otherwise only requests from
+//! localhost are accepted.
+//!
+//! methods:
+//!
+//! - `bridge_status` chain heads, checkpoints and their lag, pause reasons
+//! - `bridge_inFlightRelays` relays that haven't completed yet and their latest step
+//! - `bridge_quarantinedLogs` logs no relay could be created for and why
+//! - `bridge_retryQuarantined` with params `{"direction": "main_to_side", "tx_hash": "0x..."}`
+//!   relays a transaction with quarantined logs again and releases them on success
+//! - `bridge_pause` / `bridge_resume` with params `{"direction": "main_to_side"}`
+//! - `bridge_relay` with params `{"direction": "main_to_side", "tx_hash": "0x..."}`
+//!
+//! there is no method to flush checkpoints. every checkpoint is written to the
+//! database as soon as the bridge yields it
+
+use database::State;
+use error;
+use futures::future;
+use futures::Future;
+use health::{HealthCheck, Readiness};
+use http_server::{Request, Response};
+use manual_relay::ManualRelay;
+use pause::{PauseHandle, PauseReason};
+use quarantine::{self, quarantined_logs};
+use relay_log::{in_flight_relays, Direction};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{self, Value};
+use std::cell::RefCell;
+use std::rc::Rc;
+use susyweb::types::H256;
+use susyweb::Transport;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+/// a JSON-RPC error object
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new<S: Into<String>>(code: i64, message: S) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// a parsed JSON-RPC call
+#[derive(Debug, PartialEq)]
+pub enum Call {
+    Status,
+    InFlightRelays,
+    QuarantinedLogs,
+    RetryQuarantined(Direction, H256),
+    Pause(Direction),
+    Resume(Direction),
+    Relay(Direction, H256),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DirectionParams {
+    direction: Direction,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RelayParams {
+    direction: Direction,
+    tx_hash: H256,
+}
+
+fn params<P: DeserializeOwned>(params: Option<Value>) -> Result<P, RpcError> {
+    serde_json::from_value(params.unwrap_or(Value::Null))
+        .map_err(|err| RpcError::new(INVALID_PARAMS, format!("invalid params: {}", err)))
+}
+
+/// parses the body of a request into its id and `Call`.
+/// the id is `Value::Null` if the body doesn't contain one
+pub fn parse_call(body: &[u8]) -> (Value, Result<Call, RpcError>) {
+    let mut request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(err) => return (Value::Null, Err(RpcError::new(PARSE_ERROR, format!("parse error: {}", err)))),
+    };
+    let object = match request.as_object_mut() {
+        Some(object) => object,
+        None => return (Value::Null, Err(RpcError::new(INVALID_REQUEST, "request must be an object"))),
+    };
+    let id = object.remove("id").unwrap_or(Value::Null);
+    let method = match object.remove("method") {
+        Some(Value::String(method)) => method,
+        _ => return (id, Err(RpcError::new(INVALID_REQUEST, "`method` must be a string"))),
+    };
+    let call = match method.as_str() {
+        "bridge_status" => Ok(Call::Status),
+        "bridge_inFlightRelays" => Ok(Call::InFlightRelays),
+        "bridge_quarantinedLogs" => Ok(Call::QuarantinedLogs),
+        "bridge_retryQuarantined" => params(object.remove("params"))
+            .map(|p: RelayParams| Call::RetryQuarantined(p.direction, p.tx_hash)),
+        "bridge_pause" => params(object.remove("params")).map(|p: DirectionParams| Call::Pause(p.direction)),
+        "bridge_resume" => params(object.remove("params")).map(|p: DirectionParams| Call::Resume(p.direction)),
+        "bridge_relay" => {
+            params(object.remove("params")).map(|p: RelayParams| Call::Relay(p.direction, p.tx_hash))
+        }
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("method `{}` not found", method))),
+    };
+    (id, call)
+}
+
+/// whether `request` may use the admin API
+pub fn is_authorized(request: &Request, token: Option<&str>) -> bool {
+    match token {
+        Some(token) => request.header("Authorization").map_or(false, |header| {
+            constant_time_eq(header.as_bytes(), format!("Bearer {}", token).as_bytes())
+        }),
+        None => request.peer.ip().is_loopback(),
+    }
+}
+
+/// compares all bytes so the time taken doesn't tell how much of a guessed token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+fn rpc_response(id: Value, result: Result<Value, RpcError>) -> Response {
+    let body = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    };
+    let mut body = serde_json::to_string_pretty(&body).expect("serialization can't fail; qed");
+    body.push('\n');
+    Response::json(200, body)
+}
+
+fn to_value<S: Serialize>(value: &S) -> Value {
+    serde_json::to_value(value).expect("serialization can't fail; qed")
+}
+
+/// pause state of a single direction
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PauseStatus {
+    pub direction: Direction,
+    pub paused: bool,
+    pub reasons: Vec<PauseReason>,
+}
+
+impl PauseStatus {
+    fn new(direction: Direction, pause: &PauseHandle) -> Self {
+        PauseStatus {
+            direction,
+            paused: pause.is_paused(),
+            reasons: pause.reasons(),
+        }
+    }
+}
+
+/// result of `bridge_status`
+#[derive(Debug, PartialEq, Serialize)]
+pub struct NodeStatus {
+    pub state: State,
+    pub readiness: Readiness,
+    pub pauses: Vec<PauseStatus>,
+}
+
+/// answers the requests to the admin API. see module docs
+pub struct AdminApi<T> {
+    /// replaced on reload
+    pub health: Rc<RefCell<HealthCheck<T>>>,
+    /// replaced on reload
+    pub relay: Rc<RefCell<ManualRelay<T>>>,
+    /// latest persisted state
+    pub state: Rc<RefCell<State>>,
+    pub pauses: Vec<(Direction, PauseHandle)>,
+    pub token: Option<String>,
+}
+
+impl<T: Transport + 'static> AdminApi<T>
+where
+    T::Out: 'static,
+{
+    fn pause_handle(&self, direction: Direction) -> Result<&PauseHandle, RpcError> {
+        self.pauses
+            .iter()
+            .find(|&&(pause_direction, _)| pause_direction == direction)
+            .map(|&(_, ref pause)| pause)
+            .ok_or_else(|| RpcError::new(SERVER_ERROR, format!("no relay for direction {}", direction)))
+    }
+
+    fn call(&self, call: Call) -> Box<Future<Item = Value, Error = RpcError>> {
+        match call {
+            Call::Status => {
+                let state = self.state.borrow().clone();
+                let pauses = self.pauses
+                    .iter()
+                    .map(|&(direction, ref pause)| PauseStatus::new(direction, pause))
+                    .collect::<Vec<_>>();
+                Box::new(
+                    self.health
+                        .borrow()
+                        .readiness(state.clone())
+                        .map_err(|_| RpcError::new(SERVER_ERROR, "querying readiness failed"))
+                        .map(move |readiness| {
+                            to_value(&NodeStatus {
+                                state,
+                                readiness,
+                                pauses,
+                            })
+                        }),
+                )
+            }
+            Call::InFlightRelays => Box::new(future::ok(to_value(&in_flight_relays()))),
+            Call::QuarantinedLogs => Box::new(future::ok(to_value(&quarantined_logs()))),
+            Call::RetryQuarantined(direction, tx_hash) => {
+                let quarantined = quarantined_logs()
+                    .iter()
+                    .any(|log| log.direction == direction && log.transaction_hash == Some(tx_hash));
+                if !quarantined {
+                    return Box::new(future::err(RpcError::new(
+                        SERVER_ERROR,
+                        format!("no {} log of {:?} is quarantined", direction, tx_hash),
+                    )));
+                }
+                info!("admin: retrying quarantined {} logs of {:?}", direction, tx_hash);
+                Box::new(
+                    self.relay
+                        .borrow()
+                        .relay(direction, tx_hash)
+                        .and_then(move |outcomes| -> Result<Value, error::Error> {
+                            let released = quarantine::release(direction, tx_hash)?;
+                            info!("admin: released {} quarantined {} logs of {:?}", released, direction, tx_hash);
+                            Ok(to_value(&outcomes))
+                        })
+                        .map_err(|err| RpcError::new(SERVER_ERROR, err.to_string())),
+                )
+            }
+            Call::Pause(direction) => Box::new(future::result(self.pause_handle(direction).map(|pause| {
+                if pause.pause(PauseReason::Operator) {
+                    info!("admin: paused {}", direction);
+                }
+                to_value(&PauseStatus::new(direction, pause))
+            }))),
+            Call::Resume(direction) => Box::new(future::result(self.pause_handle(direction).map(|pause| {
+                if pause.resume(PauseReason::Operator) {
+                    info!("admin: resumed {}", direction);
+                }
+                to_value(&PauseStatus::new(direction, pause))
+            }))),
+            Call::Relay(direction, tx_hash) => {
+                info!("admin: relaying {} of {:?}", direction, tx_hash);
+                Box::new(
+                    self.relay
+                        .borrow()
+                        .relay(direction, tx_hash)
+                        .map(|outcomes| to_value(&outcomes))
+                        .map_err(|err| RpcError::new(SERVER_ERROR, err.to_string())),
+                )
+            }
+        }
+    }
+
+    /// answers a single http request
+    pub fn respond(&self, request: Request) -> Box<Future<Item = Response, Error = ()>> {
+        if !is_authorized(&request, self.token.as_ref().map(String::as_str)) {
+            warn!("admin: rejected unauthorized request from {}", request.peer);
+            return Box::new(future::ok(Response::text(401, "unauthorized\n".into())));
+        }
+        if request.method != "POST" {
+            return Box::new(future::ok(Response::text(405, "use POST\n".into())));
+        }
+        let (id, call) = parse_call(&request.body);
+        match call {
+            Ok(call) => Box::new(self.call(call).then(move |result| Ok(rpc_response(id, result)))),
+            Err(error) => Box::new(future::ok(rpc_response(id, Err(error)))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(peer: &str, headers: Vec<(&str, &str)>) -> Request {
+        Request {
+            method: "POST".into(),
+            path: "/".into(),
+            headers: headers
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                .collect(),
+            body: Vec::new(),
+            peer: peer.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        assert!(is_authorized(&request("127.0.0.1:5000", vec![]), None));
+        assert!(!is_authorized(&request("10.0.0.1:5000", vec![]), None));
+        assert!(!is_authorized(&request("127.0.0.1:5000", vec![]), Some("secret")));
+        assert!(!is_authorized(
+            &request("10.0.0.1:5000", vec![("Authorization", "Bearer wrong")]),
+            Some("secret")
+        ));
+        assert!(!is_authorized(
+            &request("10.0.0.1:5000", vec![("Authorization", "Bearer secre")]),
+            Some("secret")
+        ));
+        assert!(is_authorized(
+            &request("10.0.0.1:5000", vec![("authorization", "Bearer secret")]),
+            Some("secret")
+        ));
+    }
+
+    #[test]
+    fn test_parse_call() {
+        let (id, call) = parse_call(br#"{"jsonrpc": "2.0", "id": 1, "method": "bridge_status"}"#);
+        assert_eq!(id, json!(1));
+        assert_eq!(call, Ok(Call::Status));
+
+        let (_, call) = parse_call(
+            br#"{"jsonrpc": "2.0", "id": 2, "method": "bridge_pause", "params": {"direction": "side_to_main_sign"}}"#,
+        );
+        assert_eq!(call, Ok(Call::Pause(Direction::SideToMainSign)));
+
+        let (_, call) = parse_call(
+            br#"{"jsonrpc": "2.0", "id": 3, "method": "bridge_relay", "params": {"direction": "main_to_side", "tx_hash": "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364"}}"#,
+        );
+        assert_eq!(
+            call,
+            Ok(Call::Relay(
+                Direction::MainToSide,
+                "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into()
+            ))
+        );
+
+        let (_, call) = parse_call(
+            br#"{"jsonrpc": "2.0", "id": 4, "method": "bridge_retryQuarantined", "params": {"direction": "side_to_main_sign", "tx_hash": "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364"}}"#,
+        );
+        assert_eq!(
+            call,
+            Ok(Call::RetryQuarantined(
+                Direction::SideToMainSign,
+                "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into()
+            ))
+        );
+
+        let (id, call) = parse_call(br#"{"jsonrpc": "2.0", "id": "a", "method": "bridge_unknown"}"#);
+        assert_eq!(id, json!("a"));
+        assert_eq!(call.unwrap_err().code, METHOD_NOT_FOUND);
+
+        let (_, call) = parse_call(br#"{"jsonrpc": "2.0", "id": 4, "method": "bridge_resume", "params": {}}"#);
+        assert_eq!(call.unwrap_err().code, INVALID_PARAMS);
+
+        let (id, call) = parse_call(b"{");
+        assert_eq!(id, Value::Null);
+        assert_eq!(call.unwrap_err().code, PARSE_ERROR);
+    }
+}
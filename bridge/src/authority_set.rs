@@ -0,0 +1,406 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! the authorities and required signatures the bridge contracts were deployed with.
+//!
+//! the contracts decide which signatures count. the bridge reads their authority sets
+//! at startup and every once in a while after that and alerts if they drift apart
+//! from each other or from `authorities` in the config
+
+use config::Authorities;
+use contracts;
+use error::{self, ResultExt};
+use futures::future::{self, loop_fn, Loop};
+use futures::{Future, Stream};
+use helpers::with_timeout;
+use main_contract::MainContract;
+use side_contract::SideContract;
+use sofabi::FunctionOutputDecoder;
+use sophon_types::U256;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::time::Duration;
+use tokio_timer::Timer;
+use susyweb::api::Namespace;
+use susyweb::types::{Address, Bytes, CallRequest};
+use susyweb::{self, Transport};
+
+/// the contracts have no getter for the length of the authority list.
+/// stop reading it after that many entries
+const MAX_AUTHORITIES: u64 = 256;
+
+type BoxFuture<I> = Box<Future<Item = I, Error = error::Error>>;
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct AuthoritySet {
+    pub required_signatures: u32,
+    pub accounts: Vec<Address>,
+}
+
+impl AuthoritySet {
+    pub fn from_config(authorities: &Authorities) -> Self {
+        AuthoritySet {
+            required_signatures: authorities.required_signatures,
+            accounts: authorities.accounts.clone(),
+        }
+    }
+
+    /// human readable differences between `self` and `other`.
+    /// the order of the accounts doesn't matter
+    pub fn differences(&self, other: &AuthoritySet) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.required_signatures != other.required_signatures {
+            differences.push(format!(
+                "required signatures {} != {}",
+                self.required_signatures, other.required_signatures
+            ));
+        }
+        let accounts = self.accounts.iter().collect::<HashSet<_>>();
+        let other_accounts = other.accounts.iter().collect::<HashSet<_>>();
+        let mut missing = other_accounts.difference(&accounts).collect::<Vec<_>>();
+        missing.sort();
+        let mut extra = accounts.difference(&other_accounts).collect::<Vec<_>>();
+        extra.sort();
+        if !missing.is_empty() {
+            differences.push(format!("authorities {:?} missing", missing));
+        }
+        if !extra.is_empty() {
+            differences.push(format!("authorities {:?} not expected", extra));
+        }
+        differences
+    }
+}
+
+/// reads `authority(0)`, `authority(1)`, ... until a call reverts.
+/// all other errors fail the read so a list is never silently cut short
+fn read_authorities<F>(authority: F) -> BoxFuture<Vec<Address>>
+where
+    F: Fn(u64) -> BoxFuture<Address> + 'static,
+{
+    Box::new(loop_fn(Vec::new(), move |mut accounts| -> BoxFuture<Loop<Vec<Address>, Vec<Address>>> {
+        if accounts.len() as u64 == MAX_AUTHORITIES {
+            return Box::new(future::err(format!("the contract has more than {} authorities", MAX_AUTHORITIES).into()));
+        }
+        Box::new(authority(accounts.len() as u64).then(move |result| match result {
+            Ok(account) => {
+                accounts.push(account);
+                Ok(Loop::Continue(accounts))
+            }
+            // out of bounds
+            Err(ref err) if err.is_reverted() => Ok(Loop::Break(accounts)),
+            Err(err) => Err(err).chain_err(|| format!("reading authority {} failed", accounts.len())),
+        }))
+    }))
+}
+
+/// calls `authorities(index)` of `contract`. an out of bounds index reverts, which
+/// some nodes report as a call without output. that fails with `EmptyCallResult`
+fn read_authority<T, D>(transport: &T, contract: Address, timeout: Duration, call: (Vec<u8>, D)) -> BoxFuture<Address>
+where
+    T: Transport,
+    T::Out: 'static,
+    D: FunctionOutputDecoder<Output = Address> + 'static,
+{
+    let (payload, decoder) = call;
+    let request = CallRequest {
+        from: None,
+        to: contract,
+        gas: None,
+        gas_price: None,
+        value: None,
+        data: Some(Bytes(payload)),
+    };
+    let output = with_timeout(susyweb::api::Sof::new(transport).call(request, None), timeout);
+    Box::new(output.and_then(move |output| -> Result<Address, error::Error> {
+        if output.0.is_empty() {
+            bail!(error::ErrorKind::EmptyCallResult);
+        }
+        decoder
+            .decode(&output.0)
+            .chain_err(|| format!("failed to decode authority {:?}", output))
+    }))
+}
+
+/// `Future` resolving to the `AuthoritySet` of the main contract
+pub fn main_authority_set<T: Transport + 'static>(contract: &MainContract<T>) -> BoxFuture<AuthoritySet>
+where
+    T::Out: 'static,
+{
+    let authorities = contract.clone();
+    Box::new(
+        contract
+            .required_signatures()
+            .and_then(move |required_signatures| {
+                let authority = move |index| {
+                    read_authority(
+                        &authorities.transport,
+                        authorities.contract_address,
+                        authorities.request_timeout,
+                        contracts::main::functions::authorities::call(U256::from(index)),
+                    )
+                };
+                read_authorities(authority)
+                    .map(move |accounts| AuthoritySet {
+                        required_signatures: required_signatures.low_u32(),
+                        accounts,
+                    })
+            }),
+    )
+}
+
+/// `Future` resolving to the `AuthoritySet` of the side contract
+pub fn side_authority_set<T: Transport + 'static>(contract: &SideContract<T>) -> BoxFuture<AuthoritySet>
+where
+    T::Out: 'static,
+{
+    let authorities = contract.clone();
+    Box::new(
+        contract
+            .required_signatures()
+            .and_then(move |required_signatures| {
+                let authority = move |index| {
+                    read_authority(
+                        &authorities.transport,
+                        authorities.contract_address,
+                        authorities.request_timeout,
+                        contracts::side::functions::authorities::call(U256::from(index)),
+                    )
+                };
+                read_authorities(authority)
+                    .map(move |accounts| AuthoritySet {
+                        required_signatures: required_signatures.low_u32(),
+                        accounts,
+                    })
+            }),
+    )
+}
+
+/// the authority sets of both contracts
+#[derive(Debug, PartialEq, Clone)]
+pub struct OnChainAuthorities {
+    pub main: AuthoritySet,
+    pub side: AuthoritySet,
+}
+
+impl OnChainAuthorities {
+    /// differences between the contracts and between each contract and `configured`
+    pub fn drift(&self, configured: &AuthoritySet) -> Vec<String> {
+        let prefixed = |prefix: &'static str, differences: Vec<String>| {
+            differences
+                .into_iter()
+                .map(move |difference| format!("{}: {}", prefix, difference))
+        };
+        prefixed("side contract compared to main contract", self.side.differences(&self.main))
+            .chain(prefixed("main contract compared to config", self.main.differences(configured)))
+            .chain(prefixed("side contract compared to config", self.side.differences(configured)))
+            .collect()
+    }
+
+    /// every contract has authorities and doesn't require more signatures than it has authorities.
+    /// anything else means that a read was cut short
+    pub fn is_complete(&self) -> bool {
+        [&self.main, &self.side]
+            .iter()
+            .all(|set| set.required_signatures > 0 && set.required_signatures as usize <= set.accounts.len())
+    }
+}
+
+/// `Future` resolving to the `OnChainAuthorities` of both contracts.
+/// fails if either of them can't be read completely
+pub fn on_chain_authorities<T: Transport + 'static>(main: &MainContract<T>, side: &SideContract<T>) -> BoxFuture<OnChainAuthorities>
+where
+    T::Out: 'static,
+{
+    Box::new(
+        main_authority_set(main)
+            .join(side_authority_set(side))
+            .and_then(|(main, side)| -> Result<_, error::Error> {
+                let authorities = OnChainAuthorities { main, side };
+                if !authorities.is_complete() {
+                    bail!("read incomplete authority sets {:?}", authorities);
+                }
+                Ok(authorities)
+            }),
+    )
+}
+
+/// reads the authority sets of both contracts every `poll_interval`
+pub struct AuthorityWatch<T> {
+    pub main: MainContract<T>,
+    pub side: SideContract<T>,
+    /// `authorities` in the config
+    pub configured: AuthoritySet,
+    pub poll_interval: Duration,
+    /// the authority sets read last. shared with the caller
+    pub current: Rc<RefCell<OnChainAuthorities>>,
+    /// called once the authority sets changed
+    pub on_change: Rc<Fn(&OnChainAuthorities)>,
+}
+
+impl<T: Transport + 'static> AuthorityWatch<T>
+where
+    T::Out: 'static,
+{
+    fn check(&self) -> Box<Future<Item = (), Error = ()>> {
+        let current = self.current.clone();
+        let configured = self.configured.clone();
+        let on_change = self.on_change.clone();
+        Box::new(on_chain_authorities(&self.main, &self.side).then(move |result| {
+            match result {
+                Ok(ref authorities) if *authorities != *current.borrow() => {
+                    warn!("AuthorityWatch: authorities of the contracts changed to {:?}", authorities);
+                    for drift in authorities.drift(&configured) {
+                        error!("AuthorityWatch: authorities differ. {}", drift);
+                    }
+                    *current.borrow_mut() = authorities.clone();
+                    on_change(authorities);
+                }
+                Ok(_) => {}
+                // a failed read says nothing about the authorities. keep the current ones
+                Err(err) => warn!("AuthorityWatch: reading the authorities of the contracts failed: {}", err),
+            }
+            Ok(())
+        }))
+    }
+
+    pub fn run(self) -> Box<Future<Item = (), Error = ()>> {
+        let interval = Timer::default().interval(self.poll_interval);
+        Box::new(
+            interval
+                .map_err(|err| error!("AuthorityWatch: polling interval failed: {}", err))
+                .for_each(move |_| self.check()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_hex::ToHex;
+    use sofabi;
+
+    #[test]
+    fn test_differences() {
+        let expected = AuthoritySet {
+            required_signatures: 2,
+            accounts: vec![
+                "0000000000000000000000000000000000000001".into(),
+                "0000000000000000000000000000000000000002".into(),
+                "0000000000000000000000000000000000000003".into(),
+            ],
+        };
+        let same = AuthoritySet {
+            required_signatures: 2,
+            accounts: vec![
+                "0000000000000000000000000000000000000003".into(),
+                "0000000000000000000000000000000000000001".into(),
+                "0000000000000000000000000000000000000002".into(),
+            ],
+        };
+        assert!(same.differences(&expected).is_empty());
+
+        let drifted = AuthoritySet {
+            required_signatures: 3,
+            accounts: vec![
+                "0000000000000000000000000000000000000001".into(),
+                "0000000000000000000000000000000000000002".into(),
+                "0000000000000000000000000000000000000004".into(),
+            ],
+        };
+        let differences = drifted.differences(&expected);
+        assert_eq!(differences.len(), 3);
+        assert_eq!(differences[0], "required signatures 3 != 2");
+    }
+
+    #[test]
+    fn test_read_authorities() {
+        let authority = |failing: u64| {
+            move |index: u64| -> BoxFuture<Address> {
+                if index < failing {
+                    Box::new(future::ok(Address::from(index + 1)))
+                } else if failing == 2 {
+                    Box::new(future::err(error::ErrorKind::EmptyCallResult.into()))
+                } else {
+                    Box::new(future::err(error::ErrorKind::TimedOut.into()))
+                }
+            }
+        };
+        assert_eq!(
+            read_authorities(authority(2)).wait().unwrap(),
+            vec![Address::from(1), Address::from(2)]
+        );
+        // a failed read isn't the end of the list
+        assert!(read_authorities(authority(1)).wait().is_err());
+    }
+
+    #[test]
+    fn test_read_authority() {
+        let contract: Address = "0000000000000000000000000000000000000dd1".into();
+        let call = |index: u64| contracts::side::functions::authorities::call(U256::from(index));
+        let authority: Address = "0000000000000000000000000000000000000002".into();
+        let transport = mock_transport!(
+            "sof_call" =>
+                req => json!([{
+                    "data": format!("0x{}", call(0).0.to_hex()),
+                    "to": contract,
+                }, "latest"]),
+                res => json!(format!("0x{}", sofabi::encode(&[sofabi::Token::Address(authority)]).to_hex()));
+            "sof_call" =>
+                req => json!([{
+                    "data": format!("0x{}", call(1).0.to_hex()),
+                    "to": contract,
+                }, "latest"]),
+                res => json!("0x");
+        );
+        let timeout = Duration::from_secs(1);
+
+        assert_eq!(read_authority(&transport, contract, timeout, call(0)).wait().unwrap(), authority);
+        // out of bounds
+        assert!(read_authority(&transport, contract, timeout, call(1)).wait().unwrap_err().is_reverted());
+    }
+
+    #[test]
+    fn test_drift() {
+        let configured = AuthoritySet {
+            required_signatures: 1,
+            accounts: vec!["0000000000000000000000000000000000000001".into()],
+        };
+        let on_chain = OnChainAuthorities {
+            main: configured.clone(),
+            side: configured.clone(),
+        };
+        assert!(on_chain.drift(&configured).is_empty());
+        assert!(on_chain.is_complete());
+
+        let on_chain = OnChainAuthorities {
+            side: AuthoritySet {
+                required_signatures: 2,
+                ..configured.clone()
+            },
+            ..on_chain
+        };
+        assert_eq!(
+            on_chain.drift(&configured),
+            vec![
+                "side contract compared to main contract: required signatures 2 != 1".to_string(),
+                "side contract compared to config: required signatures 2 != 1".into(),
+            ]
+        );
+        // a cut short read of the side contract
+        assert!(!on_chain.is_complete());
+    }
+}
@@ -0,0 +1,290 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! monitoring of the authority balance on both chains.
+//!
+//! relaying costs the authority real funds. if the balance on a chain drops
+//! below the critical threshold every transaction on that chain would fail.
+//! the relays that send transactions on that chain are therefore paused
+//! until the account is topped up.
+
+use bridge::Bridge;
+use config::{BalanceConfig, BalanceThresholds};
+use error::{self, ResultExt};
+use futures::{Future, Stream};
+use pause::{PauseHandle, PauseReason};
+use relay_log::{Chain, Direction};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+use tokio_timer::Timer;
+use susyweb;
+use susyweb::api::Namespace;
+use susyweb::types::{Address, U256};
+use susyweb::Transport;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BalanceLevel {
+    Sufficient,
+    /// below `warn`
+    Low,
+    /// below `critical`
+    Critical,
+}
+
+impl BalanceLevel {
+    pub fn from_thresholds(balance: U256, thresholds: &BalanceThresholds) -> Self {
+        if balance < thresholds.critical {
+            BalanceLevel::Critical
+        } else if balance < thresholds.warn {
+            BalanceLevel::Low
+        } else {
+            BalanceLevel::Sufficient
+        }
+    }
+}
+
+/// thresholds of a single chain and the relays that pay for transactions on it
+#[derive(Clone)]
+struct BalanceGuard {
+    chain: Chain,
+    /// shared with `ThresholdsHandle`
+    thresholds: Rc<RefCell<BalanceThresholds>>,
+    pauses: Vec<PauseHandle>,
+}
+
+impl BalanceGuard {
+    /// logs and pauses or resumes the relays according to `balance`
+    fn apply(&self, balance: U256) -> BalanceLevel {
+        let thresholds = self.thresholds.borrow().clone();
+        let level = BalanceLevel::from_thresholds(balance, &thresholds);
+
+        match level {
+            BalanceLevel::Critical => {
+                let newly_paused = self.pauses
+                    .iter()
+                    .fold(false, |paused, pause| pause.pause(PauseReason::LowBalance) || paused);
+                error!(
+                    "authority balance on {} is {} which is below the critical threshold {}. {} relays sending transactions on {} until the account is topped up",
+                    self.chain,
+                    balance,
+                    thresholds.critical,
+                    if newly_paused { "suspending" } else { "still suspending" },
+                    self.chain
+                );
+            }
+            BalanceLevel::Low => warn!(
+                "authority balance on {} is {} which is below the warning threshold {}",
+                self.chain, balance, thresholds.warn
+            ),
+            BalanceLevel::Sufficient => {}
+        }
+
+        if level != BalanceLevel::Critical {
+            let resumed = self.pauses
+                .iter()
+                .fold(false, |resumed, pause| pause.resume(PauseReason::LowBalance) || resumed);
+            if resumed {
+                info!(
+                    "authority balance on {} is {} again. resuming relays sending transactions on {}",
+                    self.chain, balance, self.chain
+                );
+            }
+        }
+
+        level
+    }
+}
+
+struct ChainBalance<T> {
+    transport: T,
+    request_timeout: Duration,
+    guard: BalanceGuard,
+}
+
+fn fetch_balance<T: Transport + 'static>(
+    chain: &ChainBalance<T>,
+    authority_address: Address,
+) -> Box<Future<Item = U256, Error = error::Error>>
+where
+    T::Out: 'static,
+{
+    let future = susyweb::api::Sof::new(&chain.transport)
+        .balance(authority_address, None)
+        .from_err::<error::Error>();
+    let name = chain.guard.chain;
+    Box::new(
+        Timer::default()
+            .timeout(future, chain.request_timeout)
+            .then(move |result| {
+                result.chain_err(|| format!("BalanceMonitor: fetching authority balance on {} failed", name))
+            }),
+    )
+}
+
+/// replaces the thresholds of a running `BalanceMonitor`
+#[derive(Clone)]
+pub struct ThresholdsHandle {
+    main: Rc<RefCell<BalanceThresholds>>,
+    side: Rc<RefCell<BalanceThresholds>>,
+}
+
+impl ThresholdsHandle {
+    /// applies from the next balance check on
+    pub fn set(&self, config: &BalanceConfig) {
+        *self.main.borrow_mut() = config.main.clone();
+        *self.side.borrow_mut() = config.side.clone();
+    }
+}
+
+/// polls the authority balance on both chains every `poll_interval`
+pub struct BalanceMonitor<T> {
+    authority_address: Address,
+    poll_interval: Duration,
+    main: ChainBalance<T>,
+    side: ChainBalance<T>,
+}
+
+impl<T: Transport + 'static> BalanceMonitor<T>
+where
+    T::Out: 'static,
+{
+    /// `SideToMainSignatures` sends transactions on `main`.
+    /// `MainToSide` and `SideToMainSign` send transactions on `side`.
+    pub fn new(
+        main_transport: T,
+        side_transport: T,
+        authority_address: Address,
+        main_request_timeout: Duration,
+        side_request_timeout: Duration,
+        config: &BalanceConfig,
+        bridge: &Bridge<T>,
+    ) -> Self {
+        Self {
+            authority_address,
+            poll_interval: config.poll_interval,
+            main: ChainBalance {
+                transport: main_transport,
+                request_timeout: main_request_timeout,
+                guard: BalanceGuard {
+                    chain: Chain::Main,
+                    thresholds: Rc::new(RefCell::new(config.main.clone())),
+                    pauses: vec![bridge.pause_handle(Direction::SideToMainSignatures)],
+                },
+            },
+            side: ChainBalance {
+                transport: side_transport,
+                request_timeout: side_request_timeout,
+                guard: BalanceGuard {
+                    chain: Chain::Side,
+                    thresholds: Rc::new(RefCell::new(config.side.clone())),
+                    pauses: vec![
+                        bridge.pause_handle(Direction::MainToSide),
+                        bridge.pause_handle(Direction::SideToMainSign),
+                    ],
+                },
+            },
+        }
+    }
+
+    pub fn thresholds_handle(&self) -> ThresholdsHandle {
+        ThresholdsHandle {
+            main: self.main.guard.thresholds.clone(),
+            side: self.side.guard.thresholds.clone(),
+        }
+    }
+
+    /// fetches both balances once and applies them
+    pub fn check(&self) -> Box<Future<Item = (), Error = ()>> {
+        let main = fetch_balance(&self.main, self.authority_address).then(Ok::<_, ()>);
+        let side = fetch_balance(&self.side, self.authority_address).then(Ok::<_, ()>);
+        let guards = vec![self.main.guard.clone(), self.side.guard.clone()];
+        Box::new(main.join(side).map(move |(main, side)| {
+            for (guard, balance) in guards.into_iter().zip(vec![main, side]) {
+                match balance {
+                    Ok(balance) => {
+                        guard.apply(balance);
+                    }
+                    Err(err) => warn!("{}", err),
+                }
+            }
+        }))
+    }
+
+    /// checks the balances immediately and then every `poll_interval`, forever
+    pub fn run(self) -> Box<Future<Item = (), Error = ()>> {
+        let interval = Timer::default().interval(self.poll_interval);
+        Box::new(self.check().and_then(move |_| {
+            interval
+                .map_err(|err| error!("BalanceMonitor: polling interval failed: {}", err))
+                .for_each(move |_| self.check())
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard(pauses: Vec<PauseHandle>) -> BalanceGuard {
+        BalanceGuard {
+            chain: Chain::Main,
+            thresholds: Rc::new(RefCell::new(BalanceThresholds {
+                warn: 1000.into(),
+                critical: 100.into(),
+            })),
+            pauses,
+        }
+    }
+
+    #[test]
+    fn test_balance_level() {
+        let thresholds = BalanceThresholds {
+            warn: 1000.into(),
+            critical: 100.into(),
+        };
+        assert_eq!(BalanceLevel::from_thresholds(99.into(), &thresholds), BalanceLevel::Critical);
+        assert_eq!(BalanceLevel::from_thresholds(100.into(), &thresholds), BalanceLevel::Low);
+        assert_eq!(BalanceLevel::from_thresholds(1000.into(), &thresholds), BalanceLevel::Sufficient);
+        assert_eq!(
+            BalanceLevel::from_thresholds(0.into(), &BalanceThresholds::default()),
+            BalanceLevel::Sufficient
+        );
+    }
+
+    #[test]
+    fn test_chain_balance_pauses_below_critical_and_resumes_when_topped_up() {
+        let pause = PauseHandle::new();
+        let chain = guard(vec![pause.clone()]);
+
+        assert_eq!(chain.apply(500.into()), BalanceLevel::Low);
+        assert!(!pause.is_paused());
+
+        assert_eq!(chain.apply(50.into()), BalanceLevel::Critical);
+        assert_eq!(pause.reasons(), vec![PauseReason::LowBalance]);
+
+        assert_eq!(chain.apply(5000.into()), BalanceLevel::Sufficient);
+        assert!(!pause.is_paused());
+
+        // reloaded thresholds apply to the next check
+        *chain.thresholds.borrow_mut() = BalanceThresholds {
+            warn: 10000.into(),
+            critical: 6000.into(),
+        };
+        assert_eq!(chain.apply(5000.into()), BalanceLevel::Critical);
+        assert!(pause.is_paused());
+    }
+}
@@ -64,6 +64,13 @@ impl<T: Transport> BlockNumberStream<T> {
             state: State::AwaitInterval,
         }
     }
+
+    /// applies to requests made after the current one
+    pub fn set_options(&mut self, request_timeout: Duration, poll_interval: Duration, confirmations: u32) {
+        self.request_timeout = request_timeout;
+        self.confirmations = confirmations;
+        self.poll_interval = self.timer.interval(poll_interval);
+    }
 }
 
 impl<T: Transport> Stream for BlockNumberStream<T> {
@@ -13,7 +13,10 @@
 
 // You should have received a copy of the GNU General Public License
 // along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+use futures::task::{self, Task};
 use futures::{Async, Poll, Stream};
+use std::cell::RefCell;
+use std::rc::Rc;
 use susyweb::Transport;
 
 use database::State;
@@ -21,6 +24,8 @@ use error::{self, ResultExt};
 use log_stream::LogStream;
 use main_contract::MainContract;
 use accept_message_from_main;
+use pause::{PauseHandle, PauseReason};
+use relay_log::Direction;
 use relay_stream::RelayStream;
 use side_contract::SideContract;
 use side_to_main_sign;
@@ -33,46 +38,84 @@ use side_to_main_signatures;
 /// if polled polls all relay streams which causes them fetch
 /// all pending relays and relay them
 /// updates the database with results returned from relay streams.
-/// yields new state that should be persisted
+/// yields new state that should be persisted.
+/// ends after `ShutdownHandle::shutdown` once all relays in flight have completed
 pub struct Bridge<T: Transport> {
-    accept_message_from_main: RelayStream<LogStream<T>, accept_message_from_main::LogToAcceptMessageFromMain<T>>,
-    side_to_main_sign: RelayStream<LogStream<T>, side_to_main_sign::LogToSideToMainSign<T>>,
-    side_to_main_signatures:
-        RelayStream<LogStream<T>, side_to_main_signatures::LogToSideToMainSignatures<T>>,
+    accept_message_from_main: MainToSideStream<T>,
+    side_to_main_sign: SideToMainSignStream<T>,
+    side_to_main_signatures: SideToMainSignaturesStream<T>,
     state: State,
+    /// contracts with the current settings. used on restart
+    main_contract: MainContract<T>,
+    side_contract: SideContract<T>,
+    main_to_side_pause: PauseHandle,
+    side_to_main_sign_pause: PauseHandle,
+    side_to_main_signatures_pause: PauseHandle,
+    /// contracts with reloaded settings to apply on the next poll
+    reload: Rc<RefCell<Option<(MainContract<T>, SideContract<T>)>>>,
+    /// task polling the bridge. notified on shutdown and reload
+    task: Rc<RefCell<Option<Task>>>,
 }
 
+/// stops a `Bridge` gracefully. `clone`d versions share the same state
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    pauses: Vec<PauseHandle>,
+    task: Rc<RefCell<Option<Task>>>,
+}
+
+impl ShutdownHandle {
+    /// stops fetching new logs. the `Bridge` ends once the relays
+    /// that are in flight have completed and their checkpoints were yielded
+    pub fn shutdown(&self) {
+        for pause in &self.pauses {
+            pause.pause(PauseReason::Shutdown);
+        }
+        if let Some(task) = self.task.borrow_mut().take() {
+            task.notify();
+        }
+    }
+}
+
+/// replaces the settings of a running `Bridge`. `clone`d versions share the same state
+#[derive(Clone)]
+pub struct ReloadHandle<T> {
+    reload: Rc<RefCell<Option<(MainContract<T>, SideContract<T>)>>>,
+    task: Rc<RefCell<Option<Task>>>,
+}
+
+impl<T> ReloadHandle<T> {
+    /// relays of logs fetched after the next poll use `main_contract` and `side_contract`.
+    /// the log streams adopt their request timeouts, poll intervals and confirmations.
+    /// relays in flight keep their settings
+    pub fn reload(&self, main_contract: MainContract<T>, side_contract: SideContract<T>) {
+        *self.reload.borrow_mut() = Some((main_contract, side_contract));
+        if let Some(task) = self.task.borrow_mut().take() {
+            task.notify();
+        }
+    }
+}
+
+type MainToSideStream<T> = RelayStream<LogStream<T>, accept_message_from_main::LogToAcceptMessageFromMain<T>>;
+type SideToMainSignStream<T> = RelayStream<LogStream<T>, side_to_main_sign::LogToSideToMainSign<T>>;
+type SideToMainSignaturesStream<T> =
+    RelayStream<LogStream<T>, side_to_main_signatures::LogToSideToMainSignatures<T>>;
+
 impl<T: Transport> Bridge<T> {
     pub fn new(
         initial_state: State,
         main_contract: MainContract<T>,
         side_contract: SideContract<T>,
     ) -> Self {
-        let accept_message_from_main = RelayStream::new(
-            main_contract.main_to_side_log_stream(initial_state.last_main_to_side_sign_at_block),
-            accept_message_from_main::LogToAcceptMessageFromMain {
-                main: main_contract.clone(),
-                side: side_contract.clone(),
-            },
-        );
+        let main_to_side_pause = PauseHandle::new();
+        let side_to_main_sign_pause = PauseHandle::new();
+        let side_to_main_signatures_pause = PauseHandle::new();
 
-        let side_to_main_sign = RelayStream::new(
-            side_contract
-                .side_to_main_sign_log_stream(initial_state.last_side_to_main_sign_at_block),
-            side_to_main_sign::LogToSideToMainSign {
-                side: side_contract.clone(),
-            },
-        );
-
-        let side_to_main_signatures = RelayStream::new(
-            side_contract.side_to_main_signatures_log_stream(
-                initial_state.last_side_to_main_signatures_at_block,
-                main_contract.authority_address,
-            ),
-            side_to_main_signatures::LogToSideToMainSignatures {
-                main: main_contract.clone(),
-                side: side_contract.clone(),
-            },
+        let (accept_message_from_main, side_to_main_sign, side_to_main_signatures) = relay_streams(
+            &initial_state,
+            &main_contract,
+            &side_contract,
+            [&main_to_side_pause, &side_to_main_sign_pause, &side_to_main_signatures_pause],
         );
 
         Self {
@@ -80,15 +123,157 @@ impl<T: Transport> Bridge<T> {
             side_to_main_sign,
             side_to_main_signatures,
             state: initial_state,
+            main_contract,
+            side_contract,
+            main_to_side_pause,
+            side_to_main_sign_pause,
+            side_to_main_signatures_pause,
+            reload: Rc::new(RefCell::new(None)),
+            task: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    /// drops all relays in flight and starts over from the last yielded state.
+    /// pauses and handles stay in effect
+    pub fn restart(&mut self) {
+        let (accept_message_from_main, side_to_main_sign, side_to_main_signatures) = relay_streams(
+            &self.state,
+            &self.main_contract,
+            &self.side_contract,
+            [
+                &self.main_to_side_pause,
+                &self.side_to_main_sign_pause,
+                &self.side_to_main_signatures_pause,
+            ],
+        );
+        self.accept_message_from_main = accept_message_from_main;
+        self.side_to_main_sign = side_to_main_sign;
+        self.side_to_main_signatures = side_to_main_signatures;
+    }
+
+    pub fn reload_handle(&self) -> ReloadHandle<T> {
+        ReloadHandle {
+            reload: self.reload.clone(),
+            task: self.task.clone(),
+        }
+    }
+
+    fn apply_reload(&mut self, main_contract: MainContract<T>, side_contract: SideContract<T>) {
+        self.accept_message_from_main.stream_of_logs_mut().set_options(
+            main_contract.request_timeout,
+            main_contract.logs_poll_interval,
+            main_contract.required_log_confirmations,
+        );
+        self.side_to_main_sign.stream_of_logs_mut().set_options(
+            side_contract.request_timeout,
+            side_contract.logs_poll_interval,
+            side_contract.required_log_confirmations,
+        );
+        self.side_to_main_signatures.stream_of_logs_mut().set_options(
+            side_contract.request_timeout,
+            side_contract.logs_poll_interval,
+            side_contract.required_log_confirmations,
+        );
+
+        {
+            let log_to_future = self.accept_message_from_main.log_to_future_mut();
+            log_to_future.main = main_contract.clone();
+            log_to_future.side = side_contract.clone();
+        }
+        self.side_to_main_sign.log_to_future_mut().side = side_contract.clone();
+        {
+            let log_to_future = self.side_to_main_signatures.log_to_future_mut();
+            log_to_future.main = main_contract.clone();
+            log_to_future.side = side_contract.clone();
+        }
+        self.main_contract = main_contract;
+        self.side_contract = side_contract;
+        info!("applied reloaded settings");
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            pauses: vec![
+                self.main_to_side_pause.clone(),
+                self.side_to_main_sign_pause.clone(),
+                self.side_to_main_signatures_pause.clone(),
+            ],
+            task: self.task.clone(),
+        }
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.main_to_side_pause.reasons().contains(&PauseReason::Shutdown)
+    }
+
+    /// returns `true` if no relay futures are in flight
+    fn is_idle(&self) -> bool {
+        self.accept_message_from_main.is_idle() && self.side_to_main_sign.is_idle()
+            && self.side_to_main_signatures.is_idle()
+    }
+
+    /// handle to pause and resume the relay stream for `direction`
+    pub fn pause_handle(&self, direction: Direction) -> PauseHandle {
+        match direction {
+            Direction::MainToSide => self.main_to_side_pause.clone(),
+            Direction::SideToMainSign => self.side_to_main_sign_pause.clone(),
+            Direction::SideToMainSignatures => self.side_to_main_signatures_pause.clone(),
         }
     }
 }
 
+/// creates the relay streams of the 3 relays starting after the checkpoints in `state`
+fn relay_streams<T: Transport>(
+    state: &State,
+    main_contract: &MainContract<T>,
+    side_contract: &SideContract<T>,
+    pauses: [&PauseHandle; 3],
+) -> (MainToSideStream<T>, SideToMainSignStream<T>, SideToMainSignaturesStream<T>) {
+    let accept_message_from_main = RelayStream::new(
+        main_contract.main_to_side_log_stream(state.last_main_to_side_sign_at_block),
+        accept_message_from_main::LogToAcceptMessageFromMain {
+            main: main_contract.clone(),
+            side: side_contract.clone(),
+        },
+        pauses[0].clone(),
+        state.relay_checkpoint(Direction::MainToSide),
+    );
+
+    let side_to_main_sign = RelayStream::new(
+        side_contract.side_to_main_sign_log_stream(state.last_side_to_main_sign_at_block),
+        side_to_main_sign::LogToSideToMainSign {
+            side: side_contract.clone(),
+        },
+        pauses[1].clone(),
+        state.relay_checkpoint(Direction::SideToMainSign),
+    );
+
+    let side_to_main_signatures = RelayStream::new(
+        side_contract.side_to_main_signatures_log_stream(
+            state.last_side_to_main_signatures_at_block,
+            main_contract.authority_address,
+        ),
+        side_to_main_signatures::LogToSideToMainSignatures {
+            main: main_contract.clone(),
+            side: side_contract.clone(),
+        },
+        pauses[2].clone(),
+        state.relay_checkpoint(Direction::SideToMainSignatures),
+    );
+
+    (accept_message_from_main, side_to_main_sign, side_to_main_signatures)
+}
+
 impl<T: Transport> Stream for Bridge<T> {
     type Item = State;
     type Error = error::Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        *self.task.borrow_mut() = Some(task::current());
+        let reload = self.reload.borrow_mut().take();
+        if let Some((main_contract, side_contract)) = reload {
+            self.apply_reload(main_contract, side_contract);
+        }
         loop {
             let maybe_main_to_side_sign = try_maybe_stream!(
                 self.accept_message_from_main
@@ -113,7 +298,7 @@ impl<T: Transport> Stream for Bridge<T> {
                     "last block checked for main to side sign is now {}",
                     main_to_side_sign
                 );
-                self.state.last_main_to_side_sign_at_block = main_to_side_sign;
+                self.state.set_relay_checkpoint(Direction::MainToSide, main_to_side_sign);
                 has_state_changed = true;
             }
             if let Some(side_to_main_sign) = maybe_side_to_main_sign {
@@ -121,7 +306,7 @@ impl<T: Transport> Stream for Bridge<T> {
                     "last block checked for side to main sign is now {}",
                     side_to_main_sign
                 );
-                self.state.last_side_to_main_sign_at_block = side_to_main_sign;
+                self.state.set_relay_checkpoint(Direction::SideToMainSign, side_to_main_sign);
                 has_state_changed = true;
             }
             if let Some(side_to_main_signatures) = maybe_side_to_main_signatures {
@@ -129,12 +314,15 @@ impl<T: Transport> Stream for Bridge<T> {
                     "last block checked for side to main signatures is now {}",
                     side_to_main_signatures
                 );
-                self.state.last_side_to_main_signatures_at_block = side_to_main_signatures;
+                self.state.set_relay_checkpoint(Direction::SideToMainSignatures, side_to_main_signatures);
                 has_state_changed = true;
             }
 
             if has_state_changed {
                 return Ok(Async::Ready(Some(self.state.clone())));
+            } else if self.is_shutting_down() && self.is_idle() {
+                info!("all relays in flight have completed. stopping");
+                return Ok(Async::Ready(None));
             } else {
                 return Ok(Async::NotReady);
             }
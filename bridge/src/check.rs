@@ -0,0 +1,412 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! preflight checks run by `susy-bridge check`.
+//!
+//! every check is run, even if others fail, so that all problems
+//! with a configuration are reported at once.
+
+use config::{Config, NodeConfig};
+use database::State;
+use error;
+use futures::future::{self, join_all};
+use futures::Future;
+use helpers::with_timeout;
+use main_contract::MainContract;
+use relay_log::Chain;
+use side_contract::SideContract;
+use std::cmp;
+use std::collections::HashSet;
+use std::fmt;
+use susyweb;
+use susyweb::api::Namespace;
+use susyweb::types::{Address, Bytes, U256};
+use susyweb::Transport;
+
+/// message signed to find out whether the authority account is usable
+const SIGN_CHECK_MESSAGE: &[u8] = b"susy-bridge check";
+
+/// outcome of a single check
+#[derive(Debug, PartialEq)]
+pub struct Check {
+    pub name: String,
+    /// `Ok` with details if the check passed. `Err` with the reason otherwise
+    pub result: Result<String, String>,
+}
+
+impl Check {
+    pub fn new<N: Into<String>>(name: N, result: Result<String, String>) -> Self {
+        Check {
+            name: name.into(),
+            result,
+        }
+    }
+}
+
+/// outcome of all checks
+#[derive(Debug, PartialEq)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.result.is_ok())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for check in &self.checks {
+            match check.result {
+                Ok(ref details) => writeln!(f, "ok    {}: {}", check.name, details)?,
+                Err(ref reason) => writeln!(f, "FAIL  {}: {}", check.name, reason)?,
+            }
+        }
+        let failed = self.checks.iter().filter(|check| check.result.is_err()).count();
+        if failed == 0 {
+            write!(f, "all {} checks passed", self.checks.len())
+        } else {
+            write!(f, "{} of {} checks failed", failed, self.checks.len())
+        }
+    }
+}
+
+/// checks of `config` that don't need the chains
+pub fn check_config(config: &Config) -> Vec<Check> {
+    let accounts = &config.authorities.accounts;
+    let required_signatures = config.authorities.required_signatures;
+
+    let signatures = if required_signatures == 0 {
+        Err("`authorities.required_signatures` must be at least 1".to_string())
+    } else if required_signatures as usize > accounts.len() {
+        Err(format!(
+            "`authorities.required_signatures` is {} but there are only {} `authorities.accounts`",
+            required_signatures,
+            accounts.len()
+        ))
+    } else {
+        Ok(format!("{} of {} authorities", required_signatures, accounts.len()))
+    };
+
+    let mut seen = HashSet::new();
+    let duplicates = accounts
+        .iter()
+        .filter(|account| !seen.insert(*account))
+        .map(|account| format!("{:?}", account))
+        .collect::<Vec<_>>();
+    let unique = if duplicates.is_empty() {
+        Ok("no duplicate accounts".to_string())
+    } else {
+        Err(format!("duplicate `authorities.accounts`: {}", duplicates.join(", ")))
+    };
+
+    let member = if accounts.contains(&config.address) {
+        Ok(format!("{:?} is an authority", config.address))
+    } else {
+        Err(format!("`address` {:?} is not in `authorities.accounts`", config.address))
+    };
+
+    let mut zero_gas = Vec::new();
+    if config.txs.deposit_relay.gas.is_zero() {
+        zero_gas.push("`transactions.deposit_relay.gas`");
+    }
+    if config.txs.withdraw_confirm.gas.is_zero() {
+        zero_gas.push("`transactions.withdraw_confirm.gas`");
+    }
+    if config.estimated_gas_cost_of_withdraw.is_zero() {
+        zero_gas.push("`estimated_gas_cost_of_withdraw`");
+    }
+    let gas = if zero_gas.is_empty() {
+        Ok("gas limits of all relay transactions are set".to_string())
+    } else {
+        Err(format!("{} must not be 0", zero_gas.join(", ")))
+    };
+
+    vec![
+        Check::new("required signatures", signatures),
+        Check::new("authority accounts", unique),
+        Check::new("authority address", member),
+        Check::new("gas", gas),
+    ]
+}
+
+/// `Ok` if the node reports the configured network id
+fn check_network_id(node: &NodeConfig, network_id: &str) -> Result<String, String> {
+    match node.chain_id {
+        Some(expected) if network_id != expected.to_string() => Err(format!(
+            "node reports network id {} but `chain_id` is {}",
+            network_id, expected
+        )),
+        Some(_) => Ok(format!("network id {}", network_id)),
+        None => Ok(format!("network id {}. set `chain_id` to verify it", network_id)),
+    }
+}
+
+/// gas prices of the relay transactions `config` sends on `chain`
+fn configured_gas_prices(config: &Config, chain: Chain) -> Vec<(&'static str, U256)> {
+    match chain {
+        Chain::Main => Vec::new(),
+        Chain::Side => vec![
+            ("transactions.deposit_relay.gas_price", config.txs.deposit_relay.gas_price),
+            ("transactions.withdraw_confirm.gas_price", config.txs.withdraw_confirm.gas_price),
+        ],
+    }
+}
+
+/// `Ok` if no relay transaction on a chain with gas price `node_gas_price` is sent for free
+fn check_gas_prices(config: &Config, chain: Chain, node_gas_price: U256) -> Result<String, String> {
+    if node_gas_price.is_zero() {
+        return Ok("gas is free".to_string());
+    }
+    let free = configured_gas_prices(config, chain)
+        .into_iter()
+        .filter(|&(_, gas_price)| gas_price.is_zero())
+        .map(|(name, _)| format!("`{}`", name))
+        .collect::<Vec<_>>();
+    if free.is_empty() {
+        Ok(format!("node gas price {}", node_gas_price))
+    } else {
+        Err(format!(
+            "{} must not be 0. the node gas price is {}",
+            free.join(", "),
+            node_gas_price
+        ))
+    }
+}
+
+/// balance the authority needs on `chain`: the critical threshold of `[balance]`,
+/// `health.min_authority_balance` on `main` and at least the cost of a single relay transaction
+fn required_balance(config: &Config, chain: Chain, node_gas_price: U256) -> U256 {
+    let single_relay = match chain {
+        Chain::Main => config.estimated_gas_cost_of_withdraw * node_gas_price,
+        Chain::Side => cmp::max(
+            config.txs.deposit_relay.gas * config.txs.deposit_relay.gas_price,
+            config.txs.withdraw_confirm.gas * config.txs.withdraw_confirm.gas_price,
+        ),
+    };
+    let critical = config.balance.as_ref().map(|balance| match chain {
+        Chain::Main => balance.main.critical,
+        Chain::Side => balance.side.critical,
+    });
+    let health = match chain {
+        Chain::Main => config.health.as_ref().map(|health| health.min_authority_balance),
+        Chain::Side => None,
+    };
+    cmp::max(single_relay, cmp::max(critical.unwrap_or_default(), health.unwrap_or_default()))
+}
+
+fn check_balance(required: U256, balance: U256) -> Result<String, String> {
+    if balance < required {
+        Err(format!("balance {} is below the required {}", balance, required))
+    } else {
+        Ok(format!("balance {}", balance))
+    }
+}
+
+/// the error chain of `result` in a single line
+pub fn describe<T>(result: Result<T, error::Error>) -> Result<T, String> {
+    result.map_err(|err| {
+        err.iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join(": ")
+    })
+}
+
+type CheckFuture = Box<Future<Item = Vec<Check>, Error = ()>>;
+
+/// runs all checks against both chains
+pub struct Preflight<T> {
+    pub config: Config,
+    /// `Err` if the database couldn't be loaded
+    pub state: Result<State, String>,
+    pub main_transport: T,
+    pub side_transport: T,
+}
+
+impl<T: Transport + 'static> Preflight<T>
+where
+    T::Out: 'static,
+{
+    /// `Future` resolving to the report of all checks. never fails
+    pub fn run(&self) -> Box<Future<Item = Report, Error = ()>> {
+        let mut futures: Vec<CheckFuture> = vec![Box::new(future::ok(check_config(&self.config)))];
+        futures.push(self.check_node(Chain::Main, self.main_transport.clone(), &self.config.main));
+        futures.push(self.check_node(Chain::Side, self.side_transport.clone(), &self.config.side));
+        futures.push(self.check_contracts());
+        Box::new(join_all(futures).map(|checks| Report {
+            checks: checks.into_iter().flat_map(|checks| checks).collect(),
+        }))
+    }
+
+    /// network id, account, gas price and balance on `chain`
+    fn check_node(&self, chain: Chain, transport: T, node: &NodeConfig) -> CheckFuture {
+        let config = self.config.clone();
+        let node = node.clone();
+        let address = config.address;
+        let timeout = node.request_timeout;
+
+        let network_id = with_timeout(susyweb::api::Net::new(&transport).version(), timeout)
+            .then(move |result| Ok::<_, ()>(describe(result).and_then(|network_id| check_network_id(&node, &network_id))));
+        let account = with_timeout(
+            susyweb::api::Sof::new(&transport).sign(address, Bytes(SIGN_CHECK_MESSAGE.to_vec())),
+            timeout,
+        ).then(move |result| {
+            Ok::<_, ()>(describe(result)
+                .map(|_| format!("{:?} can sign", address))
+                .map_err(|err| format!("cannot sign with {:?}. unlock it in the node: {}", address, err)))
+        });
+        let gas_price = with_timeout(susyweb::api::Sof::new(&transport).gas_price(), timeout).then(Ok::<_, ()>);
+        let balance = with_timeout(susyweb::api::Sof::new(&transport).balance(address, None), timeout).then(Ok::<_, ()>);
+
+        Box::new(network_id.join4(account, gas_price, balance).map(move |(network_id, account, gas_price, balance)| {
+            let gas_price = describe(gas_price);
+            let (gas_prices, balance) = match gas_price {
+                Ok(gas_price) => (
+                    check_gas_prices(&config, chain, gas_price),
+                    describe(balance).and_then(|balance| check_balance(required_balance(&config, chain, gas_price), balance)),
+                ),
+                Err(err) => (Err(err.clone()), Err(err)),
+            };
+            vec![
+                Check::new(format!("{} rpc", chain), network_id),
+                Check::new(format!("{} account", chain), account),
+                Check::new(format!("{} gas price", chain), gas_prices),
+                Check::new(format!("{} balance", chain), balance),
+            ]
+        }))
+    }
+
+    /// whether the contract addresses in the database point to bridge contracts
+    fn check_contracts(&self) -> CheckFuture {
+        let state = match self.state {
+            Ok(ref state) => state,
+            Err(ref err) => {
+                return Box::new(future::ok(vec![Check::new("database", Err(err.clone()))]));
+            }
+        };
+        let main = MainContract::new(self.main_transport.clone(), &self.config, state);
+        let side = SideContract::new(self.side_transport.clone(), &self.config, state);
+        let (main_address, side_address) = (main.contract_address, side.contract_address);
+        Box::new(
+            main.is_main_contract()
+                .then(Ok::<_, ()>)
+                .join(side.is_side_contract().then(Ok::<_, ()>))
+                .map(move |(main, side)| {
+                    vec![
+                        Check::new("main contract", is_bridge_contract(main_address, describe(main))),
+                        Check::new("side contract", is_bridge_contract(side_address, describe(side))),
+                    ]
+                }),
+        )
+    }
+}
+
+pub fn is_bridge_contract(address: Address, result: Result<bool, String>) -> Result<String, String> {
+    match result {
+        Ok(true) => Ok(format!("{:?} is a bridge contract", address)),
+        Ok(false) => Err(format!("{:?} is not a bridge contract", address)),
+        Err(err) => Err(format!("{:?} is not a bridge contract: {}", address, err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{Authorities, Transactions, TransactionConfig};
+    use std::time::Duration;
+
+    fn node() -> NodeConfig {
+        NodeConfig {
+            contract: None,
+            http: String::new(),
+            request_timeout: Duration::from_secs(5),
+            poll_interval: Duration::from_secs(1),
+            required_confirmations: 12,
+            chain_id: Some(42),
+        }
+    }
+
+    fn config() -> Config {
+        let relay = TransactionConfig {
+            gas: 100.into(),
+            gas_price: 2.into(),
+        };
+        Config {
+            address: "0000000000000000000000000000000000000001".into(),
+            main: node(),
+            side: node(),
+            authorities: Authorities {
+                accounts: vec![
+                    "0000000000000000000000000000000000000001".into(),
+                    "0000000000000000000000000000000000000002".into(),
+                ],
+                required_signatures: 2,
+            },
+            txs: Transactions {
+                deposit_relay: relay.clone(),
+                withdraw_confirm: relay,
+                ..Transactions::default()
+            },
+            estimated_gas_cost_of_withdraw: 100.into(),
+            max_total_main_contract_balance: 0.into(),
+            max_single_deposit_value: 0.into(),
+            health: None,
+            balance: None,
+            tracker: None,
+            participation: None,
+            admin: None,
+        }
+    }
+
+    #[test]
+    fn test_check_config() {
+        assert!(check_config(&config()).iter().all(|check| check.result.is_ok()));
+
+        let mut config = config();
+        config.address = "0000000000000000000000000000000000000003".into();
+        config.authorities.accounts = vec![
+            "0000000000000000000000000000000000000002".into(),
+            "0000000000000000000000000000000000000002".into(),
+        ];
+        config.authorities.required_signatures = 3;
+        config.txs.withdraw_confirm.gas = 0.into();
+        let failed = check_config(&config)
+            .into_iter()
+            .filter(|check| check.result.is_err())
+            .map(|check| check.name)
+            .collect::<Vec<_>>();
+        assert_eq!(failed, vec!["required signatures", "authority accounts", "authority address", "gas"]);
+    }
+
+    #[test]
+    fn test_check_node_values() {
+        let config = config();
+        assert!(check_network_id(&config.main, "42").is_ok());
+        assert!(check_network_id(&config.main, "1").is_err());
+
+        assert!(check_gas_prices(&config, Chain::Side, 0.into()).is_ok());
+        let mut free = config.clone();
+        free.txs.deposit_relay.gas_price = 0.into();
+        assert!(check_gas_prices(&free, Chain::Side, 1.into()).is_err());
+
+        assert_eq!(required_balance(&config, Chain::Side, 1.into()), 200.into());
+        assert_eq!(required_balance(&config, Chain::Main, 3.into()), 300.into());
+        assert!(check_balance(200.into(), 199.into()).is_err());
+        assert!(check_balance(200.into(), 200.into()).is_ok());
+    }
+}
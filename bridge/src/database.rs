@@ -17,6 +17,7 @@
 //! concerning reading/writing `State` from/to toml file
 
 use error::{Error, ErrorKind, ResultExt};
+use relay_log::Direction;
 use std::io::{Read, Write};
 /// the state of a bridge node process and ways to persist it
 use std::path::{Path, PathBuf};
@@ -41,6 +42,37 @@ pub struct State {
     pub last_side_to_main_signatures_at_block: u64,
     /// Number of last block which has been checked for withdraw confirms.
     pub last_side_to_main_sign_at_block: u64,
+    /// Index of last log in block `last_main_to_side_sign_at_block + 1` which has been relayed.
+    pub last_main_to_side_sign_at_log_index: Option<u64>,
+    /// Index of last log in block `last_side_to_main_signatures_at_block + 1` which has been relayed.
+    pub last_side_to_main_signatures_at_log_index: Option<u64>,
+    /// Index of last log in block `last_side_to_main_sign_at_block + 1` which has been relayed.
+    pub last_side_to_main_sign_at_log_index: Option<u64>,
+}
+
+/// how far the relays of a direction have progressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Checkpoint {
+    /// all logs up to and including this block have been relayed
+    pub block: u64,
+    /// the logs in block `block + 1` up to and including this index have been relayed
+    pub log_index: Option<u64>,
+}
+
+impl Checkpoint {
+    /// returns `true` if the log at `log_index` in `block` has been relayed
+    pub fn covers(&self, block: u64, log_index: u64) -> bool {
+        block <= self.block || (block == self.block + 1 && self.log_index.map_or(false, |last| log_index <= last))
+    }
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.log_index {
+            Some(log_index) => write!(f, "{} (and block {} up to log {})", self.block, self.block + 1, log_index),
+            None => write!(f, "{}", self.block),
+        }
+    }
 }
 
 impl State {
@@ -70,11 +102,66 @@ impl State {
             last_main_to_side_sign_at_block: main_block_number,
             last_side_to_main_sign_at_block: side_block_number,
             last_side_to_main_signatures_at_block: side_block_number,
+            last_main_to_side_sign_at_log_index: None,
+            last_side_to_main_sign_at_log_index: None,
+            last_side_to_main_signatures_at_log_index: None,
         }
     }
 }
 
 impl State {
+    /// number of the last block checked for logs of `direction`
+    pub fn checkpoint(&self, direction: Direction) -> u64 {
+        match direction {
+            Direction::MainToSide => self.last_main_to_side_sign_at_block,
+            Direction::SideToMainSign => self.last_side_to_main_sign_at_block,
+            Direction::SideToMainSignatures => self.last_side_to_main_signatures_at_block,
+        }
+    }
+
+    /// also forgets which logs in block `block + 1` have been relayed
+    pub fn set_checkpoint(&mut self, direction: Direction, block: u64) {
+        self.set_relay_checkpoint(direction, Checkpoint { block, log_index: None });
+    }
+
+    /// the last block and log checked for logs of `direction`
+    pub fn relay_checkpoint(&self, direction: Direction) -> Checkpoint {
+        let log_index = match direction {
+            Direction::MainToSide => self.last_main_to_side_sign_at_log_index,
+            Direction::SideToMainSign => self.last_side_to_main_sign_at_log_index,
+            Direction::SideToMainSignatures => self.last_side_to_main_signatures_at_log_index,
+        };
+        Checkpoint {
+            block: self.checkpoint(direction),
+            log_index,
+        }
+    }
+
+    pub fn set_relay_checkpoint(&mut self, direction: Direction, checkpoint: Checkpoint) {
+        match direction {
+            Direction::MainToSide => {
+                self.last_main_to_side_sign_at_block = checkpoint.block;
+                self.last_main_to_side_sign_at_log_index = checkpoint.log_index;
+            }
+            Direction::SideToMainSign => {
+                self.last_side_to_main_sign_at_block = checkpoint.block;
+                self.last_side_to_main_sign_at_log_index = checkpoint.log_index;
+            }
+            Direction::SideToMainSignatures => {
+                self.last_side_to_main_signatures_at_block = checkpoint.block;
+                self.last_side_to_main_signatures_at_log_index = checkpoint.log_index;
+            }
+        }
+    }
+
+    /// number of the block at which the contract emitting the logs of `direction` has been deployed
+    pub fn deployed_at_block(&self, direction: Direction) -> u64 {
+        match direction {
+            Direction::MainToSide => self.main_deployed_at_block,
+            Direction::SideToMainSign | Direction::SideToMainSignatures => self.side_deployed_at_block,
+        }
+    }
+
     /// write state to a `std::io::write`
     pub fn write<W: Write>(&self, mut write: W) -> Result<(), Error> {
         let serialized = toml::to_string(self).expect("serialization can't fail. q.e.d.");
@@ -122,6 +209,16 @@ impl TomlFileDatabase {
             state,
         })
     }
+
+    /// copies the database file to `<filepath>.bak`, replacing any previous backup.
+    /// returns the path of the backup
+    pub fn backup(&self) -> Result<PathBuf, Error> {
+        let mut backup = self.filepath.clone().into_os_string();
+        backup.push(".bak");
+        let backup = PathBuf::from(backup);
+        fs::copy(&self.filepath, &backup).chain_err(|| format!("Cannot back up database to {:?}", backup))?;
+        Ok(backup)
+    }
 }
 
 impl Database for TomlFileDatabase {
@@ -136,6 +233,7 @@ impl Database for TomlFileDatabase {
             let file = fs::OpenOptions::new()
                 .write(true)
                 .create(true)
+                .truncate(true)
                 .open(&self.filepath)?;
 
             self.state.write(file)?;
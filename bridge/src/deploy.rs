@@ -16,112 +16,482 @@
 
 //! concerning deployment of the bridge contracts
 
-use config::Config;
+use check::Check;
+use config::{Config, DeployConfig, NodeConfig, TransactionConfig};
 use contracts;
 use error::{self, ResultExt};
-use futures::{Future, Poll};
+use futures::future::{self, FromErr};
+use futures::{Async, Future, Poll};
+use helpers::with_timeout;
+use init::deployed_at_block;
+use relay_log::Chain;
 use rustc_hex::ToHex;
+use serde_json;
 use std::fs;
 use std::fs::File;
-use std::io::Write;
+use std::io::{self, Write};
 use std::path::Path;
-use susyweb::types::{TransactionReceipt, TransactionRequest};
+use std::str::FromStr;
+use std::time::Duration;
+use susyweb;
+use susyweb::api::Namespace;
+use susyweb::helpers::CallFuture;
+use susyweb::types::{Address, Bytes, CallRequest, TransactionReceipt, TransactionRequest, H256, U256};
 use susyweb::Transport;
-use send_tx_with_receipt::{SendTransactionWithReceipt, SendTransactionWithReceiptOptions};
+use send_tx_with_receipt::{SendTransactionWithReceipt, SentTransactionOptions};
+use tokio_timer::Timeout;
 
-pub enum DeployState<T: Transport + Clone> {
+/// deployment progress of a single contract
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ContractProgress {
     NotDeployed,
-    Deploying {
-        data: Vec<u8>,
-        future: SendTransactionWithReceipt<T>,
+    /// the deployment transaction was sent but hasn't enough confirmations yet
+    Pending { transaction_hash: H256 },
+    Deployed {
+        address: Address,
+        /// `None` if the contract was attached by its address
+        transaction_hash: Option<H256>,
+        block: u64,
     },
+}
+
+impl Default for ContractProgress {
+    fn default() -> Self {
+        ContractProgress::NotDeployed
+    }
+}
+
+impl ContractProgress {
+    fn deployed(contract: &DeployedContract) -> Self {
+        ContractProgress::Deployed {
+            address: contract
+                .receipt
+                .contract_address
+                .expect("contract creation receipt must have an address; qed"),
+            transaction_hash: Some(contract.receipt.transaction_hash),
+            block: contract
+                .receipt
+                .block_number
+                .expect("receipt with enough confirmations has a block number; qed")
+                .as_u64(),
+        }
+    }
+
+    /// hash of the deployment transaction to wait for
+    pub fn pending(&self) -> Option<H256> {
+        match *self {
+            ContractProgress::Pending { transaction_hash } => Some(transaction_hash),
+            _ => None,
+        }
+    }
+
+    /// address of a deployed contract
+    pub fn address(&self) -> Option<Address> {
+        match *self {
+            ContractProgress::Deployed { address, .. } => Some(address),
+            _ => None,
+        }
+    }
+
+    /// hash of the deployment transaction of a deployed contract
+    pub fn transaction_hash(&self) -> Option<H256> {
+        match *self {
+            ContractProgress::Deployed { transaction_hash, .. } => transaction_hash,
+            _ => None,
+        }
+    }
+}
+
+/// an existing contract `susy-bridge-deploy` uses instead of deploying one
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Attach {
+    Contract(Address),
+    /// the contract created by this transaction
+    CreationTransaction(H256),
+}
+
+impl FromStr for Attach {
+    type Err = error::Error;
+
+    /// a contract address or the hash of a contract creation transaction
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim_left_matches("0x");
+        match hex.len() {
+            40 => hex.parse().map(Attach::Contract).map_err(|_| format!("invalid address {:?}", s).into()),
+            64 => hex
+                .parse()
+                .map(Attach::CreationTransaction)
+                .map_err(|_| format!("invalid transaction hash {:?}", s).into()),
+            _ => Err(format!("{:?} is neither an address nor a transaction hash", s).into()),
+        }
+    }
+}
+
+/// `Future` resolving to the `ContractProgress::Deployed` of an existing contract.
+/// the deployment block is taken from the receipt of the creation transaction if known.
+/// otherwise it's found by binary search. see `init::deployed_at_block`
+pub fn attach<T: Transport + 'static>(
+    transport: T,
+    request_timeout: Duration,
+    attach: Attach,
+) -> Box<Future<Item = ContractProgress, Error = error::Error>>
+where
+    T::Out: 'static,
+{
+    match attach {
+        Attach::Contract(address) => Box::new(deployed_at_block(transport, address, request_timeout).map(
+            move |block| ContractProgress::Deployed {
+                address,
+                transaction_hash: None,
+                block,
+            },
+        )),
+        Attach::CreationTransaction(transaction_hash) => Box::new(
+            with_timeout(
+                susyweb::api::Sof::new(&transport).transaction_receipt(transaction_hash),
+                request_timeout,
+            ).and_then(move |receipt| -> Result<_, error::Error> {
+                let receipt = receipt.ok_or_else(|| format!("transaction {:?} is unknown or not mined yet", transaction_hash))?;
+                let address = receipt
+                    .contract_address
+                    .ok_or_else(|| format!("transaction {:?} didn't create a contract", transaction_hash))?;
+                let block = receipt
+                    .block_number
+                    .ok_or_else(|| format!("transaction {:?} is not mined yet", transaction_hash))?;
+                Ok(ContractProgress::Deployed {
+                    address,
+                    transaction_hash: Some(transaction_hash),
+                    block: block.as_u64(),
+                })
+            }),
+        ),
+    }
+}
+
+/// deployment progress of both contracts.
+/// `susy-bridge-deploy` saves it after every step so that a rerun resumes
+/// instead of deploying the contracts again
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeployProgress {
+    #[serde(default)]
+    pub main: ContractProgress,
+    #[serde(default)]
+    pub side: ContractProgress,
+}
+
+impl DeployProgress {
+    /// reads the progress saved at `path`. no progress if there's no file at `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, error::Error> {
+        let path = path.as_ref();
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err).chain_err(|| format!("Cannot open deployment progress {:?}", path)),
+        };
+        serde_json::from_reader(file).chain_err(|| format!("Cannot parse deployment progress {:?}", path))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), error::Error> {
+        let path = path.as_ref();
+        let file = File::create(path).chain_err(|| format!("Cannot write deployment progress {:?}", path))?;
+        serde_json::to_writer_pretty(file, self).chain_err(|| format!("Cannot write deployment progress {:?}", path))
+    }
+
+    /// records a completed deployment
+    pub fn set_deployed(&mut self, contract: &DeployedContract) {
+        *self.contract_mut(&contract.contract_name) = ContractProgress::deployed(contract);
+    }
+
+    fn contract_mut(&mut self, name: &str) -> &mut ContractProgress {
+        if name == MAIN_CONTRACT_NAME {
+            &mut self.main
+        } else {
+            &mut self.side
+        }
+    }
+}
+
+const MAIN_CONTRACT_NAME: &str = "Main";
+const SIDE_CONTRACT_NAME: &str = "SideBridge";
+
+/// the compiled contract to deploy on `chain`
+fn compiled_contract(chain: Chain, node: &NodeConfig) -> Result<Vec<u8>, error::Error> {
+    match node.contract {
+        Some(ref contract) => Ok(contract.bin.0.clone()),
+        None => Err(format!("`{}.contract.bin` is required to deploy the {} contract", chain, chain).into()),
+    }
+}
+
+/// input of the main contract creation transaction
+fn main_constructor_data(config: &Config) -> Result<Vec<u8>, error::Error> {
+    Ok(contracts::main::constructor(
+        compiled_contract(Chain::Main, &config.main)?,
+        config.authorities.required_signatures,
+        config.authorities.accounts.clone(),
+    ))
+}
+
+/// input of the side contract creation transaction
+fn side_constructor_data(config: &Config) -> Result<Vec<u8>, error::Error> {
+    Ok(contracts::side::constructor(
+        compiled_contract(Chain::Side, &config.side)?,
+        config.authorities.required_signatures,
+        config.authorities.accounts.clone(),
+    ))
+}
+
+/// expected cost of deploying a contract. see `estimate_main_deployment`
+#[derive(Debug, PartialEq, Clone)]
+pub struct CostEstimate {
+    pub chain: Chain,
+    /// as estimated by `sof_estimateGas`
+    pub estimated_gas: U256,
+    /// `deploy.*.gas`
+    pub gas: U256,
+    /// `deploy.*.gas_price`
+    pub gas_price: U256,
+    /// as reported by the node
+    pub current_gas_price: U256,
+    /// of the deploying authority
+    pub balance: U256,
+}
+
+impl CostEstimate {
+    pub fn checks(&self) -> Vec<Check> {
+        let gas = if self.estimated_gas > self.gas {
+            Err(format!(
+                "estimated {} exceeds the configured {}. increase `deploy.{}.gas`",
+                self.estimated_gas, self.gas, self.chain
+            ))
+        } else {
+            Ok(format!("estimated {} of the configured {}", self.estimated_gas, self.gas))
+        };
+        let cost = Ok(format!(
+            "{} wei at the configured gas price {}. {} wei at the current gas price {}",
+            self.estimated_gas * self.gas_price,
+            self.gas_price,
+            self.estimated_gas * self.current_gas_price,
+            self.current_gas_price
+        ));
+        // the node reserves the full gas limit
+        let required = self.gas * self.gas_price;
+        let balance = if self.balance < required {
+            Err(format!("balance {} is below the {} the deployment may cost", self.balance, required))
+        } else {
+            Ok(format!("balance {} covers the {} the deployment may cost", self.balance, required))
+        };
+        vec![
+            Check::new(format!("{} deploy gas", self.chain), gas),
+            Check::new(format!("{} deploy cost", self.chain), cost),
+            Check::new(format!("{} deployer balance", self.chain), balance),
+        ]
+    }
+}
+
+fn estimate<T: Transport>(
+    chain: Chain,
+    address: Address,
+    node: &NodeConfig,
+    tx: &TransactionConfig,
+    data: Vec<u8>,
+    transport: &T,
+) -> Box<Future<Item = CostEstimate, Error = error::Error>>
+where
+    T::Out: 'static,
+{
+    let request = CallRequest {
+        from: Some(address),
+        to: None,
+        // a gas limit would cap the estimate at `tx.gas`
+        gas: None,
+        gas_price: Some(tx.gas_price),
+        value: None,
+        data: Some(Bytes(data)),
+    };
+    let estimated_gas = with_timeout(susyweb::api::Sof::new(transport).estimate_gas(request, None), node.request_timeout)
+        .then(move |result| result.chain_err(|| format!("estimating the gas of the {} contract deployment failed", chain)));
+    let current_gas_price = with_timeout(susyweb::api::Sof::new(transport).gas_price(), node.request_timeout);
+    let balance = with_timeout(susyweb::api::Sof::new(transport).balance(address, None), node.request_timeout);
+    let (gas, gas_price) = (tx.gas, tx.gas_price);
+    Box::new(estimated_gas.join3(current_gas_price, balance).map(
+        move |(estimated_gas, current_gas_price, balance)| CostEstimate {
+            chain,
+            estimated_gas,
+            gas,
+            gas_price,
+            current_gas_price,
+            balance,
+        },
+    ))
+}
+
+/// `Future` resolving to the expected cost of `DeployMain`. sends no transaction
+pub fn estimate_main_deployment<T: Transport>(
+    config: &Config,
+    deploy: &DeployConfig,
+    main_transport: &T,
+) -> Box<Future<Item = CostEstimate, Error = error::Error>>
+where
+    T::Out: 'static,
+{
+    let data = match main_constructor_data(config) {
+        Ok(data) => data,
+        Err(err) => return Box::new(future::err(err)),
+    };
+    estimate(Chain::Main, config.address, &config.main, &deploy.main, data, main_transport)
+}
+
+/// `Future` resolving to the expected cost of `DeploySide`. sends no transaction
+pub fn estimate_side_deployment<T: Transport>(
+    config: &Config,
+    deploy: &DeployConfig,
+    side_transport: &T,
+) -> Box<Future<Item = CostEstimate, Error = error::Error>>
+where
+    T::Out: 'static,
+{
+    let data = match side_constructor_data(config) {
+        Ok(data) => data,
+        Err(err) => return Box::new(future::err(err)),
+    };
+    estimate(Chain::Side, config.address, &config.side, &deploy.side, data, side_transport)
+}
+
+/// called with the hash of the deployment transaction as soon as it was sent
+pub type OnSent = Box<Fn(H256) -> Result<(), error::Error>>;
+
+pub enum DeployState<T: Transport + Clone> {
+    NotDeployed,
+    Sending(Timeout<FromErr<CallFuture<H256, T::Out>, error::Error>>),
+    Deploying(SendTransactionWithReceipt<T>),
     Deployed {
         contract: DeployedContract,
     },
 }
 
-pub struct DeployMain<T: Transport + Clone> {
-    config: Config,
-    main_transport: T,
+/// sends a contract creation transaction and waits for its receipt
+struct Deploy<T: Transport + Clone> {
+    name: &'static str,
+    abi: &'static str,
+    bin: &'static str,
+    address: Address,
+    node: NodeConfig,
+    tx: TransactionConfig,
+    data: Vec<u8>,
+    transport: T,
+    on_sent: Option<OnSent>,
+    /// waiting for a transaction sent by a previous run
+    resumed: bool,
     state: DeployState<T>,
 }
 
-impl<T: Transport + Clone> DeployMain<T> {
-    pub fn new(config: Config, main_transport: T) -> Self {
-        Self {
-            config,
-            main_transport,
-            state: DeployState::NotDeployed,
+/// waits for the receipt of the deployment transaction `transaction_hash`
+fn wait_for<T: Transport + Clone>(transport: &T, node: &NodeConfig, name: &str, transaction_hash: H256) -> DeployState<T> {
+    info!(
+        "waiting for {} confirmations of {} contract deployment transaction {:?}...",
+        node.required_confirmations, name, transaction_hash
+    );
+    DeployState::Deploying(SendTransactionWithReceipt::sent(SentTransactionOptions {
+        transport: transport.clone(),
+        request_timeout: node.request_timeout,
+        poll_interval: node.poll_interval,
+        confirmations: node.required_confirmations,
+        transaction_hash,
+    }))
+}
+
+impl<T: Transport + Clone> Deploy<T> {
+    /// continue with the deployment transaction `transaction_hash` sent before instead of sending a new one
+    fn resume(&mut self, transaction_hash: Option<H256>) {
+        if let Some(transaction_hash) = transaction_hash {
+            self.state = wait_for(&self.transport, &self.node, self.name, transaction_hash);
+            self.resumed = true;
         }
     }
 }
 
-impl<T: Transport + Clone> Future for DeployMain<T> {
+fn is_unknown_transaction(err: &error::Error) -> bool {
+    match *err.kind() {
+        error::ErrorKind::UnknownTransaction(_) => true,
+        _ => false,
+    }
+}
+
+impl<T: Transport + Clone> Future for Deploy<T> {
     type Item = DeployedContract;
     type Error = error::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let name = self.name;
         loop {
             let next_state = match self.state {
                 DeployState::Deployed { ref contract } => return Ok(contract.clone().into()),
                 DeployState::NotDeployed => {
-                    let data = contracts::main::constructor(
-                        self.config.main.contract.bin.clone().0,
-                        self.config.authorities.required_signatures,
-                        self.config.authorities.accounts.clone(),
-                    );
-
+                    self.resumed = false;
                     let tx_request = TransactionRequest {
-                        from: self.config.address,
+                        from: self.address,
                         to: None,
-                        gas: Some(self.config.txs.main_deploy.gas.into()),
-                        gas_price: Some(self.config.txs.main_deploy.gas_price.into()),
+                        gas: Some(self.tx.gas.into()),
+                        gas_price: Some(self.tx.gas_price.into()),
                         value: None,
-                        data: Some(data.clone().into()),
+                        data: Some(self.data.clone().into()),
                         nonce: None,
                         condition: None,
                     };
 
-                    let future = SendTransactionWithReceipt::new(SendTransactionWithReceiptOptions {
-                        transport: self.main_transport.clone(),
-                        request_timeout: self.config.main.request_timeout,
-                        poll_interval: self.config.main.poll_interval,
-                        confirmations: self.config.main.required_confirmations,
-                        transaction: tx_request,
-                    });
-
-                    info!("sending MainBridge contract deployment transaction and waiting for {} confirmations...", self.config.main.required_confirmations);
-
-                    DeployState::Deploying {
-                        data,
-                        future,
-                    }
+                    info!("sending {} contract deployment transaction", name);
+                    DeployState::Sending(with_timeout(
+                        susyweb::api::Sof::new(&self.transport).send_transaction(tx_request),
+                        self.node.request_timeout,
+                    ))
                 }
-                DeployState::Deploying {
-                    ref mut future,
-                    ref data,
-                } => {
-                    let receipt = try_ready!(
+                DeployState::Sending(ref mut future) => {
+                    let transaction_hash = try_ready!(
                         future
                             .poll()
-                            .chain_err(|| "DeployMain: deployment transaction failed")
+                            .chain_err(|| format!("Deploy{}: sending deployment transaction failed", name))
                     );
-                    let address = receipt
-                        .contract_address
-                        .expect("contract creation receipt must have an address; qed");
-                    info!("MainBridge deployment completed to {:?}", address);
-
-                    DeployState::Deployed {
-                        contract: DeployedContract::new(
-                            "Main".into(),
-                            include_str!("../../arbitrary/contracts/bridge.pol").into(),
-                            include_str!("../../compiled_contracts/Main.abi").into(),
-                            include_str!("../../compiled_contracts/Main.bin").into(),
-                            data.to_hex(),
-                            receipt,
-                        ),
+                    if let Some(ref on_sent) = self.on_sent {
+                        if let Err(err) = on_sent(transaction_hash) {
+                            // the transaction is out. waiting for it beats failing and sending another one on the next run
+                            error!(
+                                "saving {} contract deployment transaction {:?} failed: {}. \
+                                 if this run is interrupted pass the hash to `--attach-main`/`--attach-side` once it's mined",
+                                name,
+                                transaction_hash,
+                                err.iter().map(|err| err.to_string()).collect::<Vec<_>>().join(": ")
+                            );
+                        }
                     }
+                    wait_for(&self.transport, &self.node, name, transaction_hash)
                 }
+                DeployState::Deploying(ref mut future) => match future.poll() {
+                    Err(ref err) if self.resumed && is_unknown_transaction(err) => {
+                        warn!("{}. sending a new {} contract deployment transaction", err, name);
+                        DeployState::NotDeployed
+                    }
+                    Err(err) => return Err(err).chain_err(|| format!("Deploy{}: deployment transaction failed", name)),
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(receipt)) => {
+                        let address = match receipt.contract_address {
+                            Some(address) => address,
+                            None => bail!("transaction {:?} didn't create a contract", receipt.transaction_hash),
+                        };
+                        info!("{} deployment completed to {:?}", name, address);
+
+                        DeployState::Deployed {
+                            contract: DeployedContract::new(
+                                name.into(),
+                                include_str!("../../arbitrary/contracts/bridge.pol").into(),
+                                self.abi.into(),
+                                self.bin.into(),
+                                self.data.to_hex(),
+                                receipt,
+                            ),
+                        }
+                    }
+                },
             };
 
             self.state = next_state;
@@ -129,92 +499,85 @@ impl<T: Transport + Clone> Future for DeployMain<T> {
     }
 }
 
-pub struct DeploySide<T: Transport + Clone> {
-    config: Config,
-    side_transport: T,
-    state: DeployState<T>,
-}
+pub struct DeployMain<T: Transport + Clone>(Deploy<T>);
 
-impl<T: Transport + Clone> DeploySide<T> {
-    pub fn new(config: Config, side_transport: T) -> Self {
-        Self {
-            config,
-            side_transport,
+impl<T: Transport + Clone> DeployMain<T> {
+    pub fn new(config: Config, deploy: &DeployConfig, main_transport: T) -> Result<Self, error::Error> {
+        let data = main_constructor_data(&config)?;
+        Ok(DeployMain(Deploy {
+            name: MAIN_CONTRACT_NAME,
+            abi: include_str!("../../compiled_contracts/Main.abi"),
+            bin: include_str!("../../compiled_contracts/Main.bin"),
+            address: config.address,
+            node: config.main,
+            tx: deploy.main.clone(),
+            data,
+            transport: main_transport,
+            on_sent: None,
+            resumed: false,
             state: DeployState::NotDeployed,
-        }
+        }))
+    }
+
+    /// waits for `transaction_hash` sent by a previous run instead of sending a new transaction
+    pub fn resume(mut self, transaction_hash: Option<H256>) -> Self {
+        self.0.resume(transaction_hash);
+        self
+    }
+
+    pub fn on_sent(mut self, on_sent: OnSent) -> Self {
+        self.0.on_sent = Some(on_sent);
+        self
     }
 }
 
-impl<T: Transport + Clone> Future for DeploySide<T> {
+impl<T: Transport + Clone> Future for DeployMain<T> {
     type Item = DeployedContract;
     type Error = error::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        loop {
-            let next_state = match self.state {
-                DeployState::Deployed { ref contract } => return Ok(contract.clone().into()),
-                DeployState::NotDeployed => {
-                    let data = contracts::side::constructor(
-                        self.config.side.contract.bin.clone().0,
-                        self.config.authorities.required_signatures,
-                        self.config.authorities.accounts.clone(),
-                    );
+        self.0.poll()
+    }
+}
 
-                    let tx_request = TransactionRequest {
-                        from: self.config.address,
-                        to: None,
-                        gas: Some(self.config.txs.side_deploy.gas.into()),
-                        gas_price: Some(self.config.txs.side_deploy.gas_price.into()),
-                        value: None,
-                        data: Some(data.clone().into()),
-                        nonce: None,
-                        condition: None,
-                    };
+pub struct DeploySide<T: Transport + Clone>(Deploy<T>);
 
-                    let future = SendTransactionWithReceipt::new(SendTransactionWithReceiptOptions {
-                        transport: self.side_transport.clone(),
-                        request_timeout: self.config.side.request_timeout,
-                        poll_interval: self.config.side.poll_interval,
-                        confirmations: self.config.side.required_confirmations,
-                        transaction: tx_request,
-                    });
+impl<T: Transport + Clone> DeploySide<T> {
+    pub fn new(config: Config, deploy: &DeployConfig, side_transport: T) -> Result<Self, error::Error> {
+        let data = side_constructor_data(&config)?;
+        Ok(DeploySide(Deploy {
+            name: SIDE_CONTRACT_NAME,
+            abi: include_str!("../../compiled_contracts/Side.abi"),
+            bin: include_str!("../../compiled_contracts/Side.bin"),
+            address: config.address,
+            node: config.side,
+            tx: deploy.side.clone(),
+            data,
+            transport: side_transport,
+            on_sent: None,
+            resumed: false,
+            state: DeployState::NotDeployed,
+        }))
+    }
 
-                    info!("sending SideBridge contract deployment transaction and waiting for {} confirmations...", self.config.side.required_confirmations);
+    /// waits for `transaction_hash` sent by a previous run instead of sending a new transaction
+    pub fn resume(mut self, transaction_hash: Option<H256>) -> Self {
+        self.0.resume(transaction_hash);
+        self
+    }
 
-                    DeployState::Deploying {
-                        data,
-                        future,
-                    }
-                }
-                DeployState::Deploying {
-                    ref mut future,
-                    ref data,
-                } => {
-                    let receipt = try_ready!(
-                        future
-                            .poll()
-                            .chain_err(|| "DeploySide: deployment transaction failed")
-                    );
-                    let address = receipt
-                        .contract_address
-                        .expect("contract creation receipt must have an address; qed");
-                    info!("SideBridge deployment completed to {:?}", address);
-
-                    DeployState::Deployed {
-                        contract: DeployedContract::new(
-                            "SideBridge".into(),
-                            include_str!("../../arbitrary/contracts/bridge.pol").into(),
-                            include_str!("../../compiled_contracts/Side.abi").into(),
-                            include_str!("../../compiled_contracts/Side.bin").into(),
-                            data.to_hex(),
-                            receipt,
-                        ),
-                    }
-                }
-            };
+    pub fn on_sent(mut self, on_sent: OnSent) -> Self {
+        self.0.on_sent = Some(on_sent);
+        self
+    }
+}
 
-            self.state = next_state;
-        }
+impl<T: Transport + Clone> Future for DeploySide<T> {
+    type Item = DeployedContract;
+    type Error = error::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.0.poll()
     }
 }
 
@@ -319,3 +682,73 @@ impl DeployedContract {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deploy_progress_json() {
+        let progress = DeployProgress {
+            main: ContractProgress::Deployed {
+                address: "0000000000000000000000000000000000000001".into(),
+                transaction_hash: Some("0000000000000000000000000000000000000000000000000000000000000002".into()),
+                block: 100,
+            },
+            side: ContractProgress::Pending {
+                transaction_hash: "0000000000000000000000000000000000000000000000000000000000000003".into(),
+            },
+        };
+        let json = serde_json::to_value(&progress).unwrap();
+        assert_eq!(json["main"]["status"], "deployed");
+        assert_eq!(json["side"]["status"], "pending");
+        assert_eq!(serde_json::from_value::<DeployProgress>(json).unwrap(), progress);
+        assert_eq!(
+            progress.side.pending(),
+            Some("0000000000000000000000000000000000000000000000000000000000000003".into())
+        );
+
+        let empty: DeployProgress = serde_json::from_str("{}").unwrap();
+        assert_eq!(empty.main, ContractProgress::NotDeployed);
+    }
+
+    #[test]
+    fn test_cost_estimate_checks() {
+        let estimate = CostEstimate {
+            chain: Chain::Main,
+            estimated_gas: 1_200_000.into(),
+            gas: 1_500_000.into(),
+            gas_price: 2.into(),
+            current_gas_price: 3.into(),
+            balance: 3_000_000.into(),
+        };
+        let checks = estimate.checks();
+        assert!(checks.iter().all(|check| check.result.is_ok()));
+        assert_eq!(
+            checks[1].result,
+            Ok("2400000 wei at the configured gas price 2. 3600000 wei at the current gas price 3".into())
+        );
+
+        let checks = CostEstimate {
+            estimated_gas: 1_600_000.into(),
+            balance: 2_999_999.into(),
+            ..estimate
+        }.checks();
+        assert!(checks[0].result.is_err());
+        assert!(checks[2].result.is_err());
+    }
+
+    #[test]
+    fn test_parse_attach() {
+        assert_eq!(
+            "0x0000000000000000000000000000000000000001".parse::<Attach>().unwrap(),
+            Attach::Contract("0000000000000000000000000000000000000001".into())
+        );
+        assert_eq!(
+            "0000000000000000000000000000000000000000000000000000000000000002".parse::<Attach>().unwrap(),
+            Attach::CreationTransaction("0000000000000000000000000000000000000000000000000000000000000002".into())
+        );
+        assert!("0x01".parse::<Attach>().is_err());
+        assert!("0x000000000000000000000000000000000000000z".parse::<Attach>().is_err());
+    }
+}
@@ -18,7 +18,8 @@
 
 use std::io;
 use tokio_timer::{TimeoutError, TimerError};
-use {sofabi, rustc_hex, toml, susyweb};
+use susyweb::types::H256;
+use {secp256k1, sofabi, rustc_hex, toml, susyweb};
 
 error_chain! {
     types {
@@ -31,6 +32,7 @@ error_chain! {
         Sofabi(sofabi::Error);
         Timer(TimerError);
         Hex(rustc_hex::FromHexError);
+        Secp256k1(secp256k1::Error);
     }
 
     errors {
@@ -38,12 +40,25 @@ error_chain! {
             description("Request timed out"),
             display("Request timed out"),
         }
+        ShutdownTimedOut {
+            description("Shutdown timed out"),
+            display("Shutdown timed out with relays still in flight"),
+        }
         // workaround for error_chain not allowing to check internal error kind
         // https://github.com/rust-lang-nursery/error-chain/issues/206
         MissingFile(filename: String) {
             description("File not found"),
             display("File {} not found", filename),
         }
+        UnknownTransaction(hash: H256) {
+            description("Transaction unknown"),
+            display("Transaction {:?} is unknown to the node. it was dropped or replaced", hash),
+        }
+        // a contract call returned no data. that's what calls that revert return on some nodes
+        EmptyCallResult {
+            description("Call returned no data"),
+            display("Call returned no data"),
+        }
         // workaround for lack of susyweb:Error Display and Error implementations
         SusyWeb(err: susyweb::Error) {
             description("susyweb error"),
@@ -79,3 +94,104 @@ impl From<susyweb::Error> for Error {
         ErrorKind::SusyWeb(err).into()
     }
 }
+
+impl Error {
+    /// transient errors are expected to go away without intervention:
+    /// timeouts, connection failures and errors returned by the nodes.
+    /// all other errors (config, database, contract mismatch, reverted calls, ...) are fatal.
+    /// looks at this error and all errors it was chained from
+    pub fn is_transient(&self) -> bool {
+        // a reverted call reverts again until the contract or the bridge changes
+        !self.is_reverted() && self.any_in_chain(|kind| match *kind {
+            ErrorKind::TimedOut | ErrorKind::Timer(_) => true,
+            ErrorKind::SusyWeb(ref err) => match *err.kind() {
+                susyweb::ErrorKind::Transport(_)
+                | susyweb::ErrorKind::Unreachable
+                | susyweb::ErrorKind::Io(_)
+                | susyweb::ErrorKind::Rpc(_)
+                | susyweb::ErrorKind::InvalidResponse(_) => true,
+                _ => false,
+            },
+            _ => false,
+        })
+    }
+
+    /// whether a request timed out.
+    /// looks at this error and all errors it was chained from
+    pub fn is_timeout(&self) -> bool {
+        self.any_in_chain(|kind| match *kind {
+            ErrorKind::TimedOut | ErrorKind::Timer(_) => true,
+            _ => false,
+        })
+    }
+
+    /// whether a contract call reverted or returned no data.
+    /// looks at this error and all errors it was chained from
+    pub fn is_reverted(&self) -> bool {
+        self.any_in_chain(|kind| match *kind {
+            ErrorKind::EmptyCallResult => true,
+            ErrorKind::SusyWeb(ref err) => match *err.kind() {
+                susyweb::ErrorKind::Rpc(ref err) => {
+                    let message = err.message.to_lowercase();
+                    message.contains("revert") || message.contains("vm execution error")
+                }
+                _ => false,
+            },
+            _ => false,
+        })
+    }
+
+    fn any_in_chain<F: Fn(&ErrorKind) -> bool>(&self, matches: F) -> bool {
+        let mut error = self;
+        loop {
+            if matches(&error.0) {
+                return true;
+            }
+            match error.1.next_error.as_ref().and_then(|next| next.downcast_ref::<Error>()) {
+                Some(next) => error = next,
+                None => return false,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use susy_jsonrpc_core;
+
+    #[test]
+    fn test_is_transient() {
+        let timed_out: Result<()> = Err(ErrorKind::TimedOut.into());
+        assert!(timed_out.chain_err(|| "RelayStream: fetching logs failed").unwrap_err().is_transient());
+
+        let missing: Result<()> = Err(ErrorKind::MissingFile("db.toml".into()).into());
+        assert!(!missing.chain_err(|| "Cannot open database").unwrap_err().is_transient());
+        assert!(!Error::from("not a bridge contract").is_transient());
+
+        let unreachable: Result<()> = Err(susyweb::Error::from(susyweb::ErrorKind::Unreachable).into());
+        assert!(unreachable.chain_err(|| "RelayStream: fetching logs failed").unwrap_err().is_transient());
+        let transport = Error::from(susyweb::Error::from(susyweb::ErrorKind::Transport("connection refused".into())));
+        assert!(transport.is_transient());
+        let invalid = Error::from(susyweb::Error::from(susyweb::ErrorKind::InvalidResponse("not json".into())));
+        assert!(invalid.is_transient());
+        let rpc = |message: &str| {
+            Error::from(susyweb::Error::from(susyweb::ErrorKind::Rpc(susy_jsonrpc_core::Error {
+                code: susy_jsonrpc_core::ErrorCode::ServerError(-32000),
+                message: message.into(),
+                data: None,
+            })))
+        };
+        assert!(rpc("header not found").is_transient());
+        assert!(!rpc("VM execution error.").is_transient());
+        assert!(!rpc("execution reverted").is_transient());
+
+        let timed_out: Result<()> = Err(ErrorKind::TimedOut.into());
+        assert!(timed_out.chain_err(|| "failed to poll inner susyweb CallFuture future").unwrap_err().is_timeout());
+        assert!(!Error::from("failed to decode response").is_timeout());
+
+        let empty: Result<()> = Err(ErrorKind::EmptyCallResult.into());
+        assert!(empty.chain_err(|| "failed to poll inner susyweb CallFuture future").unwrap_err().is_reverted());
+        assert!(!Error::from(ErrorKind::TimedOut).is_reverted());
+    }
+}
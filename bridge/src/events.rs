@@ -0,0 +1,269 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! typed events of the bridge contracts.
+//!
+//! `BridgeEvent::from_log` decodes the logs of both contracts.
+//! `BridgeEventStream` decodes the logs of a `LogStream`
+
+use contracts;
+use error;
+use futures::{Async, Poll, Stream};
+use helpers;
+use log_stream::LogsInBlockRange;
+use relay_log::Chain;
+use sofabi;
+use std::collections::VecDeque;
+use susyweb::types::{Address, Log, H256};
+
+/// where an event was logged
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct EventOrigin {
+    pub chain: Chain,
+    /// address of the contract that logged the event
+    pub contract: Address,
+    pub block: u64,
+    pub transaction_hash: H256,
+    /// position of the log in its block
+    pub log_index: u64,
+}
+
+impl EventOrigin {
+    fn from_log(chain: Chain, log: &Log) -> Result<Self, error::Error> {
+        let mined = |field: &str| format!("`log` must be mined and contain `{}`", field);
+        Ok(EventOrigin {
+            chain,
+            contract: log.address,
+            block: log.block_number.ok_or_else(|| mined("block_number"))?.as_u64(),
+            transaction_hash: log.transaction_hash.ok_or_else(|| mined("transaction_hash"))?,
+            log_index: log.log_index.ok_or_else(|| mined("log_index"))?.as_u64(),
+        })
+    }
+}
+
+/// an event of the main or the side contract
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum BridgeEvent {
+    /// `Main.RelayMessage`. a message to `side` was sent
+    MainRelayMessage {
+        origin: EventOrigin,
+        message_id: H256,
+        sender: Address,
+        recipient: Address,
+    },
+    /// `Main.AcceptedMessage`. a message from `side` arrived
+    MainAcceptedMessage { origin: EventOrigin, message_id: H256 },
+    /// `Side.RelayMessage`. a message to `main` was sent
+    SideRelayMessage {
+        origin: EventOrigin,
+        message_id: H256,
+        sender: Address,
+        recipient: Address,
+    },
+    /// `Side.AcceptedMessage`. a message from `main` arrived
+    SideAcceptedMessage { origin: EventOrigin, message_id: H256 },
+    /// `Side.SignedMessage`. all signatures for a message to `main` were collected
+    SideSignedMessage {
+        origin: EventOrigin,
+        authority_responsible_for_relay: Address,
+        message_hash: H256,
+    },
+}
+
+impl BridgeEvent {
+    pub fn origin(&self) -> &EventOrigin {
+        match *self {
+            BridgeEvent::MainRelayMessage { ref origin, .. }
+            | BridgeEvent::MainAcceptedMessage { ref origin, .. }
+            | BridgeEvent::SideRelayMessage { ref origin, .. }
+            | BridgeEvent::SideAcceptedMessage { ref origin, .. }
+            | BridgeEvent::SideSignedMessage { ref origin, .. } => origin,
+        }
+    }
+
+    /// decodes a log of the bridge contract on `chain`.
+    /// `Ok(None)` for logs of other events
+    pub fn from_log(chain: Chain, log: &Log) -> Result<Option<Self>, error::Error> {
+        let topic = match log.topics.first() {
+            Some(topic) => *topic,
+            None => return Ok(None),
+        };
+        let is = |filter: sofabi::TopicFilter| topic == helpers::event_topic(&filter);
+
+        let event = match chain {
+            Chain::Main if is(contracts::main::events::relay_message::filter()) => {
+                let parsed = helpers::parse_log(contracts::main::events::relay_message::parse_log, log)?;
+                BridgeEvent::MainRelayMessage {
+                    origin: EventOrigin::from_log(chain, log)?,
+                    message_id: parsed.message_id,
+                    sender: parsed.sender,
+                    recipient: parsed.recipient,
+                }
+            }
+            Chain::Main if is(contracts::main::events::accepted_message::filter()) => {
+                let parsed = helpers::parse_log(contracts::main::events::accepted_message::parse_log, log)?;
+                BridgeEvent::MainAcceptedMessage {
+                    origin: EventOrigin::from_log(chain, log)?,
+                    message_id: parsed.message_id,
+                }
+            }
+            Chain::Side if is(contracts::side::events::relay_message::filter()) => {
+                let parsed = helpers::parse_log(contracts::side::events::relay_message::parse_log, log)?;
+                BridgeEvent::SideRelayMessage {
+                    origin: EventOrigin::from_log(chain, log)?,
+                    message_id: parsed.message_id,
+                    sender: parsed.sender,
+                    recipient: parsed.recipient,
+                }
+            }
+            Chain::Side if is(contracts::side::events::accepted_message::filter()) => {
+                let parsed = helpers::parse_log(contracts::side::events::accepted_message::parse_log, log)?;
+                BridgeEvent::SideAcceptedMessage {
+                    origin: EventOrigin::from_log(chain, log)?,
+                    message_id: parsed.message_id,
+                }
+            }
+            Chain::Side if is(contracts::side::events::signed_message::filter(sofabi::Topic::Any)) => {
+                let parsed = helpers::parse_log(contracts::side::events::signed_message::parse_log, log)?;
+                BridgeEvent::SideSignedMessage {
+                    origin: EventOrigin::from_log(chain, log)?,
+                    authority_responsible_for_relay: parsed.authority_responsible_for_relay,
+                    message_hash: parsed.message_hash,
+                }
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(event))
+    }
+}
+
+/// `Stream` of the `BridgeEvent`s in the logs of a `LogStream` of `chain`.
+/// logs of other events are skipped. logs that can't be decoded are errors
+pub struct BridgeEventStream<S> {
+    chain: Chain,
+    stream: S,
+    logs: VecDeque<Log>,
+}
+
+impl<S: Stream<Item = LogsInBlockRange, Error = error::Error>> BridgeEventStream<S> {
+    pub fn new(chain: Chain, stream: S) -> Self {
+        BridgeEventStream {
+            chain,
+            stream,
+            logs: VecDeque::new(),
+        }
+    }
+}
+
+impl<S: Stream<Item = LogsInBlockRange, Error = error::Error>> Stream for BridgeEventStream<S> {
+    type Item = BridgeEvent;
+    type Error = error::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            while let Some(log) = self.logs.pop_front() {
+                if let Some(event) = BridgeEvent::from_log(self.chain, &log)? {
+                    return Ok(Async::Ready(Some(event)));
+                }
+            }
+
+            match try_ready!(self.stream.poll()) {
+                Some(range) => self.logs.extend(range.logs),
+                None => return Ok(Async::Ready(None)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{stream, Future};
+    use susyweb::types::Bytes;
+
+    fn signed_message_log(log_index: u64) -> Log {
+        let authority: Address = "0000000000000000000000000000000000000001".into();
+        let topic = contracts::side::events::signed_message::filter(authority);
+        Log {
+            address: "0000000000000000000000000000000000000dd1".into(),
+            topics: vec![topic.topic0[0], topic.topic1[0]],
+            data: Bytes(sofabi::encode(&[sofabi::Token::FixedBytes(H256::from(3).0.to_vec())])),
+            transaction_hash: Some(2.into()),
+            block_hash: None,
+            block_number: Some(10.into()),
+            transaction_index: None,
+            log_index: Some(log_index.into()),
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        }
+    }
+
+    #[test]
+    fn test_bridge_event_from_log() {
+        let log = signed_message_log(4);
+        let event = BridgeEvent::from_log(Chain::Side, &log).unwrap().unwrap();
+        assert_eq!(
+            event,
+            BridgeEvent::SideSignedMessage {
+                origin: EventOrigin {
+                    chain: Chain::Side,
+                    contract: "0000000000000000000000000000000000000dd1".into(),
+                    block: 10,
+                    transaction_hash: 2.into(),
+                    log_index: 4,
+                },
+                authority_responsible_for_relay: "0000000000000000000000000000000000000001".into(),
+                message_hash: 3.into(),
+            }
+        );
+
+        // the main contract has no `SignedMessage` event
+        assert_eq!(BridgeEvent::from_log(Chain::Main, &log).unwrap(), None);
+
+        let pending = Log {
+            block_number: None,
+            ..log
+        };
+        assert!(BridgeEvent::from_log(Chain::Side, &pending).is_err());
+    }
+
+    #[test]
+    fn test_bridge_event_stream() {
+        let ranges = vec![
+            LogsInBlockRange {
+                from: 0,
+                to: 5,
+                logs: Vec::new(),
+            },
+            LogsInBlockRange {
+                from: 6,
+                to: 10,
+                logs: vec![signed_message_log(0), signed_message_log(1)],
+            },
+        ];
+        let events = BridgeEventStream::new(Chain::Side, stream::iter_ok::<_, error::Error>(ranges))
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(
+            events.iter().map(|event| event.origin().log_index).collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+    }
+}
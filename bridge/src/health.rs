@@ -0,0 +1,354 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! liveness (`/healthz`) and readiness (`/readyz`) of a running bridge node.
+//!
+//! a node is ready if both chains are reachable, every checkpoint is
+//! at most `max_checkpoint_lag` blocks behind the confirmed head of its chain
+//! and the authority balance on `main` is at least `min_authority_balance`.
+
+use config::Config;
+use database::State;
+use error;
+use futures::future;
+use futures::Future;
+use helpers::{serialize_u256, with_timeout};
+use http_server::{Request, Response};
+use serde::Serialize;
+use serde_json;
+use std::time::Duration;
+use susyweb;
+use susyweb::api::Namespace;
+use susyweb::types::{Address, U256};
+use susyweb::Transport;
+
+/// reachability and head of a single chain
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ChainStatus {
+    pub reachable: bool,
+    pub head: Option<u64>,
+    pub confirmed: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// how far a single checkpoint lags behind the confirmed head of its chain
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CheckpointStatus {
+    pub name: &'static str,
+    pub chain: &'static str,
+    pub block: u64,
+    pub lag: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BalanceStatus {
+    #[serde(serialize_with = "serialize_u256")]
+    pub balance: U256,
+    #[serde(serialize_with = "serialize_u256")]
+    pub min: U256,
+}
+
+/// body of `/readyz`
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub main: ChainStatus,
+    pub side: ChainStatus,
+    pub checkpoints: Vec<CheckpointStatus>,
+    pub authority_balance: Option<BalanceStatus>,
+    /// human readable reasons why the node is not ready
+    pub reasons: Vec<String>,
+}
+
+/// thresholds `Readiness::evaluate` checks against
+#[derive(Debug, PartialEq, Clone)]
+pub struct ReadinessOptions {
+    pub main_confirmations: u32,
+    pub side_confirmations: u32,
+    pub max_checkpoint_lag: u64,
+    pub min_authority_balance: U256,
+}
+
+/// status of a chain whose latest block is `head`
+pub fn chain_status(head: Result<u64, error::Error>, confirmations: u32) -> ChainStatus {
+    match head {
+        Ok(head) => ChainStatus {
+            reachable: true,
+            head: Some(head),
+            confirmed: Some(head.saturating_sub(confirmations as u64)),
+            error: None,
+        },
+        Err(err) => ChainStatus {
+            reachable: false,
+            head: None,
+            confirmed: None,
+            error: Some(err.to_string()),
+        },
+    }
+}
+
+/// how far each checkpoint of `state` lags behind the confirmed heads
+pub fn checkpoint_statuses(state: &State, main_confirmed: Option<u64>, side_confirmed: Option<u64>) -> Vec<CheckpointStatus> {
+    vec![
+        ("main_to_side_sign", "main", state.last_main_to_side_sign_at_block, main_confirmed),
+        ("side_to_main_sign", "side", state.last_side_to_main_sign_at_block, side_confirmed),
+        ("side_to_main_signatures", "side", state.last_side_to_main_signatures_at_block, side_confirmed),
+    ].into_iter()
+        .map(|(name, chain, block, confirmed)| CheckpointStatus {
+            name,
+            chain,
+            block,
+            lag: confirmed.map(|confirmed| confirmed.saturating_sub(block)),
+        })
+        .collect()
+}
+
+impl Readiness {
+    /// computes readiness from `state` and the results of querying both chains
+    pub fn evaluate(
+        state: &State,
+        main_head: Result<u64, error::Error>,
+        side_head: Result<u64, error::Error>,
+        authority_balance: Result<U256, error::Error>,
+        options: &ReadinessOptions,
+    ) -> Self {
+        let main = chain_status(main_head, options.main_confirmations);
+        let side = chain_status(side_head, options.side_confirmations);
+        let mut reasons = Vec::new();
+
+        if let Some(ref err) = main.error {
+            reasons.push(format!("main chain is unreachable: {}", err));
+        }
+        if let Some(ref err) = side.error {
+            reasons.push(format!("side chain is unreachable: {}", err));
+        }
+
+        let checkpoints = checkpoint_statuses(state, main.confirmed, side.confirmed);
+
+        for checkpoint in &checkpoints {
+            match checkpoint.lag {
+                Some(lag) if lag > options.max_checkpoint_lag => reasons.push(format!(
+                    "checkpoint {} lags {} blocks behind the confirmed head (max {})",
+                    checkpoint.name, lag, options.max_checkpoint_lag
+                )),
+                _ => {}
+            }
+        }
+
+        let authority_balance = match authority_balance {
+            Ok(balance) => {
+                if balance < options.min_authority_balance {
+                    reasons.push(format!(
+                        "authority balance on main {} is below {}",
+                        balance, options.min_authority_balance
+                    ));
+                }
+                Some(BalanceStatus {
+                    balance,
+                    min: options.min_authority_balance,
+                })
+            }
+            Err(err) => {
+                // only worth mentioning if the chain itself is reachable
+                if main.reachable {
+                    reasons.push(format!("failed to fetch authority balance on main: {}", err));
+                }
+                None
+            }
+        };
+
+        Self {
+            ready: reasons.is_empty(),
+            main,
+            side,
+            checkpoints,
+            authority_balance,
+            reasons,
+        }
+    }
+}
+
+/// answers `/healthz` and `/readyz` requests
+pub struct HealthCheck<T> {
+    main_transport: T,
+    side_transport: T,
+    authority_address: Address,
+    main_request_timeout: Duration,
+    side_request_timeout: Duration,
+    options: ReadinessOptions,
+}
+
+impl<T: Transport + 'static> HealthCheck<T>
+where
+    T::Out: 'static,
+{
+    pub fn new(main_transport: T, side_transport: T, config: &Config) -> Self {
+        let health = config.health.clone();
+        Self {
+            main_transport,
+            side_transport,
+            authority_address: config.address,
+            main_request_timeout: config.main.request_timeout,
+            side_request_timeout: config.side.request_timeout,
+            options: ReadinessOptions {
+                main_confirmations: config.main.required_confirmations,
+                side_confirmations: config.side.required_confirmations,
+                max_checkpoint_lag: health
+                    .as_ref()
+                    .map(|health| health.max_checkpoint_lag)
+                    .unwrap_or(u64::max_value()),
+                min_authority_balance: health
+                    .map(|health| health.min_authority_balance)
+                    .unwrap_or_default(),
+            },
+        }
+    }
+
+    /// queries both chains and evaluates the readiness of a node whose
+    /// latest persisted state is `state`
+    pub fn readiness(&self, state: State) -> Box<Future<Item = Readiness, Error = ()>> {
+        let main_head = with_timeout(
+            susyweb::api::Sof::new(&self.main_transport).block_number(),
+            self.main_request_timeout,
+        ).map(|head| head.as_u64())
+            .then(|result| Ok::<_, ()>(result));
+        let side_head = with_timeout(
+            susyweb::api::Sof::new(&self.side_transport).block_number(),
+            self.side_request_timeout,
+        ).map(|head| head.as_u64())
+            .then(|result| Ok::<_, ()>(result));
+        let balance = with_timeout(
+            susyweb::api::Sof::new(&self.main_transport).balance(self.authority_address, None),
+            self.main_request_timeout,
+        ).then(|result| Ok::<_, ()>(result));
+
+        let options = self.options.clone();
+        Box::new(main_head.join3(side_head, balance).map(
+            move |(main_head, side_head, balance)| {
+                Readiness::evaluate(&state, main_head, side_head, balance, &options)
+            },
+        ))
+    }
+
+    /// routes `/healthz` and `/readyz`. every other path is not found.
+    pub fn respond(&self, request: &Request, state: &State) -> Box<Future<Item = Response, Error = ()>> {
+        match request.path.as_str() {
+            // the event loop is answering requests. that's all liveness means.
+            "/healthz" => Box::new(future::ok(Response::json(200, "{\"status\": \"alive\"}\n".into()))),
+            "/readyz" => Box::new(self.readiness(state.clone()).map(|readiness| {
+                let status = if readiness.ready { 200 } else { 503 };
+                Response::json(status, json_body(&readiness))
+            })),
+            _ => Box::new(future::ok(Response::not_found())),
+        }
+    }
+}
+
+fn json_body<S: Serialize>(value: &S) -> String {
+    let mut body = serde_json::to_string_pretty(value).expect("serialization can't fail; qed");
+    body.push('\n');
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> ReadinessOptions {
+        ReadinessOptions {
+            main_confirmations: 12,
+            side_confirmations: 0,
+            max_checkpoint_lag: 10,
+            min_authority_balance: 1000.into(),
+        }
+    }
+
+    fn state() -> State {
+        State {
+            last_main_to_side_sign_at_block: 80,
+            last_side_to_main_sign_at_block: 195,
+            last_side_to_main_signatures_at_block: 200,
+            ..State::default()
+        }
+    }
+
+    #[test]
+    fn test_readiness_ready() {
+        let readiness = Readiness::evaluate(&state(), Ok(100), Ok(200), Ok(1000.into()), &options());
+        assert!(readiness.ready);
+        assert!(readiness.reasons.is_empty());
+        assert_eq!(readiness.main.confirmed, Some(88));
+        assert_eq!(readiness.checkpoints[0].lag, Some(8));
+        assert_eq!(readiness.checkpoints[1].lag, Some(5));
+        assert_eq!(readiness.checkpoints[2].lag, Some(0));
+    }
+
+    #[test]
+    fn test_readiness_unreachable_chain() {
+        let readiness = Readiness::evaluate(
+            &state(),
+            Ok(100),
+            Err(error::ErrorKind::TimedOut.into()),
+            Ok(1000.into()),
+            &options(),
+        );
+        assert!(!readiness.ready);
+        assert!(!readiness.side.reachable);
+        assert_eq!(readiness.checkpoints[1].lag, None);
+        assert_eq!(readiness.reasons.len(), 1);
+    }
+
+    #[test]
+    fn test_readiness_lagging_checkpoint_and_low_balance() {
+        let readiness = Readiness::evaluate(&state(), Ok(200), Ok(200), Ok(999.into()), &options());
+        assert!(!readiness.ready);
+        // main_to_side_sign lags 108 blocks and balance is too low
+        assert_eq!(readiness.reasons.len(), 2);
+    }
+
+    #[test]
+    fn test_health_check_readiness_queries_both_chains() {
+        let main_transport = mock_transport!(
+            "sof_blockNumber" =>
+                req => json!([]),
+                res => json!("0x64");
+            "sof_getBalance" =>
+                req => json!(["0x0000000000000000000000000000000000000001", "latest"]),
+                res => json!("0x3e8");
+        );
+        let side_transport = mock_transport!(
+            "sof_blockNumber" =>
+                req => json!([]),
+                res => json!("0xc8");
+        );
+
+        let health = HealthCheck {
+            main_transport: main_transport.clone(),
+            side_transport: side_transport.clone(),
+            authority_address: "0000000000000000000000000000000000000001".into(),
+            main_request_timeout: Duration::from_secs(1),
+            side_request_timeout: Duration::from_secs(1),
+            options: options(),
+        };
+
+        let mut event_loop = ::tokio_core::reactor::Core::new().unwrap();
+        let readiness = event_loop.run(health.readiness(state())).unwrap();
+        assert!(readiness.ready, "{:?}", readiness.reasons);
+        assert_eq!(readiness.authority_balance.unwrap().balance, 1000.into());
+        assert_eq!(main_transport.actual_requests(), main_transport.expected_requests());
+        assert_eq!(side_transport.actual_requests(), side_transport.expected_requests());
+    }
+}
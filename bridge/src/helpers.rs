@@ -20,8 +20,10 @@ use error::{self, ResultExt};
 use sofabi::{self, RawLog, FunctionOutputDecoder};
 use futures::future::FromErr;
 use futures::{Async, Future, Poll, Stream};
-use serde::de::Error;
-use serde::{Deserialize, Deserializer, Serializer};
+use serde::de::{DeserializeOwned, Error, Visitor};
+use serde::{Deserializer, Serializer};
+use serde_json;
+use std::fmt;
 use std::time::Duration;
 use tokio_timer::{Timeout, Timer};
 use susyweb::api::Namespace;
@@ -38,6 +40,25 @@ pub fn parse_log<T: Fn(RawLog) -> sofabi::Result<L>, L>(parse: T, susyweb_log: &
     parse(sofabi_log)
 }
 
+/// the signature hash (`topic0`) of the event `filter` was created for
+pub fn event_topic(filter: &sofabi::TopicFilter) -> H256 {
+    match filter.topic0 {
+        sofabi::Topic::This(ref hash) => hash.clone(),
+        _ => panic!("filters created from an event always contain its signature in `topic0`; qed"),
+    }
+}
+
+/// `TopicFilter` that matches logs of any of the events of `filters`.
+/// all topics except `topic0` are ignored.
+pub fn any_of_events(filters: &[sofabi::TopicFilter]) -> sofabi::TopicFilter {
+    sofabi::TopicFilter {
+        topic0: sofabi::Topic::OneOf(filters.iter().map(event_topic).collect()),
+        topic1: sofabi::Topic::Any,
+        topic2: sofabi::Topic::Any,
+        topic3: sofabi::Topic::Any,
+    }
+}
+
 /// use `AsyncCall::new(transport, contract_address, timeout, output_decoder)` to
 /// get a `Future` that resolves with the decoded output from calling `function`
 /// on `contract_address`.
@@ -127,12 +148,46 @@ impl<T: Transport> Future for AsyncTransaction<T> {
 /// frequently don't fit into `i64`.
 /// workaround: put them in string literals, use this custom
 /// deserializer and parse them as U256.
+/// integers that fit, e.g. set from environment variables, are accepted as well
 pub fn deserialize_u256<'de, D>(deserializer: D) -> Result<U256, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let s: &str = Deserialize::deserialize(deserializer)?;
-    U256::from_dec_str(s).map_err(|_| D::Error::custom("failed to parse U256 from dec str"))
+    struct U256Visitor;
+
+    impl<'de> Visitor<'de> for U256Visitor {
+        type Value = U256;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a decimal string or a non-negative integer")
+        }
+
+        fn visit_str<E: Error>(self, s: &str) -> Result<U256, E> {
+            U256::from_dec_str(s).map_err(|_| E::custom("failed to parse U256 from dec str"))
+        }
+
+        fn visit_u64<E: Error>(self, value: u64) -> Result<U256, E> {
+            Ok(value.into())
+        }
+
+        fn visit_i64<E: Error>(self, value: i64) -> Result<U256, E> {
+            if value < 0 {
+                return Err(E::custom("U256 can't be negative"));
+            }
+            Ok((value as u64).into())
+        }
+    }
+
+    deserializer.deserialize_any(U256Visitor)
+}
+
+/// like `deserialize_u256` but for optional fields.
+/// use together with `#[serde(default)]`
+pub fn deserialize_optional_u256<'de, D>(deserializer: D) -> Result<Option<U256>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_u256(deserializer).map(Some)
 }
 
 pub fn serialize_u256<S>(value: &U256, serializer: S) -> Result<S::Ok, S::Error>
@@ -142,6 +197,25 @@ where
     serializer.serialize_str(&format!("{}", value))
 }
 
+pub fn serialize_optional_u256<S>(value: &Option<U256>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match *value {
+        Some(ref value) => serialize_u256(value, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// wraps a susyweb `CallFuture` in a `Timeout` of `timeout`
+pub fn with_timeout<I, F>(future: CallFuture<I, F>, timeout: Duration) -> Timeout<FromErr<CallFuture<I, F>, error::Error>>
+where
+    I: DeserializeOwned,
+    F: Future<Item = serde_json::Value, Error = susyweb::Error>,
+{
+    Timer::default().timeout(future.from_err(), timeout)
+}
+
 /// extends the `Stream` trait by the `last` function
 pub trait StreamExt<I> {
     /// if you're interested only in the last item in a stream
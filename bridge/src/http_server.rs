@@ -0,0 +1,283 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! minimal http server for the operational endpoints of a bridge node.
+//!
+//! handles one request per connection and closes the connection afterwards.
+//! that's all orchestrators and monitoring need.
+
+use error::{self, ResultExt};
+use futures::{Async, Future, Poll, Stream};
+use std::io::{self, Read};
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::str;
+use tokio_core::net::{TcpListener, TcpStream};
+use tokio_core::reactor::Handle;
+use tokio_io::io::write_all;
+
+/// requests larger than this are rejected
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+/// a parsed http request
+#[derive(Debug, PartialEq, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub peer: SocketAddr,
+}
+
+impl Request {
+    /// value of the first header named `name` (case insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|&&(ref key, _)| key.eq_ignore_ascii_case(name))
+            .map(|&(_, ref value)| value.as_str())
+    }
+}
+
+/// an http response
+#[derive(Debug, PartialEq, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(status: u16, body: String) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body,
+        }
+    }
+
+    pub fn text(status: u16, body: String) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body,
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self::text(404, "not found\n".into())
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            500 => "Internal Server Error",
+            503 => "Service Unavailable",
+            _ => "",
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            self.reason(),
+            self.content_type,
+            self.body.len(),
+            self.body
+        ).into_bytes()
+    }
+}
+
+/// parses `buffer` into a `Request`.
+/// returns `None` if `buffer` doesn't contain a complete request yet.
+fn parse_request(buffer: &[u8], peer: SocketAddr) -> io::Result<Option<Request>> {
+    let header_end = match buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+        Some(position) => position,
+        None => return Ok(None),
+    };
+
+    let head = str::from_utf8(&buffer[..header_end])
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "request head is not utf8"))?;
+    let mut lines = head.split("\r\n");
+
+    let request_line = lines.next().unwrap_or("");
+    let mut parts = request_line.split(' ');
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) if !method.is_empty() => (method.to_string(), path.to_string()),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid request line: {:?}", request_line),
+            ))
+        }
+    };
+
+    let headers = lines
+        .filter_map(|line| {
+            let mut split = line.splitn(2, ':');
+            match (split.next(), split.next()) {
+                (Some(key), Some(value)) => Some((key.trim().to_string(), value.trim().to_string())),
+                _ => None,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let content_length = headers
+        .iter()
+        .find(|&&(ref key, _)| key.eq_ignore_ascii_case("content-length"))
+        .map(|&(_, ref value)| value.parse::<usize>())
+        .unwrap_or(Ok(0))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid content-length"))?;
+
+    let body_start = header_end + 4;
+    if buffer.len() < body_start + content_length {
+        return Ok(None);
+    }
+
+    Ok(Some(Request {
+        method,
+        path,
+        headers,
+        body: buffer[body_start..body_start + content_length].to_vec(),
+        peer,
+    }))
+}
+
+/// `Future` that reads a single complete `Request` from `socket`
+struct ReadRequest {
+    socket: Option<TcpStream>,
+    peer: SocketAddr,
+    buffer: Vec<u8>,
+}
+
+impl Future for ReadRequest {
+    type Item = (TcpStream, Request);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            if let Some(request) = parse_request(&self.buffer, self.peer)? {
+                let socket = self.socket
+                    .take()
+                    .expect("`ReadRequest` is never polled after completion; qed");
+                return Ok(Async::Ready((socket, request)));
+            }
+
+            if self.buffer.len() > MAX_REQUEST_SIZE {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "request too large"));
+            }
+
+            let mut chunk = [0u8; 1024];
+            let read = self.socket
+                .as_mut()
+                .expect("`ReadRequest` is never polled after completion; qed")
+                .read(&mut chunk);
+            match read {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "connection closed before request was complete",
+                    ))
+                }
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// binds to `addr` and returns a `Future` that, when run on the event loop of `handle`,
+/// answers every incoming request with the `Response` produced by `handler`.
+pub fn serve<H, F>(
+    addr: &SocketAddr,
+    handle: &Handle,
+    handler: H,
+) -> Result<Box<Future<Item = (), Error = ()>>, error::Error>
+where
+    H: Fn(Request) -> F + 'static,
+    F: Future<Item = Response, Error = ()> + 'static,
+{
+    let listener = TcpListener::bind(addr, handle)
+        .chain_err(|| format!("Cannot bind http server to {}", addr))?;
+    info!("http server listening on {}", addr);
+
+    let handle = handle.clone();
+    let handler = Rc::new(handler);
+    let server = listener
+        .incoming()
+        .for_each(move |(socket, peer)| {
+            let handler = handler.clone();
+            let connection = ReadRequest {
+                socket: Some(socket),
+                peer,
+                buffer: Vec::new(),
+            }.map_err(move |err| warn!("http server: failed to read request from {}: {}", peer, err))
+                .and_then(move |(socket, request)| {
+                    (*handler)(request).map(|response| (socket, response))
+                })
+                .and_then(move |(socket, response)| {
+                    write_all(socket, response.to_bytes())
+                        .map(|_| ())
+                        .map_err(move |err| warn!("http server: failed to respond to {}: {}", peer, err))
+                });
+            handle.spawn(connection);
+            Ok(())
+        })
+        .map_err(|err| error!("http server stopped accepting connections: {}", err));
+
+    Ok(Box::new(server))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer() -> SocketAddr {
+        "127.0.0.1:50000".parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_request_incomplete() {
+        assert_eq!(parse_request(b"GET /readyz HTTP/1.1\r\nHost: x\r\n", peer()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_request_without_body() {
+        let request = parse_request(b"GET /readyz HTTP/1.1\r\nHost: localhost\r\n\r\n", peer())
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/readyz");
+        assert_eq!(request.header("host"), Some("localhost"));
+        assert!(request.body.is_empty());
+    }
+
+    #[test]
+    fn test_parse_request_waits_for_body() {
+        let head = b"POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\nab";
+        assert_eq!(parse_request(head, peer()).unwrap(), None);
+
+        let full = b"POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\nabcd";
+        assert_eq!(parse_request(full, peer()).unwrap().unwrap().body, b"abcd".to_vec());
+    }
+}
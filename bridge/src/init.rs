@@ -0,0 +1,261 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! scaffolding of the config and database file of a new authority for `susy-bridge init`
+
+use database::State;
+use error;
+use futures::future::{self, loop_fn, Loop};
+use futures::Future;
+use helpers::with_timeout;
+use std::path::PathBuf;
+use std::time::Duration;
+use susyweb;
+use susyweb::api::Namespace;
+use susyweb::types::{Address, BlockNumber, U256};
+use susyweb::Transport;
+
+/// network ids of public chains. logs on them need more confirmations
+const PUBLIC_NETWORK_IDS: [&str; 4] = ["1", "3", "4", "42"];
+const PUBLIC_NETWORK_CONFIRMATIONS: u32 = 12;
+const PRIVATE_NETWORK_CONFIRMATIONS: u32 = 1;
+
+type BoxFuture<I> = Box<Future<Item = I, Error = error::Error>>;
+
+/// values for the config of a chain that `init` queries from its node
+#[derive(Debug, PartialEq, Clone)]
+pub struct ChainDefaults {
+    pub network_id: String,
+    pub required_confirmations: u32,
+    pub gas_price: U256,
+}
+
+/// what `susy-bridge init` writes into the config
+#[derive(Debug, PartialEq, Clone)]
+pub struct InitOptions {
+    pub address: Address,
+    pub main_http: String,
+    pub side_http: String,
+    pub authorities: Vec<Address>,
+    pub required_signatures: u32,
+    pub main_contract_bin: PathBuf,
+    pub side_contract_bin: PathBuf,
+}
+
+/// a majority of `authorities`
+pub fn default_required_signatures(authorities: usize) -> u32 {
+    (authorities / 2 + 1) as u32
+}
+
+pub fn default_confirmations(network_id: &str) -> u32 {
+    if PUBLIC_NETWORK_IDS.contains(&network_id) {
+        PUBLIC_NETWORK_CONFIRMATIONS
+    } else {
+        PRIVATE_NETWORK_CONFIRMATIONS
+    }
+}
+
+/// `Future` resolving to the `ChainDefaults` of the chain `transport` is connected to
+pub fn chain_defaults<T: Transport>(transport: &T, request_timeout: Duration) -> BoxFuture<ChainDefaults>
+where
+    T::Out: 'static,
+{
+    let network_id = with_timeout(susyweb::api::Net::new(transport).version(), request_timeout);
+    let gas_price = with_timeout(susyweb::api::Sof::new(transport).gas_price(), request_timeout);
+    Box::new(network_id.join(gas_price).map(|(network_id, gas_price)| ChainDefaults {
+        required_confirmations: default_confirmations(&network_id),
+        network_id,
+        gas_price,
+    }))
+}
+
+/// `Future` resolving to the number of the block the contract at `address` was deployed in.
+/// binary search for the first block with code at `address`.
+/// needs a node that keeps the state of old blocks if the contract wasn't deployed recently
+pub fn deployed_at_block<T: Transport + 'static>(transport: T, address: Address, request_timeout: Duration) -> BoxFuture<u64>
+where
+    T::Out: 'static,
+{
+    let head = with_timeout(susyweb::api::Sof::new(&transport).block_number(), request_timeout);
+    let code = with_timeout(susyweb::api::Sof::new(&transport).code(address, None), request_timeout);
+    Box::new(head.join(code).and_then(move |(head, code)| -> BoxFuture<u64> {
+        if code.0.is_empty() {
+            return Box::new(future::err(format!("there is no contract at {:?}", address).into()));
+        }
+        // the block we're looking for is in `[low, high]`
+        Box::new(loop_fn((transport, 0, head.as_u64()), move |(transport, low, high)| -> BoxFuture<Loop<u64, (T, u64, u64)>> {
+            if low == high {
+                return Box::new(future::ok(Loop::Break(low)));
+            }
+            let middle = low + (high - low) / 2;
+            let code = with_timeout(
+                susyweb::api::Sof::new(&transport).code(address, Some(BlockNumber::Number(middle))),
+                request_timeout,
+            );
+            Box::new(code.map(move |code| {
+                if code.0.is_empty() {
+                    Loop::Continue((transport, middle + 1, high))
+                } else {
+                    Loop::Continue((transport, low, middle))
+                }
+            }))
+        }))
+    }))
+}
+
+/// the initial `State` of a bridge whose contracts were deployed at the given blocks
+pub fn initial_state(main_contract: Address, main_deployed_at: u64, side_contract: Address, side_deployed_at: u64) -> State {
+    State {
+        main_contract_address: main_contract,
+        side_contract_address: side_contract,
+        main_deployed_at_block: main_deployed_at,
+        side_deployed_at_block: side_deployed_at,
+        last_main_to_side_sign_at_block: main_deployed_at,
+        last_side_to_main_sign_at_block: side_deployed_at,
+        last_side_to_main_signatures_at_block: side_deployed_at,
+        last_main_to_side_sign_at_log_index: None,
+        last_side_to_main_sign_at_log_index: None,
+        last_side_to_main_signatures_at_log_index: None,
+    }
+}
+
+/// a commented config file
+pub fn render_config(options: &InitOptions, main: &ChainDefaults, side: &ChainDefaults) -> String {
+    let accounts = options
+        .authorities
+        .iter()
+        .map(|authority| format!("\t\"{:?}\",\n", authority))
+        .collect::<String>();
+    format!(
+        r#"# generated by `susy-bridge init`
+# READ THE CONFIG DOCUMENTATION AT:
+# https://octonion.institute/susytech/susy-bridge/#configuration
+
+# address of this authority. must be unlocked on both nodes
+address = "{address:?}"
+
+estimated_gas_cost_of_withdraw = "200000"
+
+# limits total balance on `main` and therefore total sophy that could get lost
+# if the bridge is faulty or compromised in any way!
+# set to `"0"` to disable limit (not recommended at this point)
+# currently set to 10 sophy.
+max_total_main_contract_balance = "10000000000000000000"
+
+# limit `msg.value` for a single deposit into the main contract.
+# set to `"0"` to disable limit (not recommended at this point)
+# currently set to 1 sophy.
+max_single_deposit_value = "1000000000000000000"
+
+[main]
+http = "{main_http}"
+# network id {main_network_id}
+required_confirmations = {main_confirmations}
+
+[main.contract]
+# compiled main contract. optional. used by `susy-bridge-deploy` and `init --manifest`.
+# if given the bridge verifies the deployed code against it at startup
+bin = "{main_bin}"
+
+[side]
+http = "{side_http}"
+# network id {side_network_id}
+required_confirmations = {side_confirmations}
+
+[side.contract]
+# compiled side contract. optional. used by `susy-bridge-deploy` and `init --manifest`.
+# if given the bridge verifies the deployed code against it at startup
+bin = "{side_bin}"
+
+[authorities]
+accounts = [
+{accounts}]
+required_signatures = {required_signatures}
+
+[transactions]
+# `gas_price` is the gas price the nodes reported during `init`.
+# it might need adjustment once in a while.
+
+# these happen on `main`:
+withdraw_relay = {{ gas = "200000", gas_price = "{main_gas_price}" }}
+
+# these happen on `side`:
+deposit_relay = {{ gas = "150000", gas_price = "{side_gas_price}" }}
+withdraw_confirm = {{ gas = "300000", gas_price = "{side_gas_price}" }}
+
+# only read by `susy-bridge-deploy`. not needed if this authority doesn't deploy the contracts
+[deploy]
+main = {{ gas = "1500000", gas_price = "{main_gas_price}" }}
+side = {{ gas = "3000000", gas_price = "{side_gas_price}" }}
+"#,
+        address = options.address,
+        main_http = options.main_http,
+        main_network_id = main.network_id,
+        main_bin = options.main_contract_bin.display(),
+        main_confirmations = main.required_confirmations,
+        side_http = options.side_http,
+        side_network_id = side.network_id,
+        side_bin = options.side_contract_bin.display(),
+        side_confirmations = side.required_confirmations,
+        accounts = accounts,
+        required_signatures = options.required_signatures,
+        main_gas_price = main.gas_price,
+        side_gas_price = side.gas_price,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Config;
+
+    #[test]
+    fn test_render_config() {
+        let options = InitOptions {
+            address: "0000000000000000000000000000000000000001".into(),
+            main_http: "http://localhost:8545".into(),
+            side_http: "http://localhost:8546".into(),
+            authorities: vec![
+                "0000000000000000000000000000000000000001".into(),
+                "0000000000000000000000000000000000000002".into(),
+                "0000000000000000000000000000000000000003".into(),
+            ],
+            required_signatures: default_required_signatures(3),
+            main_contract_bin: "../compiled_contracts/Main.bin".into(),
+            side_contract_bin: "../compiled_contracts/Side.bin".into(),
+        };
+        let main = ChainDefaults {
+            network_id: "1".into(),
+            required_confirmations: default_confirmations("1"),
+            gas_price: 20_000_000_000u64.into(),
+        };
+        let side = ChainDefaults {
+            network_id: "77".into(),
+            required_confirmations: default_confirmations("77"),
+            gas_price: 0.into(),
+        };
+
+        let config = Config::load_from_str(&render_config(&options, &main, &side)).unwrap();
+        assert_eq!(config.address, options.address);
+        assert_eq!(config.main.http, "http://localhost:8545");
+        assert_eq!(config.main.required_confirmations, 12);
+        assert_eq!(config.side.required_confirmations, 1);
+        assert_eq!(config.authorities.accounts, options.authorities);
+        assert_eq!(config.authorities.required_signatures, 2);
+        assert_eq!(config.txs.withdraw_relay.gas_price, 20_000_000_000u64.into());
+        assert_eq!(config.txs.deposit_relay.gas_price, 0.into());
+    }
+}
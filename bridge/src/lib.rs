@@ -30,8 +30,9 @@
 //! those futures are supposed to each do one single complete relay
 //! (1. `MainToSideSign`, 2. `SideToMainSign`, 3. `SideToMainSignatures`).
 //! `RelayStream` polls the log stream, calls `LogToFuture.log_to_future` for each log,
-//! and yields the numbers of those blocks for which all such created futures
-//! have completed. these block numbers are then persisted
+//! quarantines those logs it fails for (see `quarantine`)
+//! and yields the blocks and logs up to which all such created futures
+//! have completed, including block ranges without logs. these checkpoints are then persisted
 //! so the bridge doesn't have to check logs up to them again next time it's started.
 //!
 //! a `Bridge` instance is constructed as follows (how the parts fit together):
@@ -74,14 +75,16 @@ extern crate pretty_assertions;
 #[macro_use]
 extern crate quickcheck;
 extern crate rustc_hex;
+extern crate secp256k1;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
-#[cfg_attr(test, macro_use)]
+#[macro_use]
 extern crate serde_json;
 extern crate bridge_contracts as contracts;
 extern crate tiny_keccak;
 extern crate tokio_core;
+extern crate tokio_io;
 extern crate tokio_timer;
 extern crate toml;
 extern crate susyweb;
@@ -93,20 +96,36 @@ mod macros;
 #[macro_use]
 mod test;
 
+pub mod admin;
+pub mod authority_set;
+pub mod balance_monitor;
+pub mod check;
 mod block_number_stream;
 mod bridge;
-pub use bridge::Bridge;
+pub use bridge::{Bridge, ReloadHandle, ShutdownHandle};
 pub mod config;
 pub mod database;
 pub mod deploy;
 pub mod error;
+pub mod events;
+pub mod health;
+pub mod http_server;
+pub mod init;
 mod ordered_stream;
 pub use ordered_stream::OrderedStream;
+pub mod participation;
+pub mod pause;
+pub mod quarantine;
 pub mod helpers;
 mod main_contract;
 pub use main_contract::MainContract;
+pub mod manifest;
+pub mod manual_relay;
+pub mod metrics;
 mod accept_message_from_main;
 pub use accept_message_from_main::AcceptMessageFromMain;
+pub mod relay_log;
+pub mod rewind;
 mod relay_stream;
 pub use relay_stream::RelayStream;
 mod send_tx_with_receipt;
@@ -116,9 +135,13 @@ mod side_to_main_sign;
 pub use side_to_main_sign::SideToMainSign;
 mod side_to_main_signatures;
 pub use side_to_main_signatures::SideToMainSignatures;
+pub mod status;
+pub mod supervisor;
+pub mod tracker;
+pub mod verify;
 
 mod log_stream;
-pub use log_stream::{LogStream, LogStreamOptions};
+pub use log_stream::{fetch_logs, LogStream, LogStreamOptions, LogsInBlockRange};
 
 mod signature;
 pub use signature::Signature;
@@ -128,6 +151,8 @@ pub use message_to_main::{MessageToMain, MESSAGE_LENGTH};
 
 #[cfg(test)]
 extern crate susy_jsonrpc_core;
+#[cfg(test)]
+extern crate tempdir;
 
 #[cfg(test)]
 pub use test::MockTransport;
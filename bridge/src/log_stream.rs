@@ -45,6 +45,23 @@ fn filter_to_builder(filter: &sofabi::TopicFilter, address: Address) -> FilterBu
         .topics(t0, t1, t2, t3)
 }
 
+/// returns `Future` that resolves with all logs matching `filter`
+/// emitted by `contract_address` in inclusive block range `[from, to]`
+pub fn fetch_logs<T: Transport>(
+    transport: &T,
+    contract_address: Address,
+    filter: &sofabi::TopicFilter,
+    from: u64,
+    to: u64,
+    request_timeout: Duration,
+) -> Timeout<FromErr<CallFuture<Vec<Log>, T::Out>, error::Error>> {
+    let filter = filter_to_builder(filter, contract_address)
+        .from_block(from.into())
+        .to_block(to.into())
+        .build();
+    Timer::default().timeout(susyweb::api::Sof::new(transport).logs(filter).from_err(), request_timeout)
+}
+
 /// options for creating a `LogStream`. passed to `LogStream::new`
 pub struct LogStreamOptions<T> {
     pub filter: sofabi::TopicFilter,
@@ -117,6 +134,12 @@ impl<T: Transport> LogStream<T> {
             topic,
         }
     }
+
+    /// applies to requests made after the current one
+    pub fn set_options(&mut self, request_timeout: Duration, poll_interval: Duration, confirmations: u32) {
+        self.request_timeout = request_timeout;
+        self.block_number_stream.set_options(request_timeout, poll_interval, confirmations);
+    }
 }
 
 impl<T: Transport> Stream for LogStream<T> {
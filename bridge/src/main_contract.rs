@@ -18,7 +18,7 @@ use contracts;
 use database::State;
 use sofabi::FunctionOutputDecoder;
 use sophon_types::{Address, U256, H256};
-use helpers::{AsyncCall, AsyncTransaction};
+use helpers::{self, AsyncCall, AsyncTransaction};
 use log_stream::{LogStream, LogStreamOptions};
 use message_to_main::MessageToMain;
 use signature::Signature;
@@ -65,6 +65,12 @@ impl<T: Transport> MainContract<T> {
         self.call(payload, decoder)
     }
 
+    /// number of signatures the contract requires
+    pub fn required_signatures(&self) -> AsyncCall<T, contracts::main::functions::required_signatures::Decoder> {
+        let (payload, decoder) = contracts::main::functions::required_signatures::call();
+        self.call(payload, decoder)
+    }
+
     /// relay a tx from side to main by submitting message and collected signatures
     pub fn relay_side_to_main(
         &self,
@@ -107,6 +113,22 @@ impl<T: Transport> MainContract<T> {
         })
     }
 
+    /// logs of all events `MessageTracker` follows on `main`
+    pub fn message_lifecycle_log_stream(&self, after: u64) -> LogStream<T> {
+        LogStream::new(LogStreamOptions {
+            filter: helpers::any_of_events(&[
+                contracts::main::events::relay_message::filter(),
+                contracts::main::events::accepted_message::filter(),
+            ]),
+            request_timeout: self.request_timeout,
+            poll_interval: self.logs_poll_interval,
+            confirmations: self.required_log_confirmations,
+            transport: self.transport.clone(),
+            contract_address: self.contract_address,
+            after,
+        })
+    }
+
     pub fn relayed_message_by_id(&self, id: H256) -> AsyncCall<T, contracts::main::functions::relayed_messages::Decoder> {
         let (payload, decoder) = contracts::main::functions::relayed_messages::call(id);
         self.call(payload, decoder)
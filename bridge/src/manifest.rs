@@ -0,0 +1,252 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! machine readable record of a deployment written by `susy-bridge-deploy`.
+//! other authorities pass it to `susy-bridge init --manifest` to get a verified database
+
+use authority_set::AuthoritySet;
+use check::Check;
+use config::{Config, NodeConfig};
+use database::State;
+use error::{self, ResultExt};
+use init::initial_state;
+use relay_log::Chain;
+use serde_json;
+use std::fs;
+use std::path::Path;
+use susyweb::types::{Address, H256};
+use tiny_keccak;
+
+const MAIN_ABI: &str = include_str!("../../compiled_contracts/Main.abi");
+const SIDE_ABI: &str = include_str!("../../compiled_contracts/Side.abi");
+
+fn keccak256(bytes: &[u8]) -> H256 {
+    tiny_keccak::keccak256(bytes).into()
+}
+
+/// a deployed contract and what it was deployed with
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ContractManifest {
+    pub name: String,
+    /// as reported by `net_version`
+    pub network_id: String,
+    pub address: Address,
+    pub deployed_at_block: u64,
+    /// `None` if the contract was attached by its address
+    pub transaction_hash: Option<H256>,
+    pub constructor_args: AuthoritySet,
+    /// keccak256 of the contract ABI JSON
+    pub abi_hash: H256,
+    /// keccak256 of the contract bytecode without constructor args
+    pub bytecode_hash: H256,
+}
+
+impl ContractManifest {
+    /// differences to the contract compiled from `abi` and `bytecode`
+    /// deployed with `authorities` on the chain with `network_id`
+    fn differences(&self, network_id: &str, authorities: &AuthoritySet, abi: &str, bytecode: Option<&[u8]>) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.network_id != network_id {
+            differences.push(format!("deployed on network {} but the node is on network {}", self.network_id, network_id));
+        }
+        if self.abi_hash != keccak256(abi.as_bytes()) {
+            differences.push("ABI differs from the compiled contract".into());
+        }
+        match bytecode {
+            Some(bytecode) if self.bytecode_hash != keccak256(bytecode) => {
+                differences.push("bytecode differs from the compiled contract".into())
+            }
+            Some(_) => {}
+            None => differences.push("no `contract.bin` configured to compare the bytecode with".into()),
+        }
+        differences.extend(
+            self.constructor_args
+                .differences(authorities)
+                .into_iter()
+                .map(|difference| format!("constructor args: {}", difference)),
+        );
+        differences
+    }
+}
+
+/// the deployment of both bridge contracts
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct DeploymentManifest {
+    pub bridge_version: String,
+    /// git commit `susy-bridge-deploy` was built from
+    pub git_hash: String,
+    /// polc version the contracts were compiled with
+    pub polc_version: String,
+    pub main: ContractManifest,
+    pub side: ContractManifest,
+}
+
+impl DeploymentManifest {
+    /// manifest of the contracts in `state` deployed with `config`.
+    /// fails if `config` has no compiled contracts
+    pub fn new(
+        config: &Config,
+        state: &State,
+        network_ids: (String, String),
+        transaction_hashes: (Option<H256>, Option<H256>),
+    ) -> Result<Self, error::Error> {
+        let authorities = AuthoritySet::from_config(&config.authorities);
+        let bytecode_hash = |chain: Chain, node: &NodeConfig| -> Result<H256, error::Error> {
+            match node.contract {
+                Some(ref contract) => Ok(keccak256(&contract.bin.0)),
+                None => Err(format!("`{}.contract.bin` is required to write the deployment manifest", chain).into()),
+            }
+        };
+        Ok(DeploymentManifest {
+            bridge_version: env!("CARGO_PKG_VERSION").into(),
+            git_hash: env!("GIT_HASH").trim().into(),
+            polc_version: env!("POLC_VERSION").into(),
+            main: ContractManifest {
+                name: "Main".into(),
+                network_id: network_ids.0,
+                address: state.main_contract_address,
+                deployed_at_block: state.main_deployed_at_block,
+                transaction_hash: transaction_hashes.0,
+                constructor_args: authorities.clone(),
+                abi_hash: keccak256(MAIN_ABI.as_bytes()),
+                bytecode_hash: bytecode_hash(Chain::Main, &config.main)?,
+            },
+            side: ContractManifest {
+                name: "SideBridge".into(),
+                network_id: network_ids.1,
+                address: state.side_contract_address,
+                deployed_at_block: state.side_deployed_at_block,
+                transaction_hash: transaction_hashes.1,
+                constructor_args: authorities,
+                abi_hash: keccak256(SIDE_ABI.as_bytes()),
+                bytecode_hash: bytecode_hash(Chain::Side, &config.side)?,
+            },
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, error::Error> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).chain_err(|| format!("Cannot read deployment manifest {:?}", path))?;
+        serde_json::from_str(&contents).chain_err(|| format!("Cannot parse deployment manifest {:?}", path))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), error::Error> {
+        let path = path.as_ref();
+        let contents = serde_json::to_string_pretty(self).expect("serialization of manifest can't fail; qed");
+        fs::write(path, contents).chain_err(|| format!("Cannot write deployment manifest {:?}", path))?;
+        Ok(())
+    }
+
+    /// initial database state for the deployed contracts
+    pub fn state(&self) -> State {
+        initial_state(
+            self.main.address,
+            self.main.deployed_at_block,
+            self.side.address,
+            self.side.deployed_at_block,
+        )
+    }
+
+    /// checks the manifest against the local `config` and the network ids of its nodes.
+    /// doesn't query the contracts. see `verify::Verify` for that
+    pub fn checks(&self, config: &Config, main_network_id: &str, side_network_id: &str) -> Vec<Check> {
+        let authorities = AuthoritySet::from_config(&config.authorities);
+        let check = |chain: Chain, contract: &ContractManifest, network_id: &str, abi: &str, node: &NodeConfig| {
+            let bytecode = node.contract.as_ref().map(|contract| &contract.bin.0[..]);
+            let differences = contract.differences(network_id, &authorities, abi, bytecode);
+            let result = if differences.is_empty() {
+                Ok(format!("{} at {:?} matches the local config", contract.name, contract.address))
+            } else {
+                Err(differences.join(", "))
+            };
+            Check::new(format!("{} manifest", chain), result)
+        };
+        vec![
+            check(Chain::Main, &self.main, main_network_id, MAIN_ABI, &config.main),
+            check(Chain::Side, &self.side, side_network_id, SIDE_ABI, &config.side),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contract() -> ContractManifest {
+        ContractManifest {
+            name: "Main".into(),
+            network_id: "42".into(),
+            address: "0000000000000000000000000000000000000001".into(),
+            deployed_at_block: 10,
+            transaction_hash: Some("0000000000000000000000000000000000000000000000000000000000000002".into()),
+            constructor_args: AuthoritySet {
+                required_signatures: 1,
+                accounts: vec!["0000000000000000000000000000000000000003".into()],
+            },
+            abi_hash: keccak256(b"[]"),
+            bytecode_hash: keccak256(&[0x60, 0x80]),
+        }
+    }
+
+    #[test]
+    fn test_manifest_json_and_state() {
+        let manifest = DeploymentManifest {
+            bridge_version: "0.5.0".into(),
+            git_hash: "abc".into(),
+            polc_version: "0.4.24".into(),
+            main: contract(),
+            side: ContractManifest {
+                name: "SideBridge".into(),
+                address: "0000000000000000000000000000000000000004".into(),
+                deployed_at_block: 20,
+                transaction_hash: None,
+                ..contract()
+            },
+        };
+        let json = serde_json::to_string(&manifest).unwrap();
+        assert_eq!(serde_json::from_str::<DeploymentManifest>(&json).unwrap(), manifest);
+
+        let state = manifest.state();
+        assert_eq!(state.main_contract_address, "0000000000000000000000000000000000000001".into());
+        assert_eq!(state.side_deployed_at_block, 20);
+        assert_eq!(state.last_side_to_main_sign_at_block, 20);
+    }
+
+    #[test]
+    fn test_contract_manifest_differences() {
+        let contract = contract();
+        let authorities = contract.constructor_args.clone();
+        assert!(contract.differences("42", &authorities, "[]", Some(&[0x60, 0x80][..])).is_empty());
+
+        let other_authorities = AuthoritySet {
+            required_signatures: 2,
+            ..authorities.clone()
+        };
+        assert_eq!(
+            contract.differences("1", &other_authorities, "[{}]", Some(&[0x60][..])),
+            vec![
+                "deployed on network 42 but the node is on network 1".to_string(),
+                "ABI differs from the compiled contract".into(),
+                "bytecode differs from the compiled contract".into(),
+                "constructor args: required signatures 1 != 2".into(),
+            ]
+        );
+        assert_eq!(
+            contract.differences("42", &authorities, "[]", None),
+            vec!["no `contract.bin` configured to compare the bytecode with".to_string()]
+        );
+    }
+}
@@ -0,0 +1,271 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! relaying a single transaction on demand through `susy-bridge relay`.
+//!
+//! runs the same relay futures as the `Bridge` for the logs of one transaction
+//! without touching the checkpoints in the database.
+
+use accept_message_from_main::AcceptMessageFromMain;
+use contracts;
+use error;
+use futures::future::{self, join_all};
+use futures::Future;
+use helpers::{self, with_timeout};
+use main_contract::MainContract;
+use relay_log::Direction;
+use side_contract::SideContract;
+use side_to_main_sign::SideToMainSign;
+use side_to_main_signatures::SideToMainSignatures;
+use susyweb;
+use susyweb::api::Namespace;
+use susyweb::types::{Address, Log, H256};
+use susyweb::Transport;
+
+/// parses a direction as accepted on the command line
+pub fn parse_direction(direction: &str) -> Result<Direction, error::Error> {
+    match direction {
+        "main-to-side" => Ok(Direction::MainToSide),
+        "side-to-main-sign" => Ok(Direction::SideToMainSign),
+        "side-to-main-submit" => Ok(Direction::SideToMainSignatures),
+        other => Err(format!(
+            "unknown direction {:?}. expected `main-to-side`, `side-to-main-sign` or `side-to-main-submit`",
+            other
+        ).into()),
+    }
+}
+
+/// result of relaying a single log
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RelayOutcome {
+    pub log_index: Option<u64>,
+    /// `None` if the relay had already happened
+    pub target_tx_hash: Option<H256>,
+}
+
+/// the logs of transaction `tx_hash` the relay of `direction` acts on
+fn relevant_logs(
+    direction: Direction,
+    tx_hash: H256,
+    logs: &[Log],
+    main_contract_address: Address,
+    side_contract_address: Address,
+    authority_address: Address,
+) -> Result<Vec<Log>, error::Error> {
+    let (contract_address, topic) = match direction {
+        Direction::MainToSide => (
+            main_contract_address,
+            helpers::event_topic(&contracts::main::events::relay_message::filter()),
+        ),
+        Direction::SideToMainSign => (
+            side_contract_address,
+            helpers::event_topic(&contracts::side::events::relay_message::filter()),
+        ),
+        Direction::SideToMainSignatures => (
+            side_contract_address,
+            helpers::event_topic(&contracts::side::events::signed_message::filter(authority_address)),
+        ),
+    };
+
+    let logs = logs
+        .iter()
+        .filter(|log| log.address == contract_address && log.topics.first() == Some(&topic))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if logs.is_empty() {
+        bail!(
+            "transaction {:?} contains no logs of bridge contract {:?} to relay {}",
+            tx_hash,
+            contract_address,
+            direction
+        );
+    }
+
+    if direction == Direction::SideToMainSignatures {
+        for log in &logs {
+            let parsed = helpers::parse_log(contracts::side::events::signed_message::parse_log, log)?;
+            if parsed.authority_responsible_for_relay != authority_address {
+                bail!(
+                    "the message in transaction {:?} is submitted to main by authority {:?}, not by {:?}",
+                    tx_hash,
+                    parsed.authority_responsible_for_relay,
+                    authority_address
+                );
+            }
+        }
+    }
+
+    Ok(logs)
+}
+
+/// relays single transactions. see module docs
+pub struct ManualRelay<T> {
+    pub main: MainContract<T>,
+    pub side: SideContract<T>,
+}
+
+impl<T: Transport + 'static> ManualRelay<T>
+where
+    T::Out: 'static,
+{
+    /// `Future` that fetches the receipt of `tx_hash`, checks that it has enough confirmations
+    /// and runs the relay of `direction` once for each of its logs
+    pub fn relay(&self, direction: Direction, tx_hash: H256) -> Box<Future<Item = Vec<RelayOutcome>, Error = error::Error>> {
+        let (transport, request_timeout, confirmations) = match direction {
+            Direction::MainToSide => (
+                &self.main.transport,
+                self.main.request_timeout,
+                self.main.required_log_confirmations,
+            ),
+            Direction::SideToMainSign | Direction::SideToMainSignatures => (
+                &self.side.transport,
+                self.side.request_timeout,
+                self.side.required_log_confirmations,
+            ),
+        };
+
+        let receipt = with_timeout(susyweb::api::Sof::new(transport).transaction_receipt(tx_hash), request_timeout);
+        let head = with_timeout(susyweb::api::Sof::new(transport).block_number(), request_timeout);
+
+        let main = self.main.clone();
+        let side = self.side.clone();
+        Box::new(receipt.join(head).and_then(move |(receipt, head)| -> Result<_, error::Error> {
+            let receipt = match receipt {
+                Some(receipt) => receipt,
+                None => bail!("transaction {:?} is unknown or not mined yet", tx_hash),
+            };
+            let block = receipt
+                .block_number
+                .ok_or_else(|| format!("transaction {:?} is not mined yet", tx_hash))?
+                .as_u64();
+            let head = head.as_u64();
+            if head < block + confirmations as u64 {
+                bail!(
+                    "transaction {:?} has {} of {} required confirmations. try again later",
+                    tx_hash,
+                    (head + 1).saturating_sub(block),
+                    confirmations
+                );
+            }
+
+            let logs = relevant_logs(
+                direction,
+                tx_hash,
+                &receipt.logs,
+                main.contract_address,
+                side.contract_address,
+                main.authority_address,
+            )?;
+            Ok((logs, main, side))
+        }).and_then(move |(logs, main, side)| {
+            join_all(
+                logs.into_iter()
+                    .map(|log| {
+                        let log_index = log.log_index.map(|index| index.as_u64());
+                        type Relay = Box<Future<Item = Option<H256>, Error = error::Error>>;
+                        let relay: Result<Relay, error::Error> = match direction {
+                            Direction::MainToSide => AcceptMessageFromMain::new(&log, side.clone(), main.clone())
+                                .map(|relay| Box::new(relay) as Relay),
+                            Direction::SideToMainSign => {
+                                SideToMainSign::new(&log, side.clone()).map(|relay| Box::new(relay) as Relay)
+                            }
+                            Direction::SideToMainSignatures => SideToMainSignatures::new(&log, main.clone(), side.clone())
+                                .map(|relay| Box::new(relay) as Relay),
+                        };
+                        // a malformed log fails the manual relay instead of being quarantined
+                        future::result(relay).flatten().map(move |target_tx_hash| RelayOutcome {
+                            log_index,
+                            target_tx_hash,
+                        })
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sofabi;
+    use susyweb::types::Bytes;
+
+    fn signed_message_log(contract_address: Address, authority: Address) -> Log {
+        let topic = contracts::side::events::signed_message::filter(authority);
+        let log = contracts::side::logs::SignedMessage {
+            authority_responsible_for_relay: authority,
+            message_hash: 3.into(),
+        };
+
+        Log {
+            address: contract_address,
+            topics: vec![topic.topic0[0], topic.topic1[0]],
+            data: Bytes(sofabi::encode(&[sofabi::Token::FixedBytes(log.message_hash.to_vec())])),
+            transaction_hash: Some(1.into()),
+            block_hash: None,
+            block_number: None,
+            transaction_index: None,
+            log_index: None,
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_direction() {
+        assert_eq!(parse_direction("main-to-side").unwrap(), Direction::MainToSide);
+        assert_eq!(parse_direction("side-to-main-submit").unwrap(), Direction::SideToMainSignatures);
+        assert!(parse_direction("main_to_side").is_err());
+    }
+
+    #[test]
+    fn test_relevant_logs() {
+        let main_address: Address = "0000000000000000000000000000000000000001".into();
+        let side_address: Address = "0000000000000000000000000000000000000002".into();
+        let authority: Address = "0000000000000000000000000000000000000003".into();
+        let other_authority: Address = "0000000000000000000000000000000000000004".into();
+        let tx_hash: H256 = 1.into();
+
+        let logs = vec![
+            signed_message_log(main_address, authority),
+            signed_message_log(side_address, authority),
+        ];
+        let relevant = relevant_logs(
+            Direction::SideToMainSignatures,
+            tx_hash,
+            &logs,
+            main_address,
+            side_address,
+            authority,
+        ).unwrap();
+        assert_eq!(relevant.len(), 1);
+        assert_eq!(relevant[0].address, side_address);
+
+        assert!(relevant_logs(Direction::MainToSide, tx_hash, &logs, main_address, side_address, authority).is_err());
+
+        let other_logs = vec![signed_message_log(side_address, other_authority)];
+        assert!(relevant_logs(
+            Direction::SideToMainSignatures,
+            tx_hash,
+            &other_logs,
+            main_address,
+            side_address,
+            authority
+        ).is_err());
+    }
+}
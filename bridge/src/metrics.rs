@@ -0,0 +1,123 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! metrics in the prometheus text exposition format.
+//!
+//! served on `/metrics` next to the health endpoints.
+
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+impl MetricKind {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+        }
+    }
+}
+
+/// a single value of a metric with its labels
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub labels: Vec<(&'static str, String)>,
+    pub value: u64,
+}
+
+/// all samples of a single metric
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricFamily {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: MetricKind,
+    pub samples: Vec<Sample>,
+}
+
+impl MetricFamily {
+    pub fn new(name: &'static str, help: &'static str, kind: MetricKind) -> Self {
+        Self {
+            name,
+            help,
+            kind,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn sample(mut self, labels: Vec<(&'static str, String)>, value: u64) -> Self {
+        self.samples.push(Sample { labels, value });
+        self
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// renders `families` in the prometheus text exposition format
+pub fn render(families: &[MetricFamily]) -> String {
+    let mut out = String::new();
+    for family in families {
+        writeln!(out, "# HELP {} {}", family.name, family.help).expect("writing to a String can't fail; qed");
+        writeln!(out, "# TYPE {} {}", family.name, family.kind.as_str()).expect("writing to a String can't fail; qed");
+        for sample in &family.samples {
+            out.push_str(family.name);
+            if !sample.labels.is_empty() {
+                let labels = sample
+                    .labels
+                    .iter()
+                    .map(|&(key, ref value)| format!("{}=\"{}\"", key, escape_label_value(value)))
+                    .collect::<Vec<_>>();
+                write!(out, "{{{}}}", labels.join(",")).expect("writing to a String can't fail; qed");
+            }
+            writeln!(out, " {}", sample.value).expect("writing to a String can't fail; qed");
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let families = vec![
+            MetricFamily::new("bridge_messages_pending", "messages in flight", MetricKind::Gauge)
+                .sample(vec![("direction", "main_to_side".into())], 2)
+                .sample(vec![("direction", "side_to_main".into())], 0),
+            MetricFamily::new("bridge_up", "always 1", MetricKind::Counter).sample(vec![], 1),
+        ];
+
+        assert_eq!(
+            render(&families),
+            "# HELP bridge_messages_pending messages in flight\n\
+             # TYPE bridge_messages_pending gauge\n\
+             bridge_messages_pending{direction=\"main_to_side\"} 2\n\
+             bridge_messages_pending{direction=\"side_to_main\"} 0\n\
+             # HELP bridge_up always 1\n\
+             # TYPE bridge_up counter\n\
+             bridge_up 1\n"
+        );
+    }
+}
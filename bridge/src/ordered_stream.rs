@@ -14,6 +14,7 @@
 // You should have received a copy of the GNU General Public License
 // along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
 use futures::{Async, Future, Poll, Stream};
+use std::collections::VecDeque;
 
 /// `OrderedStream` is a `Stream` that yields the
 /// values of a list of `Future`s in a predefined order which is
@@ -61,26 +62,37 @@ use futures::{Async, Future, Poll, Stream};
 /// TODO
 
 pub struct OrderedStream<O, F: Future> {
-    entries: Vec<Entry<O, F>>,
+    /// sorted by `order`. entries with the same `order` in the order they were inserted
+    entries: VecDeque<Entry<O, F>>,
 }
 
 impl<O: Ord, F: Future> OrderedStream<O, F> {
     /// returns a new empty `OrderedStream`
     pub fn new() -> Self {
         Self {
-            entries: Vec::new(),
+            entries: VecDeque::new(),
         }
     }
 
     /// insert a `future` into this that should be yielded
     /// when it is completed and there are currently no
     /// futures inside the stream that have a smaller `order`.
+    ///
+    /// `O(1)` if `order` isn't lower than the orders inserted before.
     pub fn insert(&mut self, order: O, future: F) {
-        self.entries.push(Entry {
-            order,
-            future,
-            item_if_ready: None,
-        });
+        let index = self
+            .entries
+            .iter()
+            .rposition(|entry| entry.order <= order)
+            .map_or(0, |index| index + 1);
+        self.entries.insert(
+            index,
+            Entry {
+                order,
+                future,
+                item_if_ready: None,
+            },
+        );
     }
 
     /// returns the count of futures that have completed but can't be
@@ -99,68 +111,48 @@ impl<O: Ord, F: Future> OrderedStream<O, F> {
             .filter(|x| x.item_if_ready.is_none())
             .count()
     }
+
+    /// returns the lowest `order` of the futures that have not been yielded yet
+    pub fn min_order(&self) -> Option<&O> {
+        self.entries.front().map(|entry| &entry.order)
+    }
+
+    /// returns `true` if all inserted futures have been yielded
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
 }
 
-impl<O: Ord + Clone, F: Future> Stream for OrderedStream<O, F> {
+impl<O: Ord, F: Future> Stream for OrderedStream<O, F> {
     type Item = (O, F::Item);
     type Error = F::Error;
 
     /// `O(n)` where `n = self.entries.len()`.
     /// there's not much that can be done to improve this `O` since `poll` always must `poll` all `self.entries`.
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        // minimum of orders of entries which are not ready
-        let mut maybe_min_not_ready: Option<O> = None;
-        // the index (in entries) of the completed order with the lowest order
-        let mut maybe_min_ready: Option<(O, usize)> = None;
-
-        for (index, entry) in self.entries.iter_mut().enumerate() {
+        for entry in self.entries.iter_mut() {
             // poll futures which are not ready without every polling any future twice.
-            if !entry.item_if_ready.is_some() {
+            if entry.item_if_ready.is_none() {
                 if let Async::Ready(item) = entry.future.poll()? {
                     entry.item_if_ready = Some(item);
-                } else {
-                    maybe_min_not_ready = maybe_min_not_ready
-                        .map(|x| x.min(entry.order.clone()))
-                        .or(Some(entry.order.clone()));
                 }
             }
-
-            if entry.item_if_ready.is_some() // item must be ready
-                // we must initialize `maybe_min_ready`
-                && (maybe_min_ready.is_none()
-                // or entry is the new min
-                || entry.order < maybe_min_ready.clone().expect("check in prev line. q.e.d.").0)
-            {
-                maybe_min_ready = Some((entry.order.clone(), index));
-            }
         }
 
-        if maybe_min_ready.is_none() {
-            // there is no min ready -> none are ready
+        // only the entry with the lowest order can be yielded.
+        // it's the first one since `self.entries` is sorted
+        if self.entries.front().map_or(true, |entry| entry.item_if_ready.is_none()) {
             return Ok(Async::NotReady);
         }
 
-        let (min_ready_order, min_ready_index) =
-            maybe_min_ready.expect("check and early return if none above. q.e.d.");
-
-        if let Some(min_not_ready_order) = maybe_min_not_ready {
-            // some are ready but there's unready ones with lower order
-            if min_not_ready_order < min_ready_order {
-                // there are futures which are not ready
-                // but must be yielded before the ones that are ready
-                // since their `order` is lower
-                return Ok(Async::NotReady);
-            }
-        }
-
         // this is O(1)
-        let entry_to_yield = self.entries.swap_remove(min_ready_index);
+        let entry_to_yield = self.entries.pop_front().expect("checked above. q.e.d.");
 
         Ok(Async::Ready(Some((
             entry_to_yield.order,
             entry_to_yield
                 .item_if_ready
-                .expect("`min_ready_index` points to index of entry with result. q.e.d."),
+                .expect("checked above. q.e.d."),
         ))))
     }
 }
@@ -225,6 +217,7 @@ mod tests {
 
         assert_eq!(ordered_stream.ready_count(), 0);
         assert_eq!(ordered_stream.not_ready_count(), 1);
+        assert!(!ordered_stream.is_empty());
 
         let mut event_loop = tokio_core::reactor::Core::new().unwrap();
 
@@ -239,6 +232,7 @@ mod tests {
 
         assert_eq!(ordered_stream.ready_count(), 0);
         assert_eq!(ordered_stream.not_ready_count(), 0);
+        assert!(ordered_stream.is_empty());
     }
 
     #[test]
@@ -0,0 +1,408 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! participation of the authorities in relaying messages.
+//!
+//! for every message that arrived the authorities that took part are looked up:
+//!
+//! - `side` to `main`: the signers of the signatures collected on `side` (`Side.signature` plus ecrecover)
+//! - `main` to `side`: the authorities that accepted the message on `side`
+//!
+//! only the first `required_signatures` signatures are collected on `side`.
+//! an authority that is active can therefore still miss `side` to `main` messages.
+//! `min_participation` should be set accordingly.
+
+use config::ParticipationConfig;
+use error;
+use futures::future::{self, join_all};
+use futures::Future;
+use main_contract::MainContract;
+use metrics::{MetricFamily, MetricKind};
+use side_contract::SideContract;
+use signature::Signature;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, VecDeque};
+use std::rc::Rc;
+use tokio_core::reactor::Handle;
+use tracker::{MessageDirection, MessageTracker, TrackedMessage};
+use susyweb::types::{Address, H256};
+use susyweb::Transport;
+
+/// the authorities that took part in a single message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Participation {
+    pub direction: MessageDirection,
+    pub message_id: H256,
+    pub participants: Vec<Address>,
+}
+
+/// participation rate of every authority over the most recent `window` messages
+pub struct ParticipationMonitor {
+    authorities: Vec<Address>,
+    required_signatures: u32,
+    window: usize,
+    min_participation: u32,
+    messages: VecDeque<Participation>,
+    /// authorities currently considered inactive
+    inactive: BTreeSet<Address>,
+    quorum_at_risk: bool,
+}
+
+/// below this many messages in the window rates are too noisy to warn about
+const MIN_MESSAGES: usize = 5;
+
+impl ParticipationMonitor {
+    pub fn new(authorities: Vec<Address>, required_signatures: u32, config: &ParticipationConfig) -> Self {
+        Self {
+            authorities,
+            required_signatures,
+            window: config.window,
+            min_participation: config.min_participation,
+            messages: VecDeque::new(),
+            inactive: BTreeSet::new(),
+            quorum_at_risk: false,
+        }
+    }
+
+    pub fn authorities(&self) -> &[Address] {
+        &self.authorities
+    }
+
+    /// replaces the authorities and required signatures once they changed on chain
+    pub fn set_authorities(&mut self, authorities: Vec<Address>, required_signatures: u32) {
+        self.inactive = self.inactive
+            .iter()
+            .filter(|authority| authorities.contains(authority))
+            .cloned()
+            .collect();
+        self.authorities = authorities;
+        self.required_signatures = required_signatures;
+        self.evaluate();
+    }
+
+    /// number of messages in the window `authority` took part in
+    pub fn participated(&self, authority: &Address) -> usize {
+        self.messages
+            .iter()
+            .filter(|message| message.participants.contains(authority))
+            .count()
+    }
+
+    /// percent of the messages in the window `authority` took part in.
+    /// `None` while the window is empty
+    pub fn rate(&self, authority: &Address) -> Option<u32> {
+        if self.messages.is_empty() {
+            return None;
+        }
+        Some((self.participated(authority) * 100 / self.messages.len()) as u32)
+    }
+
+    /// authorities whose rate is at least `min_participation`
+    pub fn active_authorities(&self) -> Vec<Address> {
+        self.authorities
+            .iter()
+            .filter(|authority| !self.inactive.contains(authority))
+            .cloned()
+            .collect()
+    }
+
+    pub fn record(&mut self, participation: Participation) {
+        for participant in &participation.participants {
+            if !self.authorities.contains(participant) {
+                warn!(
+                    "{} message {:?} was signed by {:?} which is not a known authority",
+                    participation.direction, participation.message_id, participant
+                );
+            }
+        }
+
+        self.messages.push_back(participation);
+        while self.messages.len() > self.window {
+            self.messages.pop_front();
+        }
+
+        self.evaluate();
+    }
+
+    /// logs authorities that stopped or resumed participating and whether quorum is at risk
+    fn evaluate(&mut self) {
+        if self.messages.len() < MIN_MESSAGES.min(self.window) {
+            return;
+        }
+
+        for authority in &self.authorities {
+            let rate = self.rate(authority).unwrap_or(0);
+            if rate < self.min_participation {
+                if self.inactive.insert(*authority) {
+                    warn!(
+                        "authority {:?} took part in only {}% of the last {} messages. it may have stopped relaying",
+                        authority,
+                        rate,
+                        self.messages.len()
+                    );
+                }
+            } else if self.inactive.remove(authority) {
+                info!(
+                    "authority {:?} is participating again ({}% of the last {} messages)",
+                    authority,
+                    rate,
+                    self.messages.len()
+                );
+            }
+        }
+
+        let active = self.authorities.len() - self.inactive.len();
+        let required = self.required_signatures as usize;
+        let quorum_at_risk = active <= required;
+        if quorum_at_risk {
+            if active < required {
+                error!(
+                    "only {} of {} authorities are participating but {} signatures are required. messages can't be relayed",
+                    active,
+                    self.authorities.len(),
+                    required
+                );
+            } else if !self.quorum_at_risk {
+                warn!(
+                    "only {} of {} authorities are participating and {} signatures are required. quorum is at risk",
+                    active,
+                    self.authorities.len(),
+                    required
+                );
+            }
+        } else if self.quorum_at_risk {
+            info!(
+                "{} of {} authorities are participating again. quorum is no longer at risk",
+                active,
+                self.authorities.len()
+            );
+        }
+        self.quorum_at_risk = quorum_at_risk;
+    }
+
+    pub fn metrics(&self) -> Vec<MetricFamily> {
+        let mut rate = MetricFamily::new(
+            "bridge_authority_participation_percent",
+            "percent of the most recent messages the authority took part in",
+            MetricKind::Gauge,
+        );
+        let mut active = MetricFamily::new(
+            "bridge_authority_active",
+            "1 if the authority is considered to be participating",
+            MetricKind::Gauge,
+        );
+        for authority in &self.authorities {
+            let labels = || vec![("authority", format!("{:?}", authority))];
+            if let Some(value) = self.rate(authority) {
+                rate = rate.sample(labels(), value as u64);
+            }
+            active = active.sample(labels(), if self.inactive.contains(authority) { 0 } else { 1 });
+        }
+
+        vec![
+            rate,
+            active,
+            MetricFamily::new(
+                "bridge_authorities_active",
+                "number of authorities considered to be participating",
+                MetricKind::Gauge,
+            ).sample(vec![], (self.authorities.len() - self.inactive.len()) as u64),
+            MetricFamily::new(
+                "bridge_required_signatures",
+                "number of signatures required to relay a message",
+                MetricKind::Gauge,
+            ).sample(vec![], self.required_signatures as u64),
+            MetricFamily::new(
+                "bridge_participation_window_messages",
+                "number of messages participation rates are computed over",
+                MetricKind::Gauge,
+            ).sample(vec![], self.messages.len() as u64),
+        ]
+    }
+}
+
+/// looks up which authorities took part in a message
+pub struct ParticipationLookup<T> {
+    pub main: MainContract<T>,
+    pub side: SideContract<T>,
+}
+
+impl<T: Transport + 'static> ParticipationLookup<T>
+where
+    T::Out: 'static,
+{
+    /// `authorities` are checked for whether they accepted a `main` to `side` message
+    pub fn lookup(&self, message: &TrackedMessage, authorities: Vec<Address>) -> Box<Future<Item = Participation, Error = error::Error>> {
+        let direction = message.direction;
+        let message_id = message.message_id;
+
+        let participants: Box<Future<Item = Vec<Address>, Error = error::Error>> = match message.message_to_main() {
+            Some(message_to_main) => {
+                let bytes = message_to_main.to_bytes();
+                Box::new(
+                    self.side
+                        .get_signatures(message_to_main.keccak256())
+                        .and_then(move |raw_signatures| {
+                            raw_signatures
+                                .iter()
+                                .map(|raw| Signature::from_bytes(raw)?.recover(&bytes))
+                                .collect::<Result<Vec<_>, _>>()
+                        })
+                        .map_err(|err| error::Error::with_chain(err, "ParticipationLookup: recovering signers failed")),
+                )
+            }
+            None => {
+                let side = self.side.clone();
+                let tx_hash = message.source_tx_hash;
+                let sender = message.sender;
+                let recipient = message.recipient;
+                Box::new(
+                    self.main
+                        .relayed_message_by_id(message_id)
+                        .and_then(move |data| {
+                            join_all(
+                                authorities
+                                    .iter()
+                                    .map(|authority| {
+                                        side.has_authority_accepted_message_from_main(
+                                            *authority,
+                                            tx_hash,
+                                            data.clone(),
+                                            sender,
+                                            recipient,
+                                        )
+                                    })
+                                    .collect::<Vec<_>>(),
+                            ).map(move |accepted| {
+                                authorities
+                                    .into_iter()
+                                    .zip(accepted)
+                                    .filter(|&(_, accepted)| accepted)
+                                    .map(|(authority, _)| authority)
+                                    .collect()
+                            })
+                        })
+                        .map_err(|err| {
+                            error::Error::with_chain(err, "ParticipationLookup: checking accepting authorities failed")
+                        }),
+                )
+            }
+        };
+
+        Box::new(participants.map(move |participants| Participation {
+            direction,
+            message_id,
+            participants,
+        }))
+    }
+}
+
+/// looks up the participation of every message that arrives in `tracker`
+/// and records it in `monitor`
+pub fn watch<T: Transport + 'static>(
+    tracker: &mut MessageTracker,
+    lookup: ParticipationLookup<T>,
+    monitor: Rc<RefCell<ParticipationMonitor>>,
+    handle: Handle,
+) where
+    T::Out: 'static,
+{
+    tracker.on_arrived(move |message| {
+        let monitor = monitor.clone();
+        let message_id = message.message_id;
+        let authorities = monitor.borrow().authorities().to_vec();
+        handle.spawn(
+            lookup
+                .lookup(message, authorities)
+                .map(move |participation| monitor.borrow_mut().record(participation))
+                .or_else(move |err| {
+                    warn!("failed to look up participation in message {:?}: {}", message_id, err);
+                    future::ok(())
+                }),
+        );
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authorities() -> Vec<Address> {
+        vec![1.into(), 2.into(), 3.into()]
+    }
+
+    fn participation(participants: Vec<Address>) -> Participation {
+        Participation {
+            direction: MessageDirection::SideToMain,
+            message_id: 0.into(),
+            participants,
+        }
+    }
+
+    #[test]
+    fn test_participation_rolling_window() {
+        let config = ParticipationConfig {
+            window: 5,
+            min_participation: 50,
+        };
+        let mut monitor = ParticipationMonitor::new(authorities(), 2, &config);
+        assert_eq!(monitor.rate(&1.into()), None);
+
+        for _ in 0..5 {
+            monitor.record(participation(vec![1.into(), 2.into(), 3.into()]));
+        }
+        assert_eq!(monitor.rate(&3.into()), Some(100));
+        assert_eq!(monitor.active_authorities(), authorities());
+        assert!(!monitor.quorum_at_risk);
+
+        // authority 3 stops participating
+        for _ in 0..3 {
+            monitor.record(participation(vec![1.into(), 2.into()]));
+        }
+        assert_eq!(monitor.messages.len(), 5);
+        assert_eq!(monitor.rate(&3.into()), Some(40));
+        assert_eq!(monitor.active_authorities(), vec![1.into(), 2.into()]);
+        assert!(monitor.quorum_at_risk);
+
+        // and comes back
+        for _ in 0..3 {
+            monitor.record(participation(vec![1.into(), 2.into(), 3.into()]));
+        }
+        assert_eq!(monitor.rate(&3.into()), Some(60));
+        assert_eq!(monitor.active_authorities(), authorities());
+        assert!(!monitor.quorum_at_risk);
+    }
+
+    #[test]
+    fn test_set_authorities() {
+        let config = ParticipationConfig {
+            window: 5,
+            min_participation: 50,
+        };
+        let mut monitor = ParticipationMonitor::new(authorities(), 2, &config);
+        for _ in 0..5 {
+            monitor.record(participation(vec![1.into(), 2.into()]));
+        }
+        assert_eq!(monitor.active_authorities(), vec![1.into(), 2.into()]);
+        assert!(monitor.quorum_at_risk);
+
+        // authority 3 was removed from the contracts and one signature less is required
+        monitor.set_authorities(vec![1.into(), 2.into()], 1);
+        assert_eq!(monitor.authorities().to_vec(), vec![1.into(), 2.into()]);
+        assert!(monitor.inactive.is_empty());
+        assert!(!monitor.quorum_at_risk);
+    }
+}
@@ -0,0 +1,110 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! pausing of `RelayStream`s from outside the `Bridge`
+
+use futures::task::{self, Task};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::rc::Rc;
+
+/// why a `RelayStream` is paused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseReason {
+    /// the authority can't pay for the transactions of the relay
+    LowBalance,
+    /// the process is shutting down. never resumed
+    Shutdown,
+    /// paused through the admin API
+    Operator,
+}
+
+#[derive(Default)]
+struct Inner {
+    reasons: BTreeSet<PauseReason>,
+    /// task of the paused `RelayStream` that must be notified on resume
+    task: Option<Task>,
+}
+
+/// a switch that pauses a `RelayStream`.
+/// a paused `RelayStream` doesn't fetch new logs but keeps polling the relays
+/// that are already in flight. checkpoints therefore never move past
+/// logs that haven't been relayed.
+/// the stream is paused as long as there is at least one `PauseReason`.
+/// `clone`d versions share the same state.
+#[derive(Clone, Default)]
+pub struct PauseHandle {
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl PauseHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// returns `true` if `reason` wasn't set before
+    pub fn pause(&self, reason: PauseReason) -> bool {
+        self.inner.borrow_mut().reasons.insert(reason)
+    }
+
+    /// returns `true` if `reason` was set before
+    pub fn resume(&self, reason: PauseReason) -> bool {
+        let mut inner = self.inner.borrow_mut();
+        let removed = inner.reasons.remove(&reason);
+        if removed && inner.reasons.is_empty() {
+            if let Some(task) = inner.task.take() {
+                task.notify();
+            }
+        }
+        removed
+    }
+
+    pub fn is_paused(&self) -> bool {
+        !self.inner.borrow().reasons.is_empty()
+    }
+
+    pub fn reasons(&self) -> Vec<PauseReason> {
+        self.inner.borrow().reasons.iter().cloned().collect()
+    }
+
+    /// to be called from within `poll` of the paused stream.
+    /// the current task gets notified once the stream is resumed.
+    pub fn park(&self) {
+        self.inner.borrow_mut().task = Some(task::current());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pause_handle_clones_share_state() {
+        let handle = PauseHandle::new();
+        let clone = handle.clone();
+        assert!(!clone.is_paused());
+
+        assert!(handle.pause(PauseReason::LowBalance));
+        assert!(!handle.pause(PauseReason::LowBalance));
+        assert!(clone.is_paused());
+        assert_eq!(clone.reasons(), vec![PauseReason::LowBalance]);
+
+        assert!(clone.resume(PauseReason::LowBalance));
+        assert!(!clone.resume(PauseReason::LowBalance));
+        assert!(!handle.is_paused());
+    }
+}
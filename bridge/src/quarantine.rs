@@ -0,0 +1,280 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! logs a relay couldn't be created for.
+//!
+//! `RelayStream` quarantines such logs instead of failing and keeps processing
+//! the rest of the block range. the checkpoint moves past quarantined logs,
+//! so once `persist_to` was called they are appended to a file before that.
+//! a log that can't be persisted fails the `RelayStream` instead.
+//! they can be inspected through the admin API (`bridge_quarantinedLogs`)
+//! and retried with `bridge_retryQuarantined` once the cause is fixed.
+
+use error::{self, ResultExt};
+use relay_log::Direction;
+use serde_json;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use susyweb::types::{Address, Bytes, Log, H256};
+
+/// the oldest quarantined logs are dropped from memory beyond that.
+/// the quarantine file keeps all of them
+const MAX_QUARANTINED_LOGS: usize = 1000;
+
+/// a log and why no relay could be created for it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuarantinedLog {
+    pub direction: Direction,
+    /// the block range the log was fetched in
+    pub from_block: u64,
+    pub to_block: u64,
+    pub address: Address,
+    pub topics: Vec<H256>,
+    pub data: Bytes,
+    pub transaction_hash: Option<H256>,
+    pub block_number: Option<u64>,
+    pub log_index: Option<u64>,
+    /// the error and all its causes
+    pub error: Vec<String>,
+}
+
+impl QuarantinedLog {
+    pub fn new(direction: Direction, from_block: u64, to_block: u64, log: &Log, error: &error::Error) -> Self {
+        QuarantinedLog {
+            direction,
+            from_block,
+            to_block,
+            address: log.address,
+            topics: log.topics.clone(),
+            data: log.data.clone(),
+            transaction_hash: log.transaction_hash,
+            block_number: log.block_number.map(|block| block.as_u64()),
+            log_index: log.log_index.map(|index| index.as_u64()),
+            error: error.iter().map(|err| err.to_string()).collect(),
+        }
+    }
+}
+
+thread_local! {
+    /// logs quarantined on the event loop of this thread in the order they were quarantined
+    static QUARANTINE: RefCell<VecDeque<QuarantinedLog>> = RefCell::new(VecDeque::new());
+    /// file quarantined logs are appended to. see `persist_to`
+    static QUARANTINE_FILE: RefCell<Option<PathBuf>> = RefCell::new(None);
+}
+
+/// the quarantine file of the database at `database`
+pub fn file_path<P: AsRef<Path>>(database: P) -> PathBuf {
+    let mut path = database.as_ref().to_path_buf().into_os_string();
+    path.push(".quarantine");
+    PathBuf::from(path)
+}
+
+/// appends logs quarantined on this thread to `path`, one json object per line,
+/// and loads the logs quarantined there before a restart
+pub fn persist_to<P: Into<PathBuf>>(path: P) -> Result<(), error::Error> {
+    let path = path.into();
+    let logs = read_file(&path)?;
+    QUARANTINE.with(|quarantine| {
+        let mut quarantine = quarantine.borrow_mut();
+        quarantine.clear();
+        quarantine.extend(logs);
+        while quarantine.len() > MAX_QUARANTINED_LOGS {
+            quarantine.pop_front();
+        }
+    });
+    QUARANTINE_FILE.with(|file| *file.borrow_mut() = Some(path));
+    Ok(())
+}
+
+/// the logs in the quarantine file at `path`. none if it doesn't exist yet
+fn read_file(path: &Path) -> Result<Vec<QuarantinedLog>, error::Error> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).chain_err(|| format!("Cannot open quarantine file {:?}", path)),
+    };
+    let mut logs = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.chain_err(|| format!("Cannot read quarantine file {:?}", path))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let log = serde_json::from_str(&line)
+            .chain_err(|| format!("line {} of quarantine file {:?} is invalid", index + 1, path))?;
+        logs.push(log);
+    }
+    Ok(logs)
+}
+
+/// appends `log` to the quarantine file if there is one
+fn append_to_file(log: &QuarantinedLog) -> Result<(), error::Error> {
+    let path = match QUARANTINE_FILE.with(|file| file.borrow().clone()) {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let mut line = serde_json::to_string(log).chain_err(|| "Cannot serialize quarantined log")?;
+    line.push('\n');
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&path)
+        .chain_err(|| format!("Cannot open quarantine file {:?}", path))?;
+    file.write_all(line.as_bytes())
+        .and_then(|_| file.sync_data())
+        .chain_err(|| format!("Cannot write quarantine file {:?}", path))?;
+    Ok(())
+}
+
+/// replaces the quarantine file at `path` with `logs`
+fn write_file(path: &Path, logs: &[QuarantinedLog]) -> Result<(), error::Error> {
+    let mut content = String::new();
+    for log in logs {
+        content.push_str(&serde_json::to_string(log).chain_err(|| "Cannot serialize quarantined log")?);
+        content.push('\n');
+    }
+    let mut temporary = path.to_path_buf().into_os_string();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    File::create(&temporary)
+        .and_then(|mut file| file.write_all(content.as_bytes()).and_then(|_| file.sync_data()))
+        .and_then(|_| fs::rename(&temporary, path))
+        .chain_err(|| format!("Cannot write quarantine file {:?}", path))
+}
+
+/// logs, persists and records `log`. fails if it can't be persisted
+pub fn quarantine(log: QuarantinedLog) -> Result<(), error::Error> {
+    error!(
+        "quarantined {} log {:?} of transaction {:?} in blocks {}..{}: {}",
+        log.direction,
+        log.log_index,
+        log.transaction_hash,
+        log.from_block,
+        log.to_block,
+        log.error.join(": ")
+    );
+    append_to_file(&log)?;
+    QUARANTINE.with(|quarantine| {
+        let mut quarantine = quarantine.borrow_mut();
+        quarantine.push_back(log);
+        while quarantine.len() > MAX_QUARANTINED_LOGS {
+            quarantine.pop_front();
+        }
+    });
+    Ok(())
+}
+
+/// logs quarantined on the event loop of the current thread, oldest first
+pub fn quarantined_logs() -> Vec<QuarantinedLog> {
+    QUARANTINE.with(|quarantine| quarantine.borrow().iter().cloned().collect())
+}
+
+/// removes the logs of transaction `transaction_hash` quarantined for `direction`
+/// after they were relayed. returns how many were removed from memory
+pub fn release(direction: Direction, transaction_hash: H256) -> Result<usize, error::Error> {
+    let of_transaction =
+        |log: &QuarantinedLog| log.direction == direction && log.transaction_hash == Some(transaction_hash);
+    if let Some(path) = QUARANTINE_FILE.with(|file| file.borrow().clone()) {
+        let logs = read_file(&path)?;
+        let kept = logs.iter().filter(|log| !of_transaction(log)).cloned().collect::<Vec<_>>();
+        if kept.len() != logs.len() {
+            write_file(&path, &kept)?;
+        }
+    }
+    Ok(QUARANTINE.with(|quarantine| {
+        let mut quarantine = quarantine.borrow_mut();
+        let before = quarantine.len();
+        quarantine.retain(|log| !of_transaction(log));
+        before - quarantine.len()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn log() -> Log {
+        Log {
+            address: 1.into(),
+            topics: vec![2.into()],
+            data: Bytes(vec![0x12]),
+            transaction_hash: Some(3.into()),
+            block_hash: None,
+            block_number: Some(10.into()),
+            transaction_index: None,
+            log_index: Some(4.into()),
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        }
+    }
+
+    #[test]
+    fn test_quarantine() {
+        let error = error::Error::with_chain(error::Error::from("invalid data"), "parsing log failed");
+        quarantine(QuarantinedLog::new(Direction::MainToSide, 5, 10, &log(), &error)).unwrap();
+
+        let quarantined = quarantined_logs();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].log_index, Some(4));
+        assert_eq!(quarantined[0].error, vec!["parsing log failed".to_string(), "invalid data".into()]);
+    }
+
+    #[test]
+    fn test_quarantine_survives_restart() {
+        let dir = TempDir::new("quarantine").unwrap();
+        let path = file_path(dir.path().join("bridge.db"));
+        assert_eq!(path, dir.path().join("bridge.db.quarantine"));
+
+        persist_to(path.clone()).unwrap();
+        assert!(quarantined_logs().is_empty());
+        let error = error::Error::from("invalid data");
+        quarantine(QuarantinedLog::new(Direction::SideToMainSign, 5, 10, &log(), &error)).unwrap();
+        quarantine(QuarantinedLog::new(Direction::MainToSide, 11, 12, &log(), &error)).unwrap();
+        let before = quarantined_logs();
+        assert_eq!(before.len(), 2);
+
+        // a restart starts with an empty quarantine
+        QUARANTINE.with(|quarantine| quarantine.borrow_mut().clear());
+        persist_to(path).unwrap();
+        assert_eq!(quarantined_logs(), before);
+    }
+
+    #[test]
+    fn test_release() {
+        let dir = TempDir::new("quarantine").unwrap();
+        let path = file_path(dir.path().join("bridge.db"));
+        persist_to(path.clone()).unwrap();
+
+        let error = error::Error::from("invalid data");
+        let mut other = log();
+        other.transaction_hash = Some(5.into());
+        quarantine(QuarantinedLog::new(Direction::MainToSide, 5, 10, &log(), &error)).unwrap();
+        quarantine(QuarantinedLog::new(Direction::MainToSide, 5, 10, &other, &error)).unwrap();
+        quarantine(QuarantinedLog::new(Direction::SideToMainSign, 5, 10, &log(), &error)).unwrap();
+
+        assert_eq!(release(Direction::MainToSide, 3.into()).unwrap(), 1);
+        assert_eq!(release(Direction::MainToSide, 3.into()).unwrap(), 0);
+        let kept = quarantined_logs();
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0].transaction_hash, Some(5.into()));
+        assert_eq!(kept[1].direction, Direction::SideToMainSign);
+        assert_eq!(read_file(&path).unwrap(), kept);
+    }
+}
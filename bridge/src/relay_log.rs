@@ -0,0 +1,314 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! structured log lines of relay futures.
+//!
+//! every line logged by a relay future carries the same fields
+//! (direction, source tx hash, message id, step, chain, target tx hash, error chain)
+//! so a single message can be followed from start to finish.
+//! all lines are logged with target `RELAY_LOG_TARGET`.
+//! if `set_json_output(true)` was called the message of each line is a JSON object
+//! containing those fields, otherwise it's a human readable line.
+//!
+//! the latest step of every relay in flight is kept so it can be listed
+//! through `in_flight_relays`.
+
+use error;
+use log::Level;
+use serde_json;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use susyweb::types::H256;
+
+/// `log` target of all relay log lines
+pub const RELAY_LOG_TARGET: &str = "bridge::relay";
+
+static JSON_OUTPUT: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// make relay log lines JSON objects instead of human readable lines
+pub fn set_json_output(enabled: bool) {
+    JSON_OUTPUT.store(enabled, Ordering::SeqCst);
+}
+
+/// the latest step of a relay that hasn't completed yet
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InFlightRelay {
+    pub direction: Direction,
+    pub source_tx_hash: H256,
+    pub message_id: Option<H256>,
+    pub step: String,
+    pub chain: Chain,
+    pub message: String,
+}
+
+thread_local! {
+    static NEXT_RELAY_ID: Cell<u64> = Cell::new(0);
+    /// relays in flight on the event loop of this thread by the id of their `RelayContext`
+    static IN_FLIGHT: RefCell<BTreeMap<u64, InFlightRelay>> = RefCell::new(BTreeMap::new());
+}
+
+/// relays in flight on the event loop of the current thread in the order they started
+pub fn in_flight_relays() -> Vec<InFlightRelay> {
+    IN_FLIGHT.with(|in_flight| in_flight.borrow().values().cloned().collect())
+}
+
+/// the three relays a bridge node is responsible for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    MainToSide,
+    SideToMainSign,
+    SideToMainSignatures,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 3] = [Direction::MainToSide, Direction::SideToMainSign, Direction::SideToMainSignatures];
+
+    /// chain whose logs trigger the relay
+    pub fn source_chain(&self) -> Chain {
+        match *self {
+            Direction::MainToSide => Chain::Main,
+            Direction::SideToMainSign | Direction::SideToMainSignatures => Chain::Side,
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Direction::MainToSide => "main_to_side",
+            Direction::SideToMainSign => "side_to_main_sign",
+            Direction::SideToMainSignatures => "side_to_main_signatures",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Chain {
+    Main,
+    Side,
+}
+
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Chain::Main => "main",
+            Chain::Side => "side",
+        })
+    }
+}
+
+/// a single relay log line
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RelayLogLine<'a> {
+    pub direction: Direction,
+    pub source_tx_hash: H256,
+    pub message_id: Option<H256>,
+    pub step: &'a str,
+    pub chain: Chain,
+    pub target_tx_hash: Option<H256>,
+    /// error followed by its causes
+    pub error: Option<Vec<String>>,
+    pub message: &'a str,
+}
+
+impl<'a> RelayLogLine<'a> {
+    fn fmt_text(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {:?} - step {} ({}) - {}",
+            self.direction, self.source_tx_hash, self.step, self.chain, self.message
+        )?;
+        if let Some(ref message_id) = self.message_id {
+            write!(f, " message_id={:?}", message_id)?;
+        }
+        if let Some(ref target_tx_hash) = self.target_tx_hash {
+            write!(f, " target_tx_hash={:?}", target_tx_hash)?;
+        }
+        if let Some(ref error) = self.error {
+            write!(f, " error={:?}", error.join(": "))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Display for RelayLogLine<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if JSON_OUTPUT.load(Ordering::SeqCst) {
+            let json = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+            f.write_str(&json)
+        } else {
+            self.fmt_text(f)
+        }
+    }
+}
+
+/// fields shared by all log lines of a single relay.
+/// the relay is listed by `in_flight_relays` from its first step until
+/// it is done, failed or dropped
+#[derive(Debug, PartialEq)]
+pub struct RelayContext {
+    pub direction: Direction,
+    pub source_tx_hash: H256,
+    /// not known upfront for every direction
+    pub message_id: Option<H256>,
+    id: u64,
+}
+
+impl RelayContext {
+    pub fn new(direction: Direction, source_tx_hash: H256) -> Self {
+        let id = NEXT_RELAY_ID.with(|next| {
+            let id = next.get();
+            next.set(id + 1);
+            id
+        });
+        Self {
+            direction,
+            source_tx_hash,
+            message_id: None,
+            id,
+        }
+    }
+
+    pub fn line<'a>(&self, step: &'a str, chain: Chain, message: &'a str) -> RelayLogLine<'a> {
+        RelayLogLine {
+            direction: self.direction,
+            source_tx_hash: self.source_tx_hash,
+            message_id: self.message_id,
+            step,
+            chain,
+            target_tx_hash: None,
+            error: None,
+            message,
+        }
+    }
+
+    /// the relay entered `step` which talks to `chain`
+    pub fn step(&self, step: &str, chain: Chain, message: &str) {
+        log!(target: RELAY_LOG_TARGET, Level::Info, "{}", self.line(step, chain, message));
+        let relay = InFlightRelay {
+            direction: self.direction,
+            source_tx_hash: self.source_tx_hash,
+            message_id: self.message_id,
+            step: step.to_string(),
+            chain,
+            message: message.to_string(),
+        };
+        IN_FLIGHT.with(|in_flight| {
+            in_flight.borrow_mut().insert(self.id, relay);
+        });
+    }
+
+    /// the relay completed. `target_tx_hash` is `None` if nothing had to be done
+    pub fn done(&self, chain: Chain, target_tx_hash: Option<H256>, message: &str) {
+        let mut line = self.line("done", chain, message);
+        line.target_tx_hash = target_tx_hash;
+        log!(target: RELAY_LOG_TARGET, Level::Info, "{}", line);
+        self.remove_in_flight();
+    }
+
+    /// the relay failed in `step`
+    pub fn failed(&self, step: &str, chain: Chain, err: &error::Error) {
+        let mut line = self.line(step, chain, "failed");
+        line.error = Some(err.iter().map(|e| e.to_string()).collect());
+        log!(target: RELAY_LOG_TARGET, Level::Error, "{}", line);
+        self.remove_in_flight();
+    }
+
+    fn remove_in_flight(&self) {
+        // the registry is already gone if the relay is dropped while the thread exits
+        let _ = IN_FLIGHT.try_with(|in_flight| {
+            in_flight.borrow_mut().remove(&self.id);
+        });
+    }
+}
+
+impl Drop for RelayContext {
+    fn drop(&mut self) {
+        self.remove_in_flight();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relay_log_line_text() {
+        let mut context = RelayContext::new(
+            Direction::MainToSide,
+            "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
+        );
+        context.message_id =
+            Some("0x1db8f385535c0d178b8f40016048f3a3cffee8f94e68978ea4b277f57b638f0b".into());
+        let mut line = context.line("3/3", Chain::Side, "accepting the message");
+        line.error = Some(vec!["outer".into(), "inner".into()]);
+
+        let text = format!("{}", DisplayText(&line));
+        assert!(text.starts_with("main_to_side 0x884e"));
+        assert!(text.contains("step 3/3 (side) - accepting the message"));
+        assert!(text.contains("message_id=0x1db8"));
+        assert!(text.ends_with("error=\"outer: inner\""));
+    }
+
+    #[test]
+    fn test_relay_log_line_json() {
+        let context = RelayContext::new(
+            Direction::SideToMainSignatures,
+            "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
+        );
+        let line = context.line("1/5", Chain::Side, "fetching message");
+        let json = serde_json::to_value(&line).unwrap();
+        assert_eq!(json["direction"], "side_to_main_signatures");
+        assert_eq!(json["chain"], "side");
+        assert_eq!(json["step"], "1/5");
+        assert_eq!(
+            json["source_tx_hash"],
+            "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364"
+        );
+        assert_eq!(json["message_id"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_in_flight_relays() {
+        let context = RelayContext::new(
+            Direction::SideToMainSign,
+            "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
+        );
+        assert!(in_flight_relays().is_empty());
+
+        context.step("1/3", Chain::Side, "checking if the message is already signed");
+        context.step("2/3", Chain::Side, "signing the message");
+        let in_flight = in_flight_relays();
+        assert_eq!(in_flight.len(), 1);
+        assert_eq!(in_flight[0].step, "2/3");
+
+        drop(context);
+        assert!(in_flight_relays().is_empty());
+    }
+
+    /// renders the text format regardless of `JSON_OUTPUT`
+    struct DisplayText<'a, 'b: 'a>(&'a RelayLogLine<'b>);
+
+    impl<'a, 'b> fmt::Display for DisplayText<'a, 'b> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            self.0.fmt_text(f)
+        }
+    }
+}
@@ -17,9 +17,14 @@
 ///
 /// where a "relay" is the detection of an event on chain A
 /// followed by a transaction on chain B
+use database::Checkpoint;
 use error::{self, ResultExt};
 use futures::{Async, Future, Poll, Stream};
+use std::collections::VecDeque;
 use log_stream::LogsInBlockRange;
+use pause::PauseHandle;
+use quarantine::{quarantine, QuarantinedLog};
+use relay_log::Direction;
 use susyweb::types::Log;
 use OrderedStream;
 
@@ -28,80 +33,314 @@ use OrderedStream;
 pub trait LogToFuture {
     type Future: Future<Error = error::Error>;
 
-    fn log_to_future(&self, log: &Log) -> Self::Future;
+    /// the relay the created futures are responsible for
+    const DIRECTION: Direction;
+
+    /// fails if `log` is malformed. `RelayStream` then quarantines `log`
+    /// and continues with the other logs.
+    fn log_to_future(&self, log: &Log) -> Result<Self::Future, error::Error>;
 }
 
 /// a tokio `Stream` that when polled fetches all new logs from `stream_of_logs`
 /// calls `log_to_future` for each to obtain relay futures, waits for those
-/// futures to complete and yields the block numbers for which all relay
+/// futures to complete and yields checkpoints up to which all relay
 /// futures have completed.
-/// those block numbers can then be persisted since they'll never need to be
-/// checked again.
+/// those checkpoints can then be persisted since the logs up to them will never
+/// need to be checked again.
 pub struct RelayStream<S: Stream<Item = LogsInBlockRange, Error = error::Error>, F: LogToFuture> {
     stream_of_logs: S,
     log_to_future: F,
-    /// reorders relay futures so they are yielded in block order
+    /// reorders relay futures so they are yielded in log order
     /// rather than the order they complete.
     /// this is required because relay futures are not guaranteed to
-    /// complete in block order.
-    ordered_stream: OrderedStream<u64, F::Future>,
+    /// complete in log order.
+    ordered_stream: OrderedStream<(u64, u64), F::Future>,
+    /// last blocks of the fetched block ranges whose relays haven't all completed yet
+    pending_ranges: VecDeque<u64>,
+    /// the last yielded checkpoint. logs it covers were relayed before
+    checkpoint: Checkpoint,
+    /// while paused no new logs are fetched
+    pause: PauseHandle,
+    /// `stream_of_logs` ended. this ends once the relays in flight completed
+    logs_ended: bool,
 }
 
 impl<S: Stream<Item = LogsInBlockRange, Error = error::Error>, F: LogToFuture> RelayStream<S, F> {
-    pub fn new(stream_of_logs: S, log_to_future: F) -> Self {
+    /// logs covered by `checkpoint` are skipped
+    pub fn new(stream_of_logs: S, log_to_future: F, pause: PauseHandle, checkpoint: Checkpoint) -> Self {
         Self {
             stream_of_logs,
             log_to_future,
             ordered_stream: OrderedStream::new(),
+            pending_ranges: VecDeque::new(),
+            checkpoint,
+            pause,
+            logs_ended: false,
         }
     }
+
+    pub fn stream_of_logs_mut(&mut self) -> &mut S {
+        &mut self.stream_of_logs
+    }
+
+    /// changes to it only affect relays of logs that are fetched afterwards
+    pub fn log_to_future_mut(&mut self) -> &mut F {
+        &mut self.log_to_future
+    }
+
+    /// returns `true` if no relay futures are in flight
+    pub fn is_idle(&self) -> bool {
+        self.ordered_stream.is_empty()
+    }
+
+    /// creates the relay future for `log` and adds it to the ordered stream
+    fn relay(&mut self, logs_in_block_range: &LogsInBlockRange, log: &Log) -> Result<(), error::Error> {
+        let (block, log_index) = match (log.block_number, log.log_index) {
+            (Some(block), Some(log_index)) => (block.as_u64(), log_index.as_u64()),
+            _ => bail!("RelayStream: `log` has no block number or log index"),
+        };
+        if block < logs_in_block_range.from || block > logs_in_block_range.to {
+            bail!("RelayStream: `log` is from block {} outside of the fetched block range", block);
+        }
+        if self.checkpoint.covers(block, log_index) {
+            // relayed before a restart
+            return Ok(());
+        }
+        let relay_future = self.log_to_future.log_to_future(log)?;
+        self.ordered_stream.insert((block, log_index), relay_future);
+        Ok(())
+    }
 }
 
 impl<S: Stream<Item = LogsInBlockRange, Error = error::Error>, F: LogToFuture> Stream
     for RelayStream<S, F>
 {
-    type Item = u64;
+    type Item = Checkpoint;
     type Error = error::Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
         // on each poll we loop until there are neither new logs
         // nor newly completed relays
         loop {
-            let maybe_logs_in_block_range = try_maybe_stream!(
-                self.stream_of_logs
+            let maybe_logs_in_block_range = if self.logs_ended {
+                None
+            } else if self.pause.is_paused() {
+                // relays that are in flight still complete below
+                self.pause.park();
+                None
+            } else {
+                match self.stream_of_logs
                     .poll()
-                    .chain_err(|| "RelayStream: fetching logs failed")
-            );
+                    .chain_err(|| "RelayStream: fetching logs failed")?
+                {
+                    Async::Ready(Some(logs_in_block_range)) => Some(logs_in_block_range),
+                    Async::Ready(None) => {
+                        // the checkpoints of the ranges fetched so far are still yielded below
+                        self.logs_ended = true;
+                        None
+                    }
+                    Async::NotReady => None,
+                }
+            };
 
             if let Some(ref logs_in_block_range) = maybe_logs_in_block_range {
                 // if there are new logs, create futures from them
                 // which are responsible for the relay and add them to the
                 // ordered stream
                 for log in &logs_in_block_range.logs {
-                    let relay_future = self.log_to_future.log_to_future(log);
-                    self.ordered_stream
-                        .insert(logs_in_block_range.to, relay_future);
+                    if let Err(err) = self.relay(logs_in_block_range, log) {
+                        // fails the stream before the checkpoint moves past a log that isn't persisted
+                        quarantine(QuarantinedLog::new(
+                            F::DIRECTION,
+                            logs_in_block_range.from,
+                            logs_in_block_range.to,
+                            log,
+                            &err,
+                        )).chain_err(|| "RelayStream: quarantining a log failed")?;
+                    }
                 }
+                self.pending_ranges.push_back(logs_in_block_range.to);
             }
 
-            let maybe_fully_relayed_until_block = try_maybe_stream!(
+            let maybe_relayed = try_maybe_stream!(
                 self.ordered_stream
                     .poll()
                     .chain_err(|| "RelayStream: relaying logs failed")
             );
 
-            if let Some((fully_relayed_until_block, _)) = maybe_fully_relayed_until_block {
-                // all relay futures for this block or before have completed
-                // we can yield the block number which can be safely
-                // persisted since it doesn't need to get checked again
-                return Ok(Async::Ready(Some(fully_relayed_until_block)));
+            let mut checkpoint = self.checkpoint;
+            if let Some(((block, log_index), _)) = maybe_relayed {
+                // all relay futures for logs before this one have completed.
+                // a checkpoint within block 0 can't be represented. every
+                // checkpoint covers block 0 so its logs are never relayed
+                if let Some(previous_block) = block.checked_sub(1) {
+                    checkpoint = checkpoint.max(Checkpoint {
+                        block: previous_block,
+                        log_index: Some(log_index),
+                    });
+                }
+            }
+            // a block range without relays in flight is done.
+            // this also moves the checkpoint across ranges without logs
+            while let Some(to) = self.pending_ranges.front().cloned() {
+                if self.ordered_stream.min_order().map_or(false, |&(block, _)| block <= to) {
+                    break;
+                }
+                self.pending_ranges.pop_front();
+                checkpoint = checkpoint.max(Checkpoint {
+                    block: to,
+                    log_index: None,
+                });
             }
 
-            if maybe_logs_in_block_range.is_none() && maybe_fully_relayed_until_block.is_none() {
-                // there are neither new logs nor is there a new block number
-                // until which all relays have completed
+            if checkpoint != self.checkpoint {
+                // the checkpoint can be safely persisted since the logs
+                // up to it don't need to get checked again
+                self.checkpoint = checkpoint;
+                return Ok(Async::Ready(Some(checkpoint)));
+            }
+
+            if maybe_logs_in_block_range.is_none() && maybe_relayed.is_none() {
+                if self.logs_ended && self.ordered_stream.is_empty() {
+                    // every fetched range has been checkpointed
+                    return Ok(Async::Ready(None));
+                }
+                // there are neither new logs nor is there a new checkpoint
+                // up to which all relays have completed
                 return Ok(Async::NotReady);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::{self, FutureResult};
+    use futures::stream;
+    use quarantine::{persist_to, quarantined_logs};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use susyweb::types::Bytes;
+    use tempdir::TempDir;
+
+    /// records the logs it creates relays for. relays complete immediately
+    #[derive(Default)]
+    struct Relays(Rc<RefCell<Vec<(u64, u64)>>>);
+
+    impl LogToFuture for Relays {
+        type Future = FutureResult<(), error::Error>;
+
+        const DIRECTION: Direction = Direction::MainToSide;
+
+        fn log_to_future(&self, log: &Log) -> Result<Self::Future, error::Error> {
+            self.0.borrow_mut().push((log.block_number.unwrap().as_u64(), log.log_index.unwrap().as_u64()));
+            Ok(future::ok(()))
+        }
+    }
+
+    /// like `Relays` but fails for logs with index 1
+    #[derive(Default)]
+    struct MalformedRelays(Relays);
+
+    impl LogToFuture for MalformedRelays {
+        type Future = FutureResult<(), error::Error>;
+
+        const DIRECTION: Direction = Direction::MainToSide;
+
+        fn log_to_future(&self, log: &Log) -> Result<Self::Future, error::Error> {
+            if log.log_index == Some(1.into()) {
+                bail!("invalid data");
+            }
+            self.0.log_to_future(log)
+        }
+    }
+
+    fn log(block: u64, log_index: u64) -> Log {
+        Log {
+            address: 1.into(),
+            topics: vec![],
+            data: Bytes(vec![]),
+            transaction_hash: Some(2.into()),
+            block_hash: None,
+            block_number: Some(block.into()),
+            transaction_index: None,
+            log_index: Some(log_index.into()),
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        }
+    }
+
+    fn checkpoint(block: u64, log_index: Option<u64>) -> Checkpoint {
+        Checkpoint { block, log_index }
+    }
+
+    #[test]
+    fn test_relay_stream_checkpoints_empty_ranges() {
+        let logs = stream::iter_ok::<_, error::Error>(vec![
+            LogsInBlockRange { from: 1, to: 5, logs: vec![] },
+            LogsInBlockRange { from: 6, to: 8, logs: vec![log(7, 0), log(7, 1)] },
+            LogsInBlockRange { from: 9, to: 10, logs: vec![] },
+        ]);
+        let relay_stream = RelayStream::new(logs, Relays::default(), PauseHandle::new(), checkpoint(0, None));
+
+        let checkpoints = relay_stream.collect().wait().unwrap();
+        assert_eq!(
+            checkpoints,
+            vec![checkpoint(5, None), checkpoint(6, Some(0)), checkpoint(10, None)]
+        );
+    }
+
+    #[test]
+    fn test_relay_stream_skips_relayed_logs() {
+        let relays = Relays::default();
+        let relayed = relays.0.clone();
+        let logs = stream::iter_ok::<_, error::Error>(vec![
+            LogsInBlockRange { from: 7, to: 8, logs: vec![log(7, 0), log(7, 1), log(8, 0)] },
+        ]);
+        let relay_stream = RelayStream::new(logs, relays, PauseHandle::new(), checkpoint(6, Some(0)));
+
+        let checkpoints = relay_stream.collect().wait().unwrap();
+        assert_eq!(*relayed.borrow(), vec![(7, 1), (8, 0)]);
+        assert_eq!(checkpoints, vec![checkpoint(6, Some(1)), checkpoint(8, None)]);
+    }
+
+    #[test]
+    fn test_relay_stream_quarantines_malformed_logs() {
+        let relays = MalformedRelays::default();
+        let relayed = (relays.0).0.clone();
+        let logs = stream::iter_ok::<_, error::Error>(vec![
+            LogsInBlockRange { from: 7, to: 8, logs: vec![log(7, 0), log(7, 1), log(8, 0)] },
+            LogsInBlockRange { from: 9, to: 10, logs: vec![] },
+        ]);
+        let relay_stream = RelayStream::new(logs, relays, PauseHandle::new(), checkpoint(6, None));
+
+        let checkpoints = relay_stream.collect().wait().unwrap();
+        assert_eq!(*relayed.borrow(), vec![(7, 0), (8, 0)]);
+        // past the quarantined log
+        assert_eq!(checkpoints, vec![checkpoint(6, Some(0)), checkpoint(10, None)]);
+
+        let quarantined = quarantined_logs();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].direction, Direction::MainToSide);
+        assert_eq!((quarantined[0].from_block, quarantined[0].to_block), (7, 8));
+        assert_eq!((quarantined[0].block_number, quarantined[0].log_index), (Some(7), Some(1)));
+        assert_eq!(quarantined[0].error, vec!["invalid data".to_string()]);
+    }
+
+    #[test]
+    fn test_relay_stream_fails_if_quarantining_fails() {
+        let dir = TempDir::new("relay_stream").unwrap();
+        // can't be created
+        persist_to(dir.path().join("missing").join("bridge.db.quarantine")).unwrap();
+        let logs = stream::iter_ok::<_, error::Error>(vec![
+            LogsInBlockRange { from: 7, to: 8, logs: vec![log(7, 0), log(7, 1), log(8, 0)] },
+        ]);
+        let mut relay_stream = RelayStream::new(logs, MalformedRelays::default(), PauseHandle::new(), checkpoint(6, None));
+
+        let err = relay_stream.poll().unwrap_err();
+        assert_eq!(err.to_string(), "RelayStream: quarantining a log failed");
+        assert!(quarantined_logs().is_empty());
+    }
+}
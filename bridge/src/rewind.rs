@@ -0,0 +1,318 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! moving checkpoints back through `susy-bridge rewind` so that the logs
+//! after the new checkpoint are relayed again.
+//!
+//! the relays are idempotent: messages that were already relayed are skipped
+//! when they are replayed.
+
+use contracts;
+use database::{Checkpoint, State};
+use error;
+use futures::future::{self, join_all, loop_fn, Loop};
+use futures::Future;
+use helpers::with_timeout;
+use log_stream::fetch_logs;
+use main_contract::MainContract;
+use relay_log::{Chain, Direction};
+use side_contract::SideContract;
+use std::fmt;
+use std::time::Duration;
+use susyweb;
+use susyweb::api::Namespace;
+use susyweb::types::{BlockId, BlockNumber, Log};
+use susyweb::Transport;
+
+/// where to move a checkpoint to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    /// logs after this block are replayed
+    Block(u64),
+    /// logs in blocks at or after this unix timestamp are replayed
+    Timestamp(u64),
+}
+
+/// a single checkpoint that is moved back
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckpointRewind {
+    pub direction: Direction,
+    pub from: u64,
+    pub to: u64,
+    /// number of logs in `(to, from]`, and in block `from + 1` if the checkpoint
+    /// has a log index, that will be relayed again
+    pub replayed_events: usize,
+}
+
+impl fmt::Display for CheckpointRewind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} -> {}. {} events will be replayed",
+            self.direction, self.from, self.to, self.replayed_events
+        )
+    }
+}
+
+/// checks that the checkpoint of `direction` in `state` can be moved to `to`
+pub fn check_rewind(state: &State, direction: Direction, to: u64) -> Result<(), error::Error> {
+    let deployed_at_block = state.deployed_at_block(direction);
+    if to < deployed_at_block {
+        bail!(
+            "cannot rewind {} to block {}. the {} contract has been deployed at block {}",
+            direction,
+            to,
+            direction.source_chain(),
+            deployed_at_block
+        );
+    }
+    let checkpoint = state.checkpoint(direction);
+    if to > checkpoint {
+        bail!(
+            "cannot rewind {} to block {}. it is ahead of the checkpoint at block {} and would skip logs",
+            direction,
+            to,
+            checkpoint
+        );
+    }
+    Ok(())
+}
+
+/// `state` with all checkpoints of `rewinds` moved back
+pub fn apply(state: &State, rewinds: &[CheckpointRewind]) -> State {
+    let mut state = state.clone();
+    for rewind in rewinds {
+        state.set_checkpoint(rewind.direction, rewind.to);
+    }
+    state
+}
+
+type BoxFuture<I> = Box<Future<Item = I, Error = error::Error>>;
+
+/// plans rewinds by resolving targets to blocks and counting the logs to replay
+pub struct Rewind<T> {
+    pub main: MainContract<T>,
+    pub side: SideContract<T>,
+}
+
+impl<T: Transport + 'static> Rewind<T>
+where
+    T::Out: 'static,
+{
+    /// `Future` resolving to the rewind of the checkpoint of every one of `directions` to `target`.
+    /// fails if any of them is not allowed by `check_rewind`
+    pub fn plan(&self, state: &State, directions: &[Direction], target: Target) -> BoxFuture<Vec<CheckpointRewind>> {
+        let rewinds = directions
+            .iter()
+            .map(|&direction| {
+                let state = state.clone();
+                let checkpoint = state.relay_checkpoint(direction);
+                let from = checkpoint.block;
+                let main = self.main.clone();
+                let side = self.side.clone();
+                self.resolve(direction.source_chain(), target).and_then(move |to| -> Result<u64, error::Error> {
+                    check_rewind(&state, direction, to)?;
+                    Ok(to)
+                }).and_then(move |to| {
+                    replayed_events(&main, &side, direction, to, checkpoint).map(move |replayed_events| CheckpointRewind {
+                        direction,
+                        from,
+                        to,
+                        replayed_events,
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        Box::new(join_all(rewinds))
+    }
+
+    /// resolves `target` to a block number on `chain`
+    fn resolve(&self, chain: Chain, target: Target) -> BoxFuture<u64> {
+        match target {
+            Target::Block(block) => Box::new(future::ok(block)),
+            Target::Timestamp(timestamp) => match chain {
+                Chain::Main => last_block_before(self.main.transport.clone(), timestamp, self.main.request_timeout),
+                Chain::Side => last_block_before(self.side.transport.clone(), timestamp, self.side.request_timeout),
+            },
+        }
+    }
+}
+
+/// `Future` resolving to the number of logs of `direction` after block `to` that are covered by `checkpoint`
+fn replayed_events<T: Transport + 'static>(
+    main: &MainContract<T>,
+    side: &SideContract<T>,
+    direction: Direction,
+    to: u64,
+    checkpoint: Checkpoint,
+) -> BoxFuture<usize>
+where
+    T::Out: 'static,
+{
+    // the logs of block `checkpoint.block + 1` up to `log_index` are replayed as well
+    let from = match checkpoint.log_index {
+        Some(_) => checkpoint.block + 1,
+        None => checkpoint.block,
+    };
+    if to == from {
+        return Box::new(future::ok(0));
+    }
+    let logs = match direction {
+        Direction::MainToSide => fetch_logs(
+            &main.transport,
+            main.contract_address,
+            &contracts::main::events::relay_message::filter(),
+            to + 1,
+            from,
+            main.request_timeout,
+        ),
+        Direction::SideToMainSign => fetch_logs(
+            &side.transport,
+            side.contract_address,
+            &contracts::side::events::relay_message::filter(),
+            to + 1,
+            from,
+            side.request_timeout,
+        ),
+        Direction::SideToMainSignatures => fetch_logs(
+            &side.transport,
+            side.contract_address,
+            &contracts::side::events::signed_message::filter(side.authority_address),
+            to + 1,
+            from,
+            side.request_timeout,
+        ),
+    };
+    Box::new(logs.map(move |logs| covered_logs(&logs, &checkpoint)))
+}
+
+/// number of `logs` that `checkpoint` covers
+fn covered_logs(logs: &[Log], checkpoint: &Checkpoint) -> usize {
+    logs.iter()
+        .filter(|log| match (log.block_number, log.log_index) {
+            (Some(block), Some(log_index)) => checkpoint.covers(block.as_u64(), log_index.as_u64()),
+            // pending logs have not been relayed
+            _ => false,
+        })
+        .count()
+}
+
+/// `Future` resolving to the number of the last block whose timestamp is before `timestamp`.
+/// binary search over the block timestamps
+fn last_block_before<T: Transport + 'static>(transport: T, timestamp: u64, request_timeout: Duration) -> BoxFuture<u64>
+where
+    T::Out: 'static,
+{
+    let head = with_timeout(susyweb::api::Sof::new(&transport).block_number(), request_timeout);
+    Box::new(head.and_then(move |head| {
+        // the block we're looking for is in `[low, high]`
+        loop_fn((transport, 0, head.as_u64()), move |(transport, low, high)| -> BoxFuture<Loop<u64, (T, u64, u64)>> {
+            if low == high {
+                return Box::new(future::ok(Loop::Break(low)));
+            }
+            let middle = low + (high - low + 1) / 2;
+            let block = with_timeout(
+                susyweb::api::Sof::new(&transport).block(BlockId::Number(BlockNumber::Number(middle))),
+                request_timeout,
+            );
+            Box::new(block.and_then(move |block| {
+                let block = block.ok_or_else(|| format!("block {} is unknown", middle))?;
+                if block.timestamp.as_u64() < timestamp {
+                    Ok(Loop::Continue((transport, middle, high)))
+                } else {
+                    Ok(Loop::Continue((transport, low, middle - 1)))
+                }
+            }))
+        })
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use susyweb::types::Bytes;
+
+    fn state() -> State {
+        State {
+            main_deployed_at_block: 100,
+            side_deployed_at_block: 200,
+            last_main_to_side_sign_at_block: 150,
+            last_side_to_main_sign_at_block: 250,
+            last_side_to_main_signatures_at_block: 240,
+            ..State::default()
+        }
+    }
+
+    #[test]
+    fn test_check_rewind() {
+        let state = state();
+        assert!(check_rewind(&state, Direction::MainToSide, 100).is_ok());
+        assert!(check_rewind(&state, Direction::MainToSide, 150).is_ok());
+        assert!(check_rewind(&state, Direction::MainToSide, 99).is_err());
+        assert!(check_rewind(&state, Direction::MainToSide, 151).is_err());
+        assert!(check_rewind(&state, Direction::SideToMainSign, 150).is_err());
+        assert!(check_rewind(&state, Direction::SideToMainSignatures, 245).is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let state = state();
+        let rewinds = vec![
+            CheckpointRewind {
+                direction: Direction::SideToMainSign,
+                from: 250,
+                to: 210,
+                replayed_events: 2,
+            },
+        ];
+        let expected = State {
+            last_side_to_main_sign_at_block: 210,
+            ..state.clone()
+        };
+        assert_eq!(apply(&state, &rewinds), expected);
+    }
+
+    fn log(block: u64, log_index: u64) -> Log {
+        Log {
+            address: 1.into(),
+            topics: vec![],
+            data: Bytes(vec![]),
+            transaction_hash: Some(2.into()),
+            block_hash: None,
+            block_number: Some(block.into()),
+            transaction_index: None,
+            log_index: Some(log_index.into()),
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        }
+    }
+
+    #[test]
+    fn test_covered_logs_counts_the_logs_after_the_checkpoint_block() {
+        let logs = vec![log(150, 0), log(150, 1), log(151, 0), log(151, 1), log(151, 2)];
+        let checkpoint = Checkpoint {
+            block: 150,
+            log_index: Some(1),
+        };
+        assert_eq!(covered_logs(&logs, &checkpoint), 4);
+        let checkpoint = Checkpoint {
+            block: 150,
+            log_index: None,
+        };
+        assert_eq!(covered_logs(&logs, &checkpoint), 2);
+    }
+}
@@ -19,10 +19,10 @@ use futures::future::FromErr;
 use futures::{Future, Poll};
 use tokio_timer::{Timeout, Timer};
 use susyweb::{self, Transport};
-use susyweb::types::{TransactionRequest, TransactionReceipt, U256};
+use susyweb::types::{Transaction, TransactionId, TransactionRequest, TransactionReceipt, H256, U256};
 use susyweb::helpers::CallFuture;
 use susyweb::api::Namespace;
-use error::{self, ResultExt};
+use error::{self, ErrorKind, ResultExt};
 
 mod inner {
     use std::time::Duration;
@@ -46,12 +46,18 @@ mod inner {
         }
     }
 
+    pub enum Transaction {
+        Unsent(TransactionRequest),
+        /// hash of a transaction that was sent before
+        Sent(H256),
+    }
+
     pub struct SendTransactionWithReceiptOptions<T: Transport> {
         pub transport: T,
         pub request_timeout: Duration,
         pub poll_interval: Duration,
         pub confirmations: u32,
-        pub transaction: TransactionRequest,
+        pub transaction: Transaction,
         pub after: u64,
     }
 
@@ -75,12 +81,17 @@ mod inner {
                 after: options.after,
             };
             let block_number_stream = BlockNumberStream::new(block_number_stream_options);
-            let future = susyweb::api::Sof::new(&options.transport).send_transaction(options.transaction);
-            let future = timer.timeout(future.from_err(), options.request_timeout);
+            let state = match options.transaction {
+                Transaction::Unsent(transaction) => {
+                    let future = susyweb::api::Sof::new(&options.transport).send_transaction(transaction);
+                    State::AwaitSendTransaction(timer.timeout(future.from_err(), options.request_timeout))
+                }
+                Transaction::Sent(transaction_hash) => State::AwaitBlockNumber(transaction_hash),
+            };
 
             SendTransactionWithReceipt {
                 transport: options.transport,
-                state: State::AwaitSendTransaction(future),
+                state,
                 block_number_stream,
                 request_timeout: options.request_timeout,
                 timer,
@@ -162,6 +173,11 @@ enum State<T: Transport> {
         future: Timeout<FromErr<CallFuture<U256, T::Out>, error::Error>>,
         transaction: Option<TransactionRequest>,
     },
+    /// checking that a transaction sent before is still known to the node
+    AwaitTransaction {
+        future: Timeout<FromErr<CallFuture<Option<Transaction>, T::Out>, error::Error>>,
+        transaction_hash: H256,
+    },
     AwaitReceipt(inner::SendTransactionWithReceipt<T>)
 }
 
@@ -174,6 +190,15 @@ pub struct SendTransactionWithReceiptOptions<T> {
     pub transaction: TransactionRequest,
 }
 
+/// options of `SendTransactionWithReceipt::sent`
+pub struct SentTransactionOptions<T> {
+    pub transport: T,
+    pub request_timeout: Duration,
+    pub poll_interval: Duration,
+    pub confirmations: u32,
+    pub transaction_hash: H256,
+}
+
 pub struct SendTransactionWithReceipt<T: Transport> {
     request_timeout: Duration,
     poll_interval: Duration,
@@ -203,6 +228,26 @@ impl<T: Transport> SendTransactionWithReceipt<T> {
     }
 }
 
+impl<T: Transport> SendTransactionWithReceipt<T> {
+    /// waits for the receipt of a transaction that was sent before, e.g. by a previous run.
+    /// fails with `ErrorKind::UnknownTransaction` if the node doesn't know the transaction,
+    /// e.g. because it was dropped or replaced
+    pub fn sent(options: SentTransactionOptions<T>) -> Self {
+        let future = susyweb::api::Sof::new(&options.transport).transaction(TransactionId::Hash(options.transaction_hash));
+
+        SendTransactionWithReceipt {
+            request_timeout: options.request_timeout,
+            poll_interval: options.poll_interval,
+            state: State::AwaitTransaction {
+                future: Timer::default().timeout(future.from_err(), options.request_timeout),
+                transaction_hash: options.transaction_hash,
+            },
+            transport: options.transport,
+            confirmations: options.confirmations,
+        }
+    }
+}
+
 impl<T: Transport> Future for SendTransactionWithReceipt<T> {
     type Item = TransactionReceipt;
     type Error = error::Error;
@@ -229,13 +274,34 @@ impl<T: Transport> Future for SendTransactionWithReceipt<T> {
                         request_timeout: self.request_timeout,
                         poll_interval: self.poll_interval,
                         confirmations: self.confirmations,
-                        transaction,
+                        transaction: inner::Transaction::Unsent(transaction),
                         after: block_number.as_u64(),
                     };
 
                     let future = inner::SendTransactionWithReceipt::new(inner_options);
                     State::AwaitReceipt(future)
                 },
+                State::AwaitTransaction { ref mut future, transaction_hash } => {
+                    let transaction = try_ready!(
+                        future
+                            .poll()
+                            .chain_err(|| "SendTransactionWithReceipt: fetching transaction failed")
+                    );
+                    if transaction.is_none() {
+                        // no receipt will ever arrive
+                        bail!(ErrorKind::UnknownTransaction(transaction_hash));
+                    }
+
+                    let inner_options = inner::SendTransactionWithReceiptOptions {
+                        transport: self.transport.clone(),
+                        request_timeout: self.request_timeout,
+                        poll_interval: self.poll_interval,
+                        confirmations: self.confirmations,
+                        transaction: inner::Transaction::Sent(transaction_hash),
+                        after: 0,
+                    };
+                    State::AwaitReceipt(inner::SendTransactionWithReceipt::new(inner_options))
+                },
                 State::AwaitReceipt(ref mut future) => {
                     return future.poll()
                 }
@@ -371,4 +437,31 @@ mod tests {
         );
         assert_eq!(transport.actual_requests(), transport.expected_requests());
     }
+
+    #[test]
+    fn test_sent_tx_dropped() {
+        let transport = mock_transport!(
+            "sof_getTransactionByHash" =>
+                req => json!(["0x36efc16910ea67a2425a1e75f7e39e3c6a94f5763c68a47258f552481e20cd34"]),
+                res => json!(null);
+        );
+
+        let sent = SendTransactionWithReceipt::sent(SentTransactionOptions {
+            transport: transport.clone(),
+            request_timeout: Duration::from_secs(1),
+            poll_interval: Duration::from_secs(0),
+            confirmations: 2,
+            transaction_hash: "0x36efc16910ea67a2425a1e75f7e39e3c6a94f5763c68a47258f552481e20cd34".into(),
+        });
+
+        let mut event_loop = Core::new().unwrap();
+        match *event_loop.run(sent).unwrap_err().kind() {
+            ErrorKind::UnknownTransaction(hash) => assert_eq!(
+                hash,
+                "0x36efc16910ea67a2425a1e75f7e39e3c6a94f5763c68a47258f552481e20cd34".into()
+            ),
+            ref other => panic!("unexpected error {:?}", other),
+        }
+        assert_eq!(transport.actual_requests(), transport.expected_requests());
+    }
 }
@@ -16,9 +16,9 @@
 use config::Config;
 use contracts;
 use database::State;
-use sofabi::FunctionOutputDecoder;
+use sofabi::{self, FunctionOutputDecoder};
 use futures::future::{join_all, JoinAll};
-use helpers::{AsyncCall, AsyncTransaction};
+use helpers::{self, AsyncCall, AsyncTransaction};
 use log_stream::{LogStream, LogStreamOptions};
 use message_to_main::MessageToMain;
 use signature::Signature;
@@ -32,7 +32,8 @@ pub struct SideContract<T> {
     pub transport: T,
     pub contract_address: Address,
     pub authority_address: Address,
-    // TODO [snd] this should get fetched from the contract
+    /// signatures `get_signatures` collects. `authorities.required_signatures` from the config
+    /// until the bridge read the value of the contract. see `authority_set::AuthorityWatch`
     pub required_signatures: u32,
     pub request_timeout: Duration,
     pub logs_poll_interval: Duration,
@@ -75,6 +76,12 @@ impl<T: Transport> SideContract<T> {
         self.call(payload, decoder)
     }
 
+    /// number of signatures the contract requires
+    pub fn required_signatures(&self) -> AsyncCall<T, contracts::side::functions::required_signatures::Decoder> {
+        let (payload, decoder) = contracts::side::functions::required_signatures::call();
+        self.call(payload, decoder)
+    }
+
     /// returns `Future` that resolves with `bool` whether `authority`
     /// has signed side to main relay for `tx_hash`
     pub fn is_side_to_main_signed_on_side(
@@ -95,13 +102,32 @@ impl<T: Transport> SideContract<T> {
         data: Vec<u8>,
         sender: Address,
         recipient: Address,
+    ) -> AsyncCall<T, contracts::side::functions::has_authority_accepted_message_from_main::Decoder> {
+        self.has_authority_accepted_message_from_main(
+            self.authority_address,
+            transaction_hash,
+            data,
+            sender,
+            recipient,
+        )
+    }
+
+    /// returns `Future` that resolves with `bool` whether `authority`
+    /// has accepted the message relayed from main in `transaction_hash`
+    pub fn has_authority_accepted_message_from_main(
+        &self,
+        authority: Address,
+        transaction_hash: H256,
+        data: Vec<u8>,
+        sender: Address,
+        recipient: Address,
     ) -> AsyncCall<T, contracts::side::functions::has_authority_accepted_message_from_main::Decoder> {
         let (payload, decoder) = contracts::side::functions::has_authority_accepted_message_from_main::call(
             transaction_hash,
             data,
             sender,
             recipient,
-            self.authority_address
+            authority
         );
 
         self.call(payload, decoder)
@@ -156,6 +182,23 @@ impl<T: Transport> SideContract<T> {
         })
     }
 
+    /// logs of all events `MessageTracker` follows on `side`
+    pub fn message_lifecycle_log_stream(&self, after: u64) -> LogStream<T> {
+        LogStream::new(LogStreamOptions {
+            filter: helpers::any_of_events(&[
+                contracts::side::events::relay_message::filter(),
+                contracts::side::events::accepted_message::filter(),
+                contracts::side::events::signed_message::filter(sofabi::Topic::Any),
+            ]),
+            request_timeout: self.request_timeout,
+            poll_interval: self.logs_poll_interval,
+            confirmations: self.required_log_confirmations,
+            transport: self.transport.clone(),
+            contract_address: self.contract_address,
+            after,
+        })
+    }
+
     pub fn submit_signed_message(
         &self,
         message: &MessageToMain,
@@ -19,6 +19,7 @@ use futures::future::FromErr;
 use futures::{Async, Future, Poll};
 use helpers::{AsyncCall, AsyncTransaction};
 use message_to_main::{MessageToMain, MESSAGE_LENGTH};
+use relay_log::{Chain, Direction, RelayContext};
 use relay_stream::LogToFuture;
 use side_contract::SideContract;
 use signature::Signature;
@@ -40,46 +41,54 @@ enum State<T: Transport> {
 /// these get created by the `side_to_main_sign` `RelayStream` that's part
 /// of the `Bridge`.
 pub struct SideToMainSign<T: Transport> {
-    tx_hash: H256,
     side: SideContract<T>,
     message: MessageToMain,
     state: State<T>,
+    context: RelayContext,
 }
 
 impl<T: Transport> SideToMainSign<T> {
-    pub fn new(log: &Log, side: SideContract<T>) -> Self {
+    /// fails if `log` is not a mined `sideContract.RelayMessage` event
+    pub fn new(log: &Log, side: SideContract<T>) -> Result<Self, error::Error> {
         let tx_hash = log.transaction_hash
-            .expect("`log` must be mined and contain `transaction_hash`. q.e.d.");
+            .ok_or_else(|| "SideToMainSign: `log` is not mined and has no `transaction_hash`")?;
 
-        let message = MessageToMain::from_log(log).expect("`log` must contain valid message. q.e.d.");
+        let message = MessageToMain::from_log(log)
+            .chain_err(|| "SideToMainSign: `log` does not contain a valid message")?;
         let message_bytes = message.to_bytes();
+        if message_bytes.len() != MESSAGE_LENGTH {
+            bail!(
+                "SideToMainSign: message has {} bytes. SideBridge only accepts messages with {} bytes",
+                message_bytes.len(),
+                MESSAGE_LENGTH
+            );
+        }
 
-        assert_eq!(
-            message_bytes.len(),
-            MESSAGE_LENGTH,
-            "SideBridge never accepts messages with len != {} bytes; qed",
-            MESSAGE_LENGTH
-        );
+        let mut context = RelayContext::new(Direction::SideToMainSign, tx_hash);
+        context.message_id = Some(message.message_id);
+        context.step("1/3", Chain::Side, "checking if the message is already signed");
 
         let future = side.is_side_to_main_signed_on_side(&message);
         let state = State::AwaitCheckAlreadySigned(future);
-        info!("{:?} - step 1/3 - about to sign message", tx_hash);
 
-        Self {
+        Ok(Self {
             side,
-            tx_hash,
             message,
             state,
-        }
+            context,
+        })
     }
-}
 
-impl<T: Transport> Future for SideToMainSign<T> {
-    /// transaction hash
-    type Item = Option<H256>;
-    type Error = error::Error;
+    /// the step the relay is currently in and the chain it's waiting for
+    pub fn step(&self) -> (&'static str, Chain) {
+        match self.state {
+            State::AwaitCheckAlreadySigned(_) => ("1/3", Chain::Side),
+            State::AwaitSignature(_) => ("2/3", Chain::Side),
+            State::AwaitTransaction(_) => ("3/3", Chain::Side),
+        }
+    }
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+    fn poll_relay(&mut self) -> Poll<Option<H256>, error::Error> {
         loop {
             let next_state = match self.state {
                 State::AwaitCheckAlreadySigned(ref mut future) => {
@@ -89,9 +98,11 @@ impl<T: Transport> Future for SideToMainSign<T> {
                             .chain_err(|| "WithdrawConfirm: message signing failed")
                     );
                     if is_already_signed {
+                        self.context.done(Chain::Side, None, "already signed");
                         return Ok(Async::Ready(None));
                     }
 
+                    self.context.step("2/3", Chain::Side, "signing the message");
                     let inner_future = susyweb::api::Sof::new(self.side.transport.clone())
                         .sign(self.side.authority_address, Bytes(self.message.to_bytes()))
                         .from_err();
@@ -105,10 +116,7 @@ impl<T: Transport> Future for SideToMainSign<T> {
                             .poll()
                             .chain_err(|| "WithdrawConfirm: message signing failed")
                     );
-                    info!(
-                        "{:?} - step 2/3 - message signed. about to send transaction",
-                        self.tx_hash
-                    );
+                    self.context.step("3/3", Chain::Side, "message signed. about to send transaction");
 
                     let signature = Signature::from_bytes(&signature_bytes)?;
 
@@ -121,10 +129,7 @@ impl<T: Transport> Future for SideToMainSign<T> {
                             .poll()
                             .chain_err(|| "WithdrawConfirm: sending transaction failed")
                     );
-                    info!(
-                        "{:?} - step 3/3 - DONE - transaction sent {:?}",
-                        self.tx_hash, tx_hash
-                    );
+                    self.context.done(Chain::Side, Some(tx_hash), "transaction sent");
                     return Ok(Async::Ready(Some(tx_hash)));
                 }
             };
@@ -133,6 +138,20 @@ impl<T: Transport> Future for SideToMainSign<T> {
     }
 }
 
+impl<T: Transport> Future for SideToMainSign<T> {
+    /// transaction hash
+    type Item = Option<H256>;
+    type Error = error::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.poll_relay().map_err(|err| {
+            let (step, chain) = self.step();
+            self.context.failed(step, chain, &err);
+            err
+        })
+    }
+}
+
 pub struct LogToSideToMainSign<T: Transport> {
     pub side: SideContract<T>,
 }
@@ -141,7 +160,9 @@ pub struct LogToSideToMainSign<T: Transport> {
 impl<T: Transport> LogToFuture for LogToSideToMainSign<T> {
     type Future = SideToMainSign<T>;
 
-    fn log_to_future(&self, log: &Log) -> Self::Future {
+    const DIRECTION: Direction = Direction::SideToMainSign;
+
+    fn log_to_future(&self, log: &Log) -> Result<Self::Future, error::Error> {
         SideToMainSign::new(log, self.side.clone())
     }
 }
@@ -252,7 +273,7 @@ mod tests {
             sign_side_to_main_gas_price: 0xa0.into(),
         };
 
-        let future = SideToMainSign::new(&raw_log, side_contract);
+        let future = SideToMainSign::new(&raw_log, side_contract).unwrap();
 
         let mut event_loop = Core::new().unwrap();
         let result = event_loop.run(future).unwrap();
@@ -334,7 +355,7 @@ mod tests {
             sign_side_to_main_gas_price: 0xa0.into(),
         };
 
-        let future = SideToMainSign::new(&raw_log, side_contract);
+        let future = SideToMainSign::new(&raw_log, side_contract).unwrap();
 
         let mut event_loop = Core::new().unwrap();
         let result = event_loop.run(future).unwrap();
@@ -342,4 +363,45 @@ mod tests {
 
         assert_eq!(transport.actual_requests(), transport.expected_requests());
     }
+
+    #[test]
+    fn test_side_to_main_sign_rejects_short_messages() {
+        let raw_log = Log {
+            address: "0000000000000000000000000000000000000001".into(),
+            topics: contracts::side::events::relay_message::filter().topic0.into(),
+            // a message id without sender and recipient
+            data: Bytes(sofabi::encode(&[sofabi::Token::FixedBytes(vec![3; 32])])),
+            transaction_hash: Some("0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into()),
+            block_hash: None,
+            block_number: None,
+            transaction_index: None,
+            log_index: None,
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        };
+
+        let transport = ::MockTransport {
+            expected_requests: vec![],
+            actual_requests: Default::default(),
+            mock_responses: vec![],
+        };
+
+        let side_contract = SideContract {
+            transport: transport.clone(),
+            contract_address: "0000000000000000000000000000000000000dd1".into(),
+            authority_address: "0000000000000000000000000000000000000001".into(),
+            required_signatures: 1,
+            request_timeout: ::std::time::Duration::from_millis(0),
+            logs_poll_interval: ::std::time::Duration::from_millis(0),
+            required_log_confirmations: 0,
+            sign_main_to_side_gas: 0.into(),
+            sign_main_to_side_gas_price: 0.into(),
+            sign_side_to_main_gas: 0.into(),
+            sign_side_to_main_gas_price: 0.into(),
+        };
+
+        assert!(SideToMainSign::new(&raw_log, side_contract).is_err());
+        assert!(transport.actual_requests().is_empty());
+    }
 }
@@ -22,6 +22,7 @@ use helpers;
 use helpers::{AsyncCall, AsyncTransaction};
 use main_contract::MainContract;
 use message_to_main::MessageToMain;
+use relay_log::{Chain, Direction, RelayContext};
 use relay_stream::LogToFuture;
 use side_contract::SideContract;
 use signature::Signature;
@@ -51,47 +52,59 @@ enum State<T: Transport> {
 /// these get created by the `side_to_main_signatures` `RelayStream` that's part
 /// of the `Bridge`.
 pub struct SideToMainSignatures<T: Transport> {
-    side_tx_hash: H256,
     main: MainContract<T>,
     side: SideContract<T>,
     state: State<T>,
+    context: RelayContext,
 }
 
 impl<T: Transport> SideToMainSignatures<T> {
-    pub fn new(raw_log: &Log, main: MainContract<T>, side: SideContract<T>) -> Self {
+    /// fails if `raw_log` is not a mined `sideContract.SignedMessage` event
+    /// this authority is responsible for
+    pub fn new(raw_log: &Log, main: MainContract<T>, side: SideContract<T>) -> Result<Self, error::Error> {
         let side_tx_hash = raw_log
             .transaction_hash
-            .expect("`log` must be mined and contain `transaction_hash`. q.e.d.");
+            .ok_or_else(|| "SideToMainSignatures: `log` is not mined and has no `transaction_hash`")?;
 
         let log = helpers::parse_log(contracts::side::events::signed_message::parse_log, raw_log)
-            .expect("`Log` must be a from a `CollectedSignatures` event. q.e.d.");
+            .chain_err(|| "SideToMainSignatures: `log` is not a valid `SignedMessage` event")?;
 
         // authority_responsible_for_relay is an indexed topic and it should be
         // always set up when creating the filter, so we receive only logs that
         // we should relay
-        assert_eq!(
-            log.authority_responsible_for_relay, main.authority_address,
-            "incorrectly set up collected_signatures filter, we should only received logs where authority_responsible_for_relay == main.authority_address; qed"
-        );
+        if log.authority_responsible_for_relay != main.authority_address {
+            bail!(
+                "SideToMainSignatures: `log` is for authority {:?} but we are {:?}. is the `SignedMessage` filter set up correctly?",
+                log.authority_responsible_for_relay,
+                main.authority_address
+            );
+        }
 
-        info!("{:?} - step 1/3 - about to fetch message", side_tx_hash,);
+        let context = RelayContext::new(Direction::SideToMainSignatures, side_tx_hash);
+        context.step("1/5", Chain::Side, "fetching message");
         let (payload, decoder) = contracts::side::functions::message::call(log.message_hash);
         let state = State::AwaitMessage(side.call(payload, decoder));
 
-        Self {
-            side_tx_hash,
+        Ok(Self {
             main,
             side,
             state,
-        }
+            context,
+        })
     }
-}
 
-impl<T: Transport> Future for SideToMainSignatures<T> {
-    type Item = Option<H256>;
-    type Error = error::Error;
+    /// the step the relay is currently in and the chain it's waiting for
+    pub fn step(&self) -> (&'static str, Chain) {
+        match self.state {
+            State::AwaitMessage(_) => ("1/5", Chain::Side),
+            State::AwaitIsRelayed { .. } => ("2/5", Chain::Main),
+            State::AwaitSignatures { .. } => ("3/5", Chain::Side),
+            State::AwaitMessageData { .. } => ("4/5", Chain::Side),
+            State::AwaitTxSent(_) => ("5/5", Chain::Main),
+        }
+    }
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+    fn poll_relay(&mut self) -> Poll<Option<H256>, error::Error> {
         loop {
             let next_state = match self.state {
                 State::AwaitMessage(ref mut future) => {
@@ -101,7 +114,9 @@ impl<T: Transport> Future for SideToMainSignatures<T> {
                             .chain_err(|| "SubmitSignature: fetching message failed")
                     );
                     let message = MessageToMain::from_bytes(&message_bytes)?;
+                    self.context.message_id = Some(message.message_id);
 
+                    self.context.step("2/5", Chain::Main, "checking if the message is already accepted");
                     let (payload, decoder) = contracts::main::functions::accepted_messages::call(message.keccak256());
                     State::AwaitIsRelayed {
                         future: self.main.call(payload, decoder),
@@ -117,9 +132,11 @@ impl<T: Transport> Future for SideToMainSignatures<T> {
                     );
 
                     if is_relayed {
+                        self.context.done(Chain::Main, None, "already accepted");
                         return Ok(Async::Ready(None));
                     }
 
+                    self.context.step("3/5", Chain::Side, "fetching signatures");
                     State::AwaitSignatures {
                         future: self.side.get_signatures(message.keccak256()),
                         message: message.clone(),
@@ -135,7 +152,11 @@ impl<T: Transport> Future for SideToMainSignatures<T> {
                         .iter()
                         .map(|x| Signature::from_bytes(x))
                         .collect::<Result<_, _>>()?;
-                    info!("{:?} - step 2/3 - message and {} signatures received. about to send transaction", self.side_tx_hash, signatures.len());
+                    self.context.step(
+                        "4/5",
+                        Chain::Side,
+                        &format!("{} signatures received. fetching message data", signatures.len()),
+                    );
 
                     let (payload, decoder) = contracts::side::functions::relayed_messages::call(message.message_id);
                     State::AwaitMessageData {
@@ -151,6 +172,7 @@ impl<T: Transport> Future for SideToMainSignatures<T> {
                             .chain_err(|| "SubmitSignature: fetching message failed")
                     );
 
+                    self.context.step("5/5", Chain::Main, "about to send transaction");
                     State::AwaitTxSent(self.main.relay_side_to_main(&message, &signatures, message_data))
                 },
                 State::AwaitTxSent(ref mut future) => {
@@ -159,10 +181,7 @@ impl<T: Transport> Future for SideToMainSignatures<T> {
                             .poll()
                             .chain_err(|| "WithdrawRelay: sending transaction failed")
                     );
-                    info!(
-                        "{:?} - step 3/3 - DONE - transaction sent {:?}",
-                        self.side_tx_hash, main_tx_hash
-                    );
+                    self.context.done(Chain::Main, Some(main_tx_hash), "transaction sent");
                     return Ok(Async::Ready(Some(main_tx_hash)));
                 }
             };
@@ -171,6 +190,19 @@ impl<T: Transport> Future for SideToMainSignatures<T> {
     }
 }
 
+impl<T: Transport> Future for SideToMainSignatures<T> {
+    type Item = Option<H256>;
+    type Error = error::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.poll_relay().map_err(|err| {
+            let (step, chain) = self.step();
+            self.context.failed(step, chain, &err);
+            err
+        })
+    }
+}
+
 /// options for relays from side to main
 pub struct LogToSideToMainSignatures<T> {
     pub main: MainContract<T>,
@@ -181,7 +213,9 @@ pub struct LogToSideToMainSignatures<T> {
 impl<T: Transport> LogToFuture for LogToSideToMainSignatures<T> {
     type Future = SideToMainSignatures<T>;
 
-    fn log_to_future(&self, log: &Log) -> Self::Future {
+    const DIRECTION: Direction = Direction::SideToMainSignatures;
+
+    fn log_to_future(&self, log: &Log) -> Result<Self::Future, error::Error> {
         SideToMainSignatures::new(log, self.main.clone(), self.side.clone())
     }
 }
@@ -317,7 +351,7 @@ mod tests {
             sign_side_to_main_gas_price: 0xa0.into(),
         };
 
-        let future = SideToMainSignatures::new(&raw_log, main_contract, side_contract);
+        let future = SideToMainSignatures::new(&raw_log, main_contract, side_contract).unwrap();
 
         let mut event_loop = Core::new().unwrap();
         let result = event_loop.run(future).unwrap();
@@ -415,7 +449,7 @@ mod tests {
             sign_side_to_main_gas_price: 0xa0.into(),
         };
 
-        let future = SideToMainSignatures::new(&raw_log, main_contract, side_contract);
+        let future = SideToMainSignatures::new(&raw_log, main_contract, side_contract).unwrap();
 
         let mut event_loop = Core::new().unwrap();
         let result = event_loop.run(future).unwrap();
@@ -427,4 +461,64 @@ mod tests {
             side_transport.expected_requests()
         );
     }
+
+    #[test]
+    fn test_side_to_main_signatures_rejects_malformed_logs() {
+        let authority_address: Address = "0000000000000000000000000000000000000001".into();
+        let other_authority: Address = "0000000000000000000000000000000000000002".into();
+        let topic = contracts::side::events::signed_message::filter(other_authority);
+
+        let mut raw_log = Log {
+            address: "0000000000000000000000000000000000000dd1".into(),
+            topics: vec![topic.topic0[0], topic.topic1[0]],
+            data: Bytes(sofabi::encode(&[sofabi::Token::FixedBytes(vec![3; 32])])),
+            transaction_hash: Some("0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into()),
+            block_hash: None,
+            block_number: None,
+            transaction_index: None,
+            log_index: None,
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        };
+
+        let transport = ::MockTransport {
+            expected_requests: vec![],
+            actual_requests: Default::default(),
+            mock_responses: vec![],
+        };
+
+        let main_contract = MainContract {
+            transport: transport.clone(),
+            contract_address: "0000000000000000000000000000000000000fff".into(),
+            authority_address,
+            request_timeout: ::std::time::Duration::from_millis(0),
+            logs_poll_interval: ::std::time::Duration::from_millis(0),
+            required_log_confirmations: 0,
+            submit_collected_signatures_gas: 0xfd.into(),
+        };
+
+        let side_contract = SideContract {
+            transport: transport.clone(),
+            contract_address: "0000000000000000000000000000000000000dd1".into(),
+            authority_address,
+            required_signatures: 1,
+            request_timeout: ::std::time::Duration::from_millis(0),
+            logs_poll_interval: ::std::time::Duration::from_millis(0),
+            required_log_confirmations: 0,
+            sign_main_to_side_gas: 0.into(),
+            sign_main_to_side_gas_price: 0.into(),
+            sign_side_to_main_gas: 0xfd.into(),
+            sign_side_to_main_gas_price: 0xa0.into(),
+        };
+
+        // another authority is responsible
+        assert!(SideToMainSignatures::new(&raw_log, main_contract.clone(), side_contract.clone()).is_err());
+
+        // not mined
+        raw_log.transaction_hash = None;
+        assert!(SideToMainSignatures::new(&raw_log, main_contract, side_contract).is_err());
+
+        assert!(transport.actual_requests().is_empty());
+    }
 }
@@ -17,9 +17,11 @@ use sofabi;
 /// ECDSA signatures:
 /// conversion from/to byte vectors.
 /// from/to v, r, s components.
-use sophon_types::H256;
+use sophon_types::{Address, H256};
 
 use error::Error;
+use secp256k1::{Message, RecoverableSignature, RecoveryId, Secp256k1};
+use tiny_keccak;
 
 pub const SIGNATURE_LENGTH: usize = 65;
 
@@ -55,12 +57,41 @@ impl Signature {
     pub fn to_payload(&self) -> Vec<u8> {
         sofabi::encode(&[sofabi::Token::Bytes(self.to_bytes())])
     }
+
+    /// address of the account that signed `message` through `sof_sign`
+    pub fn recover(&self, message: &[u8]) -> Result<Address, Error> {
+        let mut prefixed = format!("\x19Sophon Signed Message:\n{}", message.len()).into_bytes();
+        prefixed.extend_from_slice(message);
+        let hash = tiny_keccak::keccak256(&prefixed);
+
+        // `sof_sign` returns `v` as 27 or 28
+        let recovery_id = RecoveryId::from_i32(if self.v >= 27 { self.v - 27 } else { self.v } as i32)?;
+        let mut compact = [0u8; 64];
+        compact[0..32].copy_from_slice(&self.r.0[..]);
+        compact[32..64].copy_from_slice(&self.s.0[..]);
+        let signature = RecoverableSignature::from_compact(&compact, recovery_id)?;
+
+        let secp = Secp256k1::verification_only();
+        let public = secp.recover(&Message::from_slice(&hash)?, &signature)?;
+        let public = public.serialize_uncompressed();
+        Ok(tiny_keccak::keccak256(&public[1..])[12..].into())
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use quickcheck::TestResult;
+    use rustc_hex::FromHex;
+
+    #[test]
+    fn test_signature_recover() {
+        let message = "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a94243641db8f385535c0d178b8f40016048f3a3cffee8f94e68978ea4b277f57b638f0baff3454fce5edbc8cca8697c15331677e6ebccffaff3454fce5edbc8cca8697c15331677e6ebcccc".from_hex().unwrap();
+        let signature = Signature::from_bytes(&"544e77a4dbfad982648d8e4c293bc8bb5185e04417b4b073d7155c4ee55a13502b9916682913504465d77e073011df5eb78cc30026f0f04e970cfa158232e3aa1c".from_hex().unwrap()).unwrap();
+        let expected: Address = "7800992c17ab24517ef38010b5e8cb59a9ae15cc".into();
+        assert_eq!(signature.recover(&message).unwrap(), expected);
+        assert!(signature.recover(&message[1..]).unwrap() != expected);
+    }
 
     quickcheck! {
         fn quickcheck_signature_roundtrips(v: u8, r_raw: Vec<u8>, s_raw: Vec<u8>) -> TestResult {
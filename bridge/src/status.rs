@@ -0,0 +1,430 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! snapshot of a bridge node for `susy-bridge status`
+
+use contracts;
+use database::State;
+use error;
+use futures::future::{self, join_all};
+use futures::Future;
+use health::{chain_status, checkpoint_statuses, ChainStatus, CheckpointStatus};
+use helpers::{self, serialize_optional_u256, with_timeout};
+use log_stream::fetch_logs;
+use main_contract::MainContract;
+use message_to_main::MessageToMain;
+use relay_log::{Chain, Direction};
+use side_contract::SideContract;
+use std::fmt;
+use std::time::Duration;
+use susyweb;
+use susyweb::api::Namespace;
+use susyweb::types::{Address, Log, H256, U256};
+use susyweb::Transport;
+
+/// whether the contract at `address` is a bridge contract
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ContractStatus {
+    pub chain: Chain,
+    pub address: Address,
+    pub is_bridge_contract: Option<bool>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct AccountBalance {
+    pub chain: Chain,
+    #[serde(serialize_with = "serialize_optional_u256")]
+    pub balance: Option<U256>,
+    pub error: Option<String>,
+}
+
+/// a log after the checkpoint of `direction` this authority hasn't acted on yet
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PendingRelay {
+    pub direction: Direction,
+    pub tx_hash: H256,
+    pub block: Option<u64>,
+    pub message_id: Option<H256>,
+}
+
+/// output of `susy-bridge status`
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Status {
+    pub authority: Address,
+    pub main: ChainStatus,
+    pub side: ChainStatus,
+    pub contracts: Vec<ContractStatus>,
+    pub checkpoints: Vec<CheckpointStatus>,
+    pub balances: Vec<AccountBalance>,
+    pub pending: Vec<PendingRelay>,
+    /// why `pending` may be incomplete
+    pub pending_errors: Vec<String>,
+}
+
+fn split<I>(result: Result<I, error::Error>) -> (Option<I>, Option<String>) {
+    match result {
+        Ok(value) => (Some(value), None),
+        Err(err) => (None, Some(err.to_string())),
+    }
+}
+
+fn fmt_block(block: Option<u64>) -> String {
+    block.map(|block| block.to_string()).unwrap_or_else(|| "-".into())
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &(name, ref chain) in &[("main", &self.main), ("side", &self.side)] {
+            match chain.error {
+                Some(ref err) => writeln!(f, "{} chain: unreachable: {}", name, err)?,
+                None => writeln!(
+                    f,
+                    "{} chain: head {}, confirmed {}",
+                    name,
+                    fmt_block(chain.head),
+                    fmt_block(chain.confirmed)
+                )?,
+            }
+        }
+
+        writeln!(f, "\ncontracts:")?;
+        for contract in &self.contracts {
+            let check = match (contract.is_bridge_contract, &contract.error) {
+                (Some(true), _) => "ok".to_string(),
+                (Some(false), _) => "NOT a bridge contract".to_string(),
+                (None, &Some(ref err)) => format!("check failed: {}", err),
+                (None, &None) => "unknown".to_string(),
+            };
+            writeln!(f, "  {} {:?}: {}", contract.chain, contract.address, check)?;
+        }
+
+        writeln!(f, "\ncheckpoints:")?;
+        for checkpoint in &self.checkpoints {
+            writeln!(
+                f,
+                "  {:<24} block {} ({}), lag {}",
+                checkpoint.name,
+                checkpoint.block,
+                checkpoint.chain,
+                fmt_block(checkpoint.lag)
+            )?;
+        }
+
+        writeln!(f, "\nbalances of {:?}:", self.authority)?;
+        for balance in &self.balances {
+            match (&balance.balance, &balance.error) {
+                (&Some(ref value), _) => writeln!(f, "  {} {}", balance.chain, value)?,
+                (&None, &Some(ref err)) => writeln!(f, "  {} unknown: {}", balance.chain, err)?,
+                (&None, &None) => writeln!(f, "  {} unknown", balance.chain)?,
+            }
+        }
+
+        writeln!(f, "\npending relays of {:?}: {}", self.authority, self.pending.len())?;
+        for pending in &self.pending {
+            write!(f, "  {} tx {:?} at block {}", pending.direction, pending.tx_hash, fmt_block(pending.block))?;
+            if let Some(ref message_id) = pending.message_id {
+                write!(f, " message_id {:?}", message_id)?;
+            }
+            writeln!(f, "")?;
+        }
+        for err in &self.pending_errors {
+            writeln!(f, "  incomplete: {}", err)?;
+        }
+        Ok(())
+    }
+}
+
+type BoxFuture<I> = Box<Future<Item = I, Error = error::Error>>;
+
+fn failed<I: 'static>(err: error::Error) -> BoxFuture<I> {
+    Box::new(future::err(err))
+}
+
+fn pending_relay(direction: Direction, log: &Log, message_id: Option<H256>) -> Result<PendingRelay, error::Error> {
+    Ok(PendingRelay {
+        direction,
+        tx_hash: log.transaction_hash
+            .ok_or_else(|| "`log` must be mined and contain `transaction_hash`")?,
+        block: log.block_number.map(|block| block.as_u64()),
+        message_id,
+    })
+}
+
+/// queries everything `Status` contains
+pub struct StatusQuery<T> {
+    pub main: MainContract<T>,
+    pub side: SideContract<T>,
+    pub state: State,
+}
+
+impl<T: Transport + 'static> StatusQuery<T>
+where
+    T::Out: 'static,
+{
+    fn head(transport: &T, request_timeout: Duration) -> BoxFuture<u64> {
+        Box::new(with_timeout(susyweb::api::Sof::new(transport).block_number(), request_timeout).map(|head| head.as_u64()))
+    }
+
+    fn balance(&self, chain: Chain) -> Box<Future<Item = AccountBalance, Error = ()>> {
+        let future = match chain {
+            Chain::Main => with_timeout(
+                susyweb::api::Sof::new(&self.main.transport).balance(self.main.authority_address, None),
+                self.main.request_timeout,
+            ),
+            Chain::Side => with_timeout(
+                susyweb::api::Sof::new(&self.side.transport).balance(self.side.authority_address, None),
+                self.side.request_timeout,
+            ),
+        };
+        Box::new(future.then(move |result| {
+            let (balance, error) = split(result);
+            Ok::<_, ()>(AccountBalance { chain, balance, error })
+        }))
+    }
+
+    fn contracts(&self) -> Box<Future<Item = Vec<ContractStatus>, Error = ()>> {
+        let main_address = self.main.contract_address;
+        let side_address = self.side.contract_address;
+        Box::new(
+            self.main
+                .is_main_contract()
+                .then(Ok::<_, ()>)
+                .join(self.side.is_side_contract().then(Ok::<_, ()>))
+                .map(move |(main, side)| {
+                    vec![(Chain::Main, main_address, main), (Chain::Side, side_address, side)]
+                        .into_iter()
+                        .map(|(chain, address, result)| {
+                            let (is_bridge_contract, error) = split(result);
+                            ContractStatus {
+                                chain,
+                                address,
+                                is_bridge_contract,
+                                error,
+                            }
+                        })
+                        .collect()
+                }),
+        )
+    }
+
+    /// `MainToSide` relays whose message wasn't accepted by this authority yet
+    fn pending_main_to_side(&self, to: u64) -> BoxFuture<Vec<PendingRelay>> {
+        let main = self.main.clone();
+        let side = self.side.clone();
+        let logs = fetch_logs(
+            &self.main.transport,
+            self.main.contract_address,
+            &contracts::main::events::relay_message::filter(),
+            self.state.last_main_to_side_sign_at_block + 1,
+            to,
+            self.main.request_timeout,
+        );
+        Box::new(logs.and_then(move |logs| {
+            join_all(logs.into_iter().map(move |log| -> BoxFuture<Option<PendingRelay>> {
+                let parsed = match helpers::parse_log(contracts::main::events::relay_message::parse_log, &log) {
+                    Ok(parsed) => parsed,
+                    Err(err) => return failed(err.into()),
+                };
+                let pending = match pending_relay(Direction::MainToSide, &log, Some(parsed.message_id)) {
+                    Ok(pending) => pending,
+                    Err(err) => return failed(err),
+                };
+                let side = side.clone();
+                let (tx_hash, sender, recipient) = (pending.tx_hash, parsed.sender, parsed.recipient);
+                Box::new(
+                    main.relayed_message_by_id(parsed.message_id)
+                        .and_then(move |data| side.is_message_accepted_from_main(tx_hash, data, sender, recipient))
+                        .map(move |accepted| if accepted { None } else { Some(pending) }),
+                )
+            }).collect::<Vec<_>>())
+                .map(|pending| pending.into_iter().filter_map(|pending| pending).collect())
+        }))
+    }
+
+    /// `SideToMainSign` relays whose message wasn't signed by this authority yet
+    fn pending_side_to_main_sign(&self, to: u64) -> BoxFuture<Vec<PendingRelay>> {
+        let side = self.side.clone();
+        let logs = fetch_logs(
+            &self.side.transport,
+            self.side.contract_address,
+            &contracts::side::events::relay_message::filter(),
+            self.state.last_side_to_main_sign_at_block + 1,
+            to,
+            self.side.request_timeout,
+        );
+        Box::new(logs.and_then(move |logs| {
+            join_all(logs.into_iter().map(move |log| -> BoxFuture<Option<PendingRelay>> {
+                let message = match MessageToMain::from_log(&log) {
+                    Ok(message) => message,
+                    Err(err) => return failed(err),
+                };
+                let pending = match pending_relay(Direction::SideToMainSign, &log, Some(message.message_id)) {
+                    Ok(pending) => pending,
+                    Err(err) => return failed(err),
+                };
+                Box::new(
+                    side.is_side_to_main_signed_on_side(&message)
+                        .map(move |signed| if signed { None } else { Some(pending) }),
+                )
+            }).collect::<Vec<_>>())
+                .map(|pending| pending.into_iter().filter_map(|pending| pending).collect())
+        }))
+    }
+
+    /// `SideToMainSignatures` relays this authority is responsible for
+    /// whose message wasn't accepted on `main` yet
+    fn pending_side_to_main_signatures(&self, to: u64) -> BoxFuture<Vec<PendingRelay>> {
+        let main = self.main.clone();
+        let logs = fetch_logs(
+            &self.side.transport,
+            self.side.contract_address,
+            &contracts::side::events::signed_message::filter(self.side.authority_address),
+            self.state.last_side_to_main_signatures_at_block + 1,
+            to,
+            self.side.request_timeout,
+        );
+        Box::new(logs.and_then(move |logs| {
+            join_all(logs.into_iter().map(move |log| -> BoxFuture<Option<PendingRelay>> {
+                let parsed = match helpers::parse_log(contracts::side::events::signed_message::parse_log, &log) {
+                    Ok(parsed) => parsed,
+                    Err(err) => return failed(err.into()),
+                };
+                let pending = match pending_relay(Direction::SideToMainSignatures, &log, None) {
+                    Ok(pending) => pending,
+                    Err(err) => return failed(err),
+                };
+                let (payload, decoder) = contracts::main::functions::accepted_messages::call(parsed.message_hash);
+                Box::new(
+                    main.call(payload, decoder)
+                        .map(move |accepted| if accepted { None } else { Some(pending) }),
+                )
+            }).collect::<Vec<_>>())
+                .map(|pending| pending.into_iter().filter_map(|pending| pending).collect())
+        }))
+    }
+
+    /// pending relays of all directions whose chain is reachable
+    fn pending(&self, main_confirmed: Option<u64>, side_confirmed: Option<u64>) -> Box<Future<Item = (Vec<PendingRelay>, Vec<String>), Error = ()>> {
+        let mut futures: Vec<(Direction, BoxFuture<Vec<PendingRelay>>)> = Vec::new();
+        if let Some(confirmed) = main_confirmed {
+            futures.push((Direction::MainToSide, self.pending_main_to_side(confirmed)));
+        }
+        if let Some(confirmed) = side_confirmed {
+            futures.push((Direction::SideToMainSign, self.pending_side_to_main_sign(confirmed)));
+            futures.push((Direction::SideToMainSignatures, self.pending_side_to_main_signatures(confirmed)));
+        }
+
+        Box::new(join_all(
+            futures
+                .into_iter()
+                .map(|(direction, future)| future.then(move |result| Ok::<_, ()>((direction, result))))
+                .collect::<Vec<_>>(),
+        ).map(|results| {
+            let mut pending = Vec::new();
+            let mut errors = Vec::new();
+            for (direction, result) in results {
+                match result {
+                    Ok(relays) => pending.extend(relays),
+                    Err(err) => errors.push(format!("looking up pending {} relays failed: {}", direction, err)),
+                }
+            }
+            (pending, errors)
+        }))
+    }
+
+    pub fn query(self) -> Box<Future<Item = Status, Error = ()>> {
+        let heads = Self::head(&self.main.transport, self.main.request_timeout)
+            .then(Ok::<_, ()>)
+            .join(Self::head(&self.side.transport, self.side.request_timeout).then(Ok::<_, ()>));
+        let others = self.contracts()
+            .join3(self.balance(Chain::Main), self.balance(Chain::Side));
+
+        Box::new(heads.join(others).and_then(move |((main_head, side_head), (contracts, main_balance, side_balance))| {
+            let main = chain_status(main_head, self.main.required_log_confirmations);
+            let side = chain_status(side_head, self.side.required_log_confirmations);
+            let checkpoints = checkpoint_statuses(&self.state, main.confirmed, side.confirmed);
+            let mut pending_errors: Vec<String> = Vec::new();
+            if main.confirmed.is_none() {
+                pending_errors.push("main chain is unreachable".into());
+            }
+            if side.confirmed.is_none() {
+                pending_errors.push("side chain is unreachable".into());
+            }
+            let authority = self.main.authority_address;
+
+            self.pending(main.confirmed, side.confirmed)
+                .map(move |(pending, errors)| {
+                    pending_errors.extend(errors);
+                    Status {
+                        authority,
+                        main,
+                        side,
+                        contracts,
+                        checkpoints,
+                        balances: vec![main_balance, side_balance],
+                        pending,
+                        pending_errors,
+                    }
+                })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_display() {
+        let status = Status {
+            authority: "0000000000000000000000000000000000000001".into(),
+            main: chain_status(Ok(100), 12),
+            side: chain_status(Err(error::ErrorKind::TimedOut.into()), 0),
+            contracts: vec![ContractStatus {
+                chain: Chain::Main,
+                address: "0000000000000000000000000000000000000002".into(),
+                is_bridge_contract: Some(true),
+                error: None,
+            }],
+            checkpoints: checkpoint_statuses(&State::default(), Some(88), None),
+            balances: vec![AccountBalance {
+                chain: Chain::Main,
+                balance: Some(1000.into()),
+                error: None,
+            }],
+            pending: vec![PendingRelay {
+                direction: Direction::MainToSide,
+                tx_hash: 3.into(),
+                block: Some(80),
+                message_id: None,
+            }],
+            pending_errors: vec!["side chain is unreachable".into()],
+        };
+
+        let text = status.to_string();
+        assert!(text.contains("main chain: head 100, confirmed 88"));
+        assert!(text.contains("side chain: unreachable: Request timed out"));
+        assert!(text.contains("main 0x0000000000000000000000000000000000000002: ok"));
+        assert!(text.contains("main_to_side_sign        block 0 (main), lag 88"));
+        assert!(text.contains("pending relays of 0x0000000000000000000000000000000000000001: 1"));
+        assert!(text.contains("incomplete: side chain is unreachable"));
+
+        let json = ::serde_json::to_value(&status).unwrap();
+        assert_eq!(json["balances"][0]["balance"], "1000");
+        assert_eq!(json["pending"][0]["direction"], "main_to_side");
+    }
+}
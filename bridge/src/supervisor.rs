@@ -0,0 +1,151 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! keeping the `Bridge` running through transient errors.
+//!
+//! timeouts and failing nodes are expected while the bridge runs for weeks.
+//! instead of exiting and depending on an outside supervisor the `Bridge` is
+//! restarted from its last yielded (and therefore persisted) state.
+
+use bridge::Bridge;
+use database::State;
+use error::{self, ResultExt};
+use futures::{Async, Future, Poll, Stream};
+use std::cmp;
+use std::time::Duration;
+use susyweb::Transport;
+use tokio_timer::{Sleep, Timer};
+
+/// seconds to wait before the first restart after an error
+const INITIAL_RESTART_DELAY: u64 = 1;
+/// delays double with every consecutive error up to this many seconds
+const MAX_RESTART_DELAY: u64 = 300;
+
+/// exponentially growing delays between consecutive restarts
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Backoff {
+            initial,
+            max,
+            next: initial,
+        }
+    }
+
+    /// the delay to wait now. doubles the delay for the next call
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = cmp::min(self.next * 2, self.max);
+        delay
+    }
+
+    /// to be called once the bridge makes progress again
+    pub fn reset(&mut self) {
+        self.next = self.initial;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff::new(
+            Duration::from_secs(INITIAL_RESTART_DELAY),
+            Duration::from_secs(MAX_RESTART_DELAY),
+        )
+    }
+}
+
+/// `Stream` of the states yielded by `bridge` that restarts it after transient errors.
+/// fails on fatal errors only
+pub struct Supervised<T: Transport> {
+    bridge: Bridge<T>,
+    backoff: Backoff,
+    timer: Timer,
+    /// waiting to restart
+    restart: Option<Sleep>,
+}
+
+impl<T: Transport> Supervised<T> {
+    pub fn new(bridge: Bridge<T>, backoff: Backoff) -> Self {
+        Supervised {
+            bridge,
+            backoff,
+            timer: Timer::default(),
+            restart: None,
+        }
+    }
+}
+
+impl<T: Transport> Stream for Supervised<T> {
+    type Item = State;
+    type Error = error::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(ref mut restart) = self.restart {
+                try_ready!(restart.poll().chain_err(|| "Supervised: waiting to restart the bridge failed"));
+            }
+            if self.restart.take().is_some() {
+                info!("restarting the bridge");
+                self.bridge.restart();
+            }
+
+            match self.bridge.poll() {
+                Ok(Async::Ready(Some(state))) => {
+                    self.backoff.reset();
+                    return Ok(Async::Ready(Some(state)));
+                }
+                Ok(Async::Ready(None)) => return Ok(Async::Ready(None)),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(err) => {
+                    if !err.is_transient() || self.bridge.is_shutting_down() {
+                        return Err(err);
+                    }
+                    let delay = self.backoff.next_delay();
+                    warn!(
+                        "bridge failed with a transient error. restarting in {:?}: {}",
+                        delay,
+                        err.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": ")
+                    );
+                    self.restart = Some(self.timer.sleep(delay));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_max_and_resets() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(2));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(4));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(5));
+        assert_eq!(backoff.next_delay(), Duration::from_secs(5));
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,539 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! end-to-end tracking of messages.
+//!
+//! the relays of a bridge node only cover the part of a message its own authority
+//! is responsible for. `MessageTracker` follows every message through all its events
+//! on both chains until it has arrived on the other chain:
+//!
+//! - `main` to `side`: `Main.RelayMessage` -> `Side.AcceptedMessage`
+//! - `side` to `main`: `Side.RelayMessage` -> `Side.SignedMessage` -> `Main.AcceptedMessage`
+//!
+//! events are linked by message id. `Side.SignedMessage` only contains the hash of
+//! the `MessageToMain`. that hash is linked to the message id through the
+//! `Side.RelayMessage` the `MessageToMain` is built from.
+//!
+//! messages that haven't arrived `stuck_after_blocks` blocks after they were relayed
+//! are reported as stuck through logs and metrics.
+//! only messages relayed after the tracker was started are followed.
+
+use error;
+use events::BridgeEvent;
+use futures::{Future, Stream};
+use log_stream::{LogStream, LogsInBlockRange};
+use message_to_main::MessageToMain;
+use metrics::{MetricFamily, MetricKind};
+use relay_log::Chain;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use susyweb::types::{Address, Log, H256};
+use susyweb::Transport;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageDirection {
+    MainToSide,
+    SideToMain,
+}
+
+impl MessageDirection {
+    /// the chain the message is relayed on
+    pub fn source(&self) -> Chain {
+        match *self {
+            MessageDirection::MainToSide => Chain::Main,
+            MessageDirection::SideToMain => Chain::Side,
+        }
+    }
+
+    /// the chain the message is accepted on
+    pub fn target(&self) -> Chain {
+        match *self {
+            MessageDirection::MainToSide => Chain::Side,
+            MessageDirection::SideToMain => Chain::Main,
+        }
+    }
+}
+
+impl fmt::Display for MessageDirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            MessageDirection::MainToSide => "main_to_side",
+            MessageDirection::SideToMain => "side_to_main",
+        })
+    }
+}
+
+const DIRECTIONS: [MessageDirection; 2] = [MessageDirection::MainToSide, MessageDirection::SideToMain];
+
+/// an event in the lifecycle of a message
+#[derive(Debug, Clone, PartialEq)]
+pub enum LifecycleEvent {
+    /// `RelayMessage` on the source chain
+    Relayed {
+        direction: MessageDirection,
+        message_id: H256,
+        tx_hash: H256,
+        sender: Address,
+        recipient: Address,
+    },
+    /// `Side.SignedMessage`. all signatures for a `side` to `main` message were collected
+    Signed { message_hash: H256 },
+    /// `AcceptedMessage` on the target chain. the message has arrived
+    Accepted {
+        direction: MessageDirection,
+        message_id: H256,
+    },
+}
+
+impl LifecycleEvent {
+    /// parses a log of a `message_lifecycle_log_stream` of `chain`.
+    /// returns `None` for logs of other events.
+    pub fn from_log(chain: Chain, log: &Log) -> Result<Option<Self>, error::Error> {
+        Ok(BridgeEvent::from_log(chain, log)?.map(LifecycleEvent::from))
+    }
+}
+
+impl From<BridgeEvent> for LifecycleEvent {
+    fn from(event: BridgeEvent) -> Self {
+        match event {
+            BridgeEvent::MainRelayMessage {
+                origin,
+                message_id,
+                sender,
+                recipient,
+            } => LifecycleEvent::Relayed {
+                direction: MessageDirection::MainToSide,
+                message_id,
+                tx_hash: origin.transaction_hash,
+                sender,
+                recipient,
+            },
+            BridgeEvent::SideRelayMessage {
+                origin,
+                message_id,
+                sender,
+                recipient,
+            } => LifecycleEvent::Relayed {
+                direction: MessageDirection::SideToMain,
+                message_id,
+                tx_hash: origin.transaction_hash,
+                sender,
+                recipient,
+            },
+            BridgeEvent::MainAcceptedMessage { message_id, .. } => LifecycleEvent::Accepted {
+                direction: MessageDirection::SideToMain,
+                message_id,
+            },
+            BridgeEvent::SideAcceptedMessage { message_id, .. } => LifecycleEvent::Accepted {
+                direction: MessageDirection::MainToSide,
+                message_id,
+            },
+            BridgeEvent::SideSignedMessage { message_hash, .. } => LifecycleEvent::Signed { message_hash },
+        }
+    }
+}
+
+/// a message that was relayed but hasn't arrived yet
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrackedMessage {
+    pub direction: MessageDirection,
+    pub message_id: H256,
+    pub source_tx_hash: H256,
+    pub sender: Address,
+    pub recipient: Address,
+    /// block on the source chain
+    pub relayed_at_block: u64,
+    /// block on `side` at which all signatures were collected. only for `side` to `main`
+    pub signed_at_block: Option<u64>,
+    pub stuck: bool,
+}
+
+impl TrackedMessage {
+    /// the message authorities sign. only for `side` to `main`
+    pub fn message_to_main(&self) -> Option<MessageToMain> {
+        match self.direction {
+            MessageDirection::MainToSide => None,
+            MessageDirection::SideToMain => Some(MessageToMain {
+                side_tx_hash: self.source_tx_hash,
+                message_id: self.message_id,
+                sender: self.sender,
+                recipient: self.recipient,
+            }),
+        }
+    }
+
+    /// the last step the message completed
+    pub fn stage(&self) -> &'static str {
+        if self.signed_at_block.is_some() {
+            "signed"
+        } else {
+            "relayed"
+        }
+    }
+}
+
+/// state of all messages in flight. fed by `track`
+pub struct MessageTracker {
+    stuck_after_blocks: u64,
+    pending: HashMap<(MessageDirection, H256), TrackedMessage>,
+    /// `MessageToMain` hash -> message id of pending `side` to `main` messages
+    message_ids_by_hash: HashMap<H256, H256>,
+    /// block of `AcceptedMessage`s whose `RelayMessage` wasn't seen yet.
+    /// both chains are followed independently and with different confirmations
+    /// so a message can be seen arriving before it is seen leaving.
+    accepted_early: HashMap<(MessageDirection, H256), u64>,
+    main_head: u64,
+    side_head: u64,
+    completed: HashMap<MessageDirection, u64>,
+    /// called with every message that arrived
+    arrived_callbacks: Vec<Box<FnMut(&TrackedMessage)>>,
+}
+
+impl MessageTracker {
+    /// `main_head` and `side_head` are the blocks the log streams start after
+    pub fn new(stuck_after_blocks: u64, main_head: u64, side_head: u64) -> Self {
+        Self {
+            stuck_after_blocks,
+            pending: HashMap::new(),
+            message_ids_by_hash: HashMap::new(),
+            accepted_early: HashMap::new(),
+            main_head,
+            side_head,
+            completed: HashMap::new(),
+            arrived_callbacks: Vec::new(),
+        }
+    }
+
+    /// calls `callback` with every message that arrives from now on
+    pub fn on_arrived<F: FnMut(&TrackedMessage) + 'static>(&mut self, callback: F) {
+        self.arrived_callbacks.push(Box::new(callback));
+    }
+
+    /// last block checked on `chain`
+    pub fn head(&self, chain: Chain) -> u64 {
+        match chain {
+            Chain::Main => self.main_head,
+            Chain::Side => self.side_head,
+        }
+    }
+
+    /// all messages in flight, oldest first
+    pub fn pending(&self) -> Vec<&TrackedMessage> {
+        let mut pending = self.pending.values().collect::<Vec<_>>();
+        pending.sort_by_key(|message| (message.direction.source() == Chain::Side, message.relayed_at_block));
+        pending
+    }
+
+    pub fn completed(&self, direction: MessageDirection) -> u64 {
+        self.completed.get(&direction).cloned().unwrap_or(0)
+    }
+
+    fn complete(&mut self, message: &TrackedMessage) {
+        *self.completed.entry(message.direction).or_insert(0) += 1;
+        for callback in &mut self.arrived_callbacks {
+            callback(message);
+        }
+    }
+
+    /// applies `event` that happened in `block`
+    pub fn apply(&mut self, event: LifecycleEvent, block: u64) {
+        match event {
+            LifecycleEvent::Relayed {
+                direction,
+                message_id,
+                tx_hash,
+                sender,
+                recipient,
+            } => {
+                let message = TrackedMessage {
+                    direction,
+                    message_id,
+                    source_tx_hash: tx_hash,
+                    sender,
+                    recipient,
+                    relayed_at_block: block,
+                    signed_at_block: None,
+                    stuck: false,
+                };
+                if self.accepted_early.remove(&(direction, message_id)).is_some() {
+                    debug!("MessageTracker: {} message {:?} has arrived", direction, message_id);
+                    self.complete(&message);
+                    return;
+                }
+                if let Some(message_to_main) = message.message_to_main() {
+                    self.message_ids_by_hash.insert(message_to_main.keccak256(), message_id);
+                }
+                self.pending.insert((direction, message_id), message);
+            }
+            LifecycleEvent::Signed { message_hash } => {
+                let message_id = self.message_ids_by_hash.get(&message_hash).cloned();
+                let message =
+                    message_id.and_then(|id| self.pending.get_mut(&(MessageDirection::SideToMain, id)));
+                match message {
+                    Some(message) => message.signed_at_block = Some(block),
+                    None => debug!(
+                        "MessageTracker: ignoring signatures for message {:?} relayed before tracking started",
+                        message_hash
+                    ),
+                }
+            }
+            LifecycleEvent::Accepted { direction, message_id } => {
+                match self.pending.remove(&(direction, message_id)) {
+                    Some(message) => {
+                        if let Some(message_to_main) = message.message_to_main() {
+                            self.message_ids_by_hash.remove(&message_to_main.keccak256());
+                        }
+                        if message.stuck {
+                            info!(
+                                "{} message {:?} has arrived on {} after being stuck",
+                                direction,
+                                message_id,
+                                direction.target()
+                            );
+                        } else {
+                            debug!("MessageTracker: {} message {:?} has arrived", direction, message_id);
+                        }
+                        self.complete(&message);
+                    }
+                    None => {
+                        self.accepted_early.insert((direction, message_id), block);
+                    }
+                }
+            }
+        }
+    }
+
+    /// all logs up to `head` on `chain` were applied.
+    /// reports messages that became stuck.
+    pub fn update_head(&mut self, chain: Chain, head: u64) {
+        match chain {
+            Chain::Main => self.main_head = head,
+            Chain::Side => self.side_head = head,
+        }
+
+        // acceptances of messages relayed before tracking started would pile up otherwise
+        let stuck_after_blocks = self.stuck_after_blocks;
+        let expired = self.accepted_early
+            .iter()
+            .filter(|&(&(direction, _), &block)| {
+                direction.target() == chain && head.saturating_sub(block) > stuck_after_blocks
+            })
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        for key in expired {
+            self.accepted_early.remove(&key);
+        }
+
+        for message in self.pending.values_mut() {
+            if message.stuck || message.direction.source() != chain {
+                continue;
+            }
+            let age = head.saturating_sub(message.relayed_at_block);
+            if age > stuck_after_blocks {
+                message.stuck = true;
+                warn!(
+                    "{} message {:?} relayed in tx {:?} at block {} on {} hasn't arrived on {} after {} blocks. last step: {}",
+                    message.direction,
+                    message.message_id,
+                    message.source_tx_hash,
+                    message.relayed_at_block,
+                    message.direction.source(),
+                    message.direction.target(),
+                    age,
+                    message.stage()
+                );
+            }
+        }
+    }
+
+    /// applies all logs in `range` of a `message_lifecycle_log_stream` of `chain`
+    pub fn on_logs(&mut self, chain: Chain, range: &LogsInBlockRange) {
+        for log in &range.logs {
+            let block = log.block_number
+                .map(|block| block.as_u64())
+                .unwrap_or(range.to);
+            match LifecycleEvent::from_log(chain, log) {
+                Ok(Some(event)) => self.apply(event, block),
+                Ok(None) => {}
+                Err(err) => warn!("MessageTracker: ignoring log that can't be parsed: {}", err),
+            }
+        }
+        self.update_head(chain, range.to);
+    }
+
+    pub fn metrics(&self) -> Vec<MetricFamily> {
+        let mut pending = MetricFamily::new(
+            "bridge_messages_pending",
+            "messages relayed that haven't arrived on the other chain yet",
+            MetricKind::Gauge,
+        );
+        let mut stuck = MetricFamily::new(
+            "bridge_messages_stuck",
+            "pending messages older than stuck_after_blocks",
+            MetricKind::Gauge,
+        );
+        let mut oldest = MetricFamily::new(
+            "bridge_messages_oldest_pending_age_blocks",
+            "blocks since the oldest pending message was relayed",
+            MetricKind::Gauge,
+        );
+        let mut completed = MetricFamily::new(
+            "bridge_messages_completed_total",
+            "messages that arrived on the other chain since the bridge was started",
+            MetricKind::Counter,
+        );
+
+        for direction in DIRECTIONS.iter().cloned() {
+            let messages = self.pending
+                .values()
+                .filter(|message| message.direction == direction)
+                .collect::<Vec<_>>();
+            let head = self.head(direction.source());
+            let labels = || vec![("direction", direction.to_string())];
+
+            pending = pending.sample(labels(), messages.len() as u64);
+            stuck = stuck.sample(labels(), messages.iter().filter(|message| message.stuck).count() as u64);
+            oldest = oldest.sample(
+                labels(),
+                messages
+                    .iter()
+                    .map(|message| head.saturating_sub(message.relayed_at_block))
+                    .max()
+                    .unwrap_or(0),
+            );
+            completed = completed.sample(labels(), self.completed(direction));
+        }
+
+        let head = MetricFamily::new(
+            "bridge_tracker_head_block",
+            "last block checked for message events",
+            MetricKind::Gauge,
+        ).sample(vec![("chain", Chain::Main.to_string())], self.main_head)
+            .sample(vec![("chain", Chain::Side.to_string())], self.side_head);
+
+        vec![pending, stuck, oldest, completed, head]
+    }
+}
+
+/// `Future` that feeds all logs of `main_logs` and `side_logs` into `tracker`.
+/// `main_logs` and `side_logs` must be `message_lifecycle_log_stream`s.
+pub fn track<T: Transport + 'static>(
+    tracker: Rc<RefCell<MessageTracker>>,
+    main_logs: LogStream<T>,
+    side_logs: LogStream<T>,
+) -> Box<Future<Item = (), Error = ()>>
+where
+    T::Out: 'static,
+{
+    let main_logs = main_logs.map(|range| (Chain::Main, range));
+    let side_logs = side_logs.map(|range| (Chain::Side, range));
+    Box::new(
+        main_logs
+            .select(side_logs)
+            .for_each(move |(chain, range)| {
+                tracker.borrow_mut().on_logs(chain, &range);
+                Ok(())
+            })
+            .map_err(|err| error!("MessageTracker: stopped tracking messages: {}", err)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relayed(direction: MessageDirection, id: u64) -> LifecycleEvent {
+        LifecycleEvent::Relayed {
+            direction,
+            message_id: id.into(),
+            tx_hash: (id + 100).into(),
+            sender: "aff3454fce5edbc8cca8697c15331677e6ebccff".into(),
+            recipient: "aff3454fce5edbc8cca8697c15331677e6ebcccc".into(),
+        }
+    }
+
+    fn accepted(direction: MessageDirection, id: u64) -> LifecycleEvent {
+        LifecycleEvent::Accepted {
+            direction,
+            message_id: id.into(),
+        }
+    }
+
+    #[test]
+    fn test_tracker_main_to_side_arrives() {
+        let mut tracker = MessageTracker::new(10, 0, 0);
+        let arrived = Rc::new(RefCell::new(Vec::new()));
+        let arrived_clone = arrived.clone();
+        tracker.on_arrived(move |message| arrived_clone.borrow_mut().push(message.message_id));
+
+        tracker.apply(relayed(MessageDirection::MainToSide, 1), 5);
+        tracker.update_head(Chain::Main, 10);
+        assert_eq!(tracker.pending().len(), 1);
+        assert_eq!(tracker.pending()[0].stage(), "relayed");
+
+        tracker.apply(accepted(MessageDirection::MainToSide, 1), 3);
+        assert!(tracker.pending().is_empty());
+        assert_eq!(tracker.completed(MessageDirection::MainToSide), 1);
+        assert_eq!(tracker.completed(MessageDirection::SideToMain), 0);
+        assert_eq!(*arrived.borrow(), vec![H256::from(1)]);
+    }
+
+    #[test]
+    fn test_tracker_side_to_main_links_signatures_and_reports_stuck() {
+        let mut tracker = MessageTracker::new(10, 0, 0);
+        tracker.apply(relayed(MessageDirection::SideToMain, 1), 5);
+        let message_hash = tracker.pending()[0].message_to_main().unwrap().keccak256();
+        tracker.apply(LifecycleEvent::Signed { message_hash }, 7);
+        assert_eq!(tracker.pending()[0].signed_at_block, Some(7));
+        assert_eq!(tracker.pending()[0].stage(), "signed");
+
+        // age is measured on the source chain
+        tracker.update_head(Chain::Main, 100);
+        assert!(!tracker.pending()[0].stuck);
+        tracker.update_head(Chain::Side, 15);
+        assert!(!tracker.pending()[0].stuck);
+        tracker.update_head(Chain::Side, 16);
+        assert!(tracker.pending()[0].stuck);
+
+        let metrics = tracker.metrics();
+        assert_eq!(metrics[1].name, "bridge_messages_stuck");
+        assert_eq!(metrics[1].samples[1].value, 1);
+        assert_eq!(metrics[2].samples[1].value, 11);
+
+        tracker.apply(accepted(MessageDirection::SideToMain, 1), 110);
+        assert!(tracker.pending().is_empty());
+        assert!(tracker.message_ids_by_hash.is_empty());
+        assert_eq!(tracker.completed(MessageDirection::SideToMain), 1);
+    }
+
+    #[test]
+    fn test_tracker_accepted_before_relayed_is_seen() {
+        let mut tracker = MessageTracker::new(10, 0, 0);
+        tracker.apply(accepted(MessageDirection::MainToSide, 1), 8);
+        tracker.apply(accepted(MessageDirection::MainToSide, 2), 3);
+        // acceptance of message 2 expires. it was relayed before tracking started
+        tracker.update_head(Chain::Side, 14);
+
+        tracker.apply(relayed(MessageDirection::MainToSide, 1), 5);
+        tracker.apply(relayed(MessageDirection::MainToSide, 2), 5);
+        assert_eq!(tracker.completed(MessageDirection::MainToSide), 1);
+        assert_eq!(tracker.pending().len(), 1);
+        assert_eq!(tracker.pending()[0].message_id, 2.into());
+    }
+}
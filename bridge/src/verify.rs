@@ -0,0 +1,177 @@
+// Copyleft 2017 Superstring.Community
+// This file is part of Susy-Bridge.
+
+// Susy-Bridge is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Susy-Bridge is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MSRCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Susy-Bridge.  If not, see <http://www.gnu.org/licenses/>.
+
+//! verification of deployed bridge contracts for `susy-bridge-deploy`.
+//!
+//! for each contract checks that:
+//!
+//! - its code is the runtime part of the compiled contract in `config`
+//! - it reports to be a bridge contract
+//! - it was deployed with the `authorities` in `config`
+
+use authority_set::{main_authority_set, side_authority_set, AuthoritySet};
+use check::{describe, is_bridge_contract, Check, Report};
+use config::{Config, NodeConfig};
+use error;
+use futures::future;
+use futures::Future;
+use helpers::with_timeout;
+use main_contract::MainContract;
+use relay_log::Chain;
+use side_contract::SideContract;
+use susyweb;
+use susyweb::api::Namespace;
+use susyweb::types::{Address, Bytes};
+use susyweb::Transport;
+
+type CheckFuture = Box<Future<Item = Vec<Check>, Error = ()>>;
+
+/// whether `code` is the runtime part of `creation_code`.
+/// the constructor copies the runtime code from the creation code
+/// so the runtime code is a part of it
+pub fn is_runtime_of(code: &[u8], creation_code: &[u8]) -> bool {
+    !code.is_empty() && creation_code.windows(code.len()).any(|window| window == code)
+}
+
+fn code_check(address: Address, code: Result<Bytes, String>, creation_code: Option<&[u8]>) -> Result<String, String> {
+    let code = code?;
+    let creation_code = creation_code.ok_or_else(|| "no `contract.bin` configured to compare the code with".to_string())?;
+    if code.0.is_empty() {
+        Err(format!("there is no contract at {:?}", address))
+    } else if is_runtime_of(&code.0, creation_code) {
+        Ok(format!("code at {:?} matches the compiled contract", address))
+    } else {
+        Err(format!("code at {:?} doesn't match the compiled contract", address))
+    }
+}
+
+fn authorities_check(authority_set: Result<AuthoritySet, String>, expected: &AuthoritySet) -> Result<String, String> {
+    let authority_set = authority_set?;
+    let differences = authority_set.differences(expected);
+    if differences.is_empty() {
+        Ok(format!(
+            "{} of {} authorities as configured",
+            authority_set.required_signatures,
+            authority_set.accounts.len()
+        ))
+    } else {
+        Err(format!("differs from `authorities` in the config: {}", differences.join(", ")))
+    }
+}
+
+/// `Future` resolving to an error if the code at `address` isn't the runtime part of `node.contract`.
+/// resolves right away if no compiled contract is configured
+pub fn verify_code<T: Transport>(transport: &T, node: &NodeConfig, address: Address) -> Box<Future<Item = (), Error = error::Error>>
+where
+    T::Out: 'static,
+{
+    let creation_code = match node.contract {
+        Some(ref contract) => contract.bin.0.clone(),
+        None => return Box::new(future::ok(())),
+    };
+    Box::new(
+        with_timeout(susyweb::api::Sof::new(transport).code(address, None), node.request_timeout).then(move |code| {
+            code_check(address, describe(code), Some(&creation_code[..]))
+                .map(|_| ())
+                .map_err(error::Error::from)
+        }),
+    )
+}
+
+/// verifies deployed contracts. see module docs
+pub struct Verify<T> {
+    pub config: Config,
+    pub main: MainContract<T>,
+    pub side: SideContract<T>,
+}
+
+impl<T: Transport + 'static> Verify<T>
+where
+    T::Out: 'static,
+{
+    /// `Future` resolving to the outcome of all checks. never fails
+    pub fn run(&self) -> Box<Future<Item = Report, Error = ()>> {
+        Box::new(self.check_main().join(self.check_side()).map(|(main, side)| Report {
+            checks: main.into_iter().chain(side).collect(),
+        }))
+    }
+
+    fn check_main(&self) -> CheckFuture {
+        let address = self.main.contract_address;
+        let creation_code = self.config.main.contract.as_ref().map(|contract| contract.bin.0.clone());
+        let expected = AuthoritySet::from_config(&self.config.authorities);
+        let code = with_timeout(
+            susyweb::api::Sof::new(&self.main.transport).code(address, None),
+            self.main.request_timeout,
+        ).then(Ok::<_, ()>);
+        let is_bridge = self.main.is_main_contract().then(Ok::<_, ()>);
+        let authority_set = main_authority_set(&self.main).then(Ok::<_, ()>);
+        Box::new(code.join3(is_bridge, authority_set).map(move |(code, is_bridge, authority_set)| {
+            checks(Chain::Main, address, describe(code), creation_code.as_ref().map(Vec::as_slice), describe(is_bridge), describe(authority_set), &expected)
+        }))
+    }
+
+    fn check_side(&self) -> CheckFuture {
+        let address = self.side.contract_address;
+        let creation_code = self.config.side.contract.as_ref().map(|contract| contract.bin.0.clone());
+        let expected = AuthoritySet::from_config(&self.config.authorities);
+        let code = with_timeout(
+            susyweb::api::Sof::new(&self.side.transport).code(address, None),
+            self.side.request_timeout,
+        ).then(Ok::<_, ()>);
+        let is_bridge = self.side.is_side_contract().then(Ok::<_, ()>);
+        let authority_set = side_authority_set(&self.side).then(Ok::<_, ()>);
+        Box::new(code.join3(is_bridge, authority_set).map(move |(code, is_bridge, authority_set)| {
+            checks(Chain::Side, address, describe(code), creation_code.as_ref().map(Vec::as_slice), describe(is_bridge), describe(authority_set), &expected)
+        }))
+    }
+}
+
+fn checks(
+    chain: Chain,
+    address: Address,
+    code: Result<Bytes, String>,
+    creation_code: Option<&[u8]>,
+    is_bridge: Result<bool, String>,
+    authority_set: Result<AuthoritySet, String>,
+    expected: &AuthoritySet,
+) -> Vec<Check> {
+    vec![
+        Check::new(format!("{} code", chain), code_check(address, code, creation_code)),
+        Check::new(format!("{} contract", chain), is_bridge_contract(address, is_bridge)),
+        Check::new(format!("{} authorities", chain), authorities_check(authority_set, expected)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_runtime_of() {
+        let creation_code = [0x60, 0x80, 0x01, 0x02, 0x03, 0x04, 0x00, 0x29];
+        assert!(is_runtime_of(&[0x01, 0x02, 0x03, 0x04], &creation_code));
+        assert!(!is_runtime_of(&[0x01, 0x03], &creation_code));
+        assert!(!is_runtime_of(&[], &creation_code));
+
+        let address = Address::default();
+        let compiled = Some(&creation_code[..]);
+        assert!(code_check(address, Ok(Bytes(vec![0x02, 0x03])), compiled).is_ok());
+        assert!(code_check(address, Ok(Bytes(Vec::new())), compiled).is_err());
+        assert!(code_check(address, Err("timed out".into()), compiled).is_err());
+        assert!(code_check(address, Ok(Bytes(vec![0x02, 0x03])), None).is_err());
+    }
+}
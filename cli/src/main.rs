@@ -22,36 +22,106 @@ extern crate log;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde_json;
 extern crate tokio_core;
+extern crate tokio_signal;
 extern crate susyweb;
 
 use docopt::Docopt;
-use futures::Stream;
+use futures::future::{self, Either};
+use futures::{Future, Stream};
+use std::cell::RefCell;
+use std::cmp;
 use std::env;
+use std::fs;
+use std::io::{self, Write};
 use std::path::PathBuf;
-use tokio_core::reactor::Core;
+use std::process;
+use std::rc::Rc;
+use std::time::Duration;
+use tokio_core::reactor::{Core, Timeout};
+use tokio_signal::unix::{Signal, SIGHUP, SIGINT, SIGTERM};
 use susyweb::transports::http::Http;
+use susyweb::types::{Address, H256};
 
+use bridge::admin::AdminApi;
+use bridge::balance_monitor::BalanceMonitor;
+use bridge::check::{Preflight, Report};
 use bridge::config::Config;
-use bridge::database::{Database, TomlFileDatabase};
-use bridge::error::{self, ResultExt};
+use bridge::authority_set::{self, AuthoritySet, AuthorityWatch, OnChainAuthorities};
+use bridge::database::{Database, State, TomlFileDatabase};
+use bridge::error::{self, ErrorKind, ResultExt};
+use bridge::health::HealthCheck;
+use bridge::init::{self, InitOptions};
+use bridge::manifest::DeploymentManifest;
 use bridge::helpers::StreamExt;
+use bridge::http_server::{self, Response};
+use bridge::manual_relay::{self, ManualRelay};
+use bridge::metrics;
+use bridge::participation::{self, ParticipationLookup, ParticipationMonitor};
+use bridge::quarantine;
+use bridge::relay_log::{self, Direction};
+use bridge::rewind::{self, Rewind, Target};
+use bridge::status::StatusQuery;
+use bridge::supervisor::{Backoff, Supervised};
+use bridge::tracker::{self, MessageTracker};
+use bridge::verify::{self, Verify};
 
 const MAX_PARALLEL_REQUESTS: usize = 10;
 
+/// seconds between reads of the authorities of the contracts
+const AUTHORITIES_POLL_INTERVAL: u64 = 300;
+
+/// timeout in seconds of the requests `init` sends to the nodes
+const INIT_REQUEST_TIMEOUT: u64 = 5;
+
+const EXIT_ERROR: i32 = 1;
+/// relays were still in flight when the shutdown timeout expired
+const EXIT_SHUTDOWN_TIMED_OUT: i32 = 2;
+
 #[derive(Debug, Deserialize)]
 pub struct Args {
-    arg_config: PathBuf,
+    cmd_status: bool,
+    cmd_relay: bool,
+    cmd_rewind: bool,
+    cmd_check: bool,
+    cmd_init: bool,
+    arg_config: Vec<PathBuf>,
     arg_database: PathBuf,
+    flag_json: bool,
+    flag_print_config: bool,
+    flag_direction: String,
+    flag_tx: String,
+    flag_block: Option<u64>,
+    flag_timestamp: Option<u64>,
+    flag_log_format: String,
+    flag_shutdown_timeout: u64,
+    flag_main_http: Option<String>,
+    flag_side_http: Option<String>,
+    flag_address: Option<String>,
+    flag_authorities: Option<String>,
+    flag_required_signatures: Option<u32>,
+    flag_main_contract: Option<String>,
+    flag_side_contract: Option<String>,
+    flag_main_bin: PathBuf,
+    flag_side_bin: PathBuf,
+    flag_force: bool,
+    flag_manifest: Option<PathBuf>,
 }
 
 fn main() {
-    let _ = env_logger::init();
     let result = execute(env::args());
 
     match result {
         Ok(s) => println!("{}", s),
-        Err(err) => print_err(err),
+        Err(err) => {
+            let code = match *err.kind() {
+                ErrorKind::ShutdownTimedOut => EXIT_SHUTDOWN_TIMED_OUT,
+                _ => EXIT_ERROR,
+            };
+            print_err(err);
+            process::exit(code);
+        }
     }
 }
 
@@ -63,6 +133,52 @@ fn print_err(err: error::Error) {
     println!("{}", message);
 }
 
+/// sets up `env_logger` (filtered through `RUST_LOG`) to log in `log_format`
+/// which is either `text` or `json`.
+/// in `json` format every log line is a JSON object. the fields of
+/// relay log lines are merged into that object so they can be indexed.
+fn init_logger(log_format: &str) -> Result<(), error::Error> {
+    let mut builder = env_logger::Builder::new();
+    if let Ok(filters) = env::var("RUST_LOG") {
+        builder.parse(&filters);
+    }
+
+    match log_format {
+        "text" => {}
+        "json" => {
+            relay_log::set_json_output(true);
+            builder.format(|buf, record| {
+                let mut line = serde_json::Map::new();
+                line.insert("timestamp".into(), buf.timestamp().to_string().into());
+                line.insert("level".into(), record.level().to_string().into());
+                line.insert("target".into(), record.target().into());
+
+                let message = record.args().to_string();
+                let relay_fields = if record.target() == relay_log::RELAY_LOG_TARGET {
+                    serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&message).ok()
+                } else {
+                    None
+                };
+                match relay_fields {
+                    Some(fields) => line.extend(fields),
+                    None => {
+                        line.insert("message".into(), message.into());
+                    }
+                }
+
+                writeln!(buf, "{}", serde_json::Value::Object(line))
+            });
+        }
+        other => {
+            return Err(format!("unknown log format {:?}. expected `text` or `json`", other).into())
+        }
+    }
+
+    // fails only if a logger is already set
+    let _ = builder.try_init();
+    Ok(())
+}
+
 fn execute<S, I>(command: I) -> Result<String, error::Error>
 where
     I: IntoIterator<Item = S>,
@@ -76,26 +192,103 @@ Susy-bridge
     Commit: {}
 
 Usage:
-    susy-bridge --config <config> --database <database>
+    susy-bridge (--config <config>)... --database <database> [--log-format <format>] [--shutdown-timeout <seconds>]
+    susy-bridge status (--config <config>)... --database <database> [--json] [--log-format <format>]
+    susy-bridge relay (--config <config>)... --database <database> --direction <direction> --tx <hash> [--log-format <format>]
+    susy-bridge rewind (--config <config>)... --database <database> --direction <direction> (--block <block> | --timestamp <timestamp>) [--log-format <format>]
+    susy-bridge check (--config <config>)... --database <database> [--log-format <format>]
+    susy-bridge init --config <config> --database <database> [--main-http <url>] [--side-http <url>] [--address <address>] [--authorities <addresses>] [--required-signatures <n>] [--main-contract <address> --side-contract <address> | --manifest <manifest>] [--main-bin <path>] [--side-bin <path>] [--force] [--log-format <format>]
+    susy-bridge --print-config (--config <config>)...
     susy-bridge -h | --help
 
+Commands:
+    status                   Print the state of both chains, checkpoints, balances
+                             and relays this authority still has to act on.
+    relay                    Relay the messages of a single transaction once.
+                             Does not change the checkpoints in the database.
+    rewind                   Move checkpoints back so that later logs are relayed
+                             again. Stop the bridge before running it.
+    check                    Verify the config, both nodes, the contracts, the
+                             authority account and its balances. Exits non-zero
+                             if any check fails.
+    init                     Write a commented config for a new authority and,
+                             given the contract addresses or a deployment
+                             manifest, its database.
+                             Asks for values not passed as options.
+
+Config:
+    Every further `--config` overrides the values of the files before it.
+    Environment variables `SUSY_BRIDGE_<KEY>` override the files. `__` separates
+    nested keys, e.g. `SUSY_BRIDGE_MAIN__HTTP` sets `main.http`.
+
 Options:
-    -h, --help           Display help message and exit.
+    -h, --help               Display help message and exit.
+    --print-config           Print the merged config with secrets redacted and exit.
+    --direction <direction>  Relay to run: `main-to-side`, `side-to-main-sign`
+                             or `side-to-main-submit`. `rewind` also accepts `all`.
+    --tx <hash>              Hash of the transaction on the source chain
+                             that emitted the message.
+    --block <block>          Rewind to this block. Logs after it are replayed.
+                             Not allowed with `--direction all`.
+    --timestamp <timestamp>  Rewind to the last block before this unix timestamp.
+    --json                   Print output as JSON.
+    --log-format <format>    Log output format: `text` or `json` [default: text].
+    --shutdown-timeout <seconds>
+                             On SIGINT or SIGTERM wait that long for relays in
+                             flight to complete [default: 60].
+    --main-http <url>        Url of the main node.
+    --side-http <url>        Url of the side node.
+    --address <address>      Address of this authority.
+    --authorities <addresses>
+                             Comma separated addresses of all authorities.
+    --required-signatures <n>
+                             Signatures required to relay a message to main.
+                             Defaults to a majority of the authorities.
+    --main-contract <address>
+                             Address of the deployed main contract.
+    --side-contract <address>
+                             Address of the deployed side contract.
+    --manifest <manifest>    Deployment manifest written by `susy-bridge-deploy`.
+                             The contracts are verified before the database is written.
+                             Its authorities are the default for `--authorities`.
+    --main-bin <path>        Compiled main contract [default: compiled_contracts/Main.bin].
+    --side-bin <path>        Compiled side contract [default: compiled_contracts/Side.bin].
+    --force                  Overwrite existing files.
 "#,
         env!("CARGO_PKG_VERSION"),
         env!("GIT_HASH")
     );
 
-    info!("Parsing cli arguments");
     let args: Args = Docopt::new(usage)
         .and_then(|d| d.argv(command).deserialize())
         .map_err(|e| e.to_string())?;
 
+    init_logger(&args.flag_log_format)?;
+
+    if args.flag_print_config {
+        Config::print_layered(&args.arg_config, env::vars())
+    } else if args.cmd_status {
+        status(&args)
+    } else if args.cmd_relay {
+        relay(&args)
+    } else if args.cmd_rewind {
+        rewind(&args)
+    } else if args.cmd_check {
+        check(&args)
+    } else if args.cmd_init {
+        init(&args)
+    } else {
+        run(&args)
+    }
+}
+
+/// loads the config and connects to both chains
+fn setup(args: &Args) -> Result<(Config, Core, Http, Http), error::Error> {
     info!("Loading config from {:?}", args.arg_config);
-    let config = Config::load(&args.arg_config)?;
+    let config = Config::load_layered(&args.arg_config, env::vars())?;
 
     info!("Starting event loop");
-    let mut event_loop = Core::new().unwrap();
+    let event_loop = Core::new().unwrap();
 
     info!(
         "Establishing HTTP connection to susy node connected to main chain at {:?}",
@@ -127,12 +320,277 @@ Options:
         )
     })?;
 
+    Ok((config, event_loop, main_transport, side_transport))
+}
+
+fn status(args: &Args) -> Result<String, error::Error> {
+    let (config, mut event_loop, main_transport, side_transport) = setup(args)?;
+
+    info!("Loading database from {:?}", args.arg_database);
+    let database = TomlFileDatabase::from_path(&args.arg_database)?;
+    let state = database.read();
+
+    let query = StatusQuery {
+        main: bridge::MainContract::new(main_transport, &config, &state),
+        side: bridge::SideContract::new(side_transport, &config, &state),
+        state,
+    };
+    let status = event_loop
+        .run(query.query())
+        .expect("`StatusQuery::query` captures all errors in `Status`; qed");
+
+    if args.flag_json {
+        Ok(serde_json::to_string_pretty(&status).expect("serialization can't fail; qed"))
+    } else {
+        Ok(status.to_string())
+    }
+}
+
+fn relay(args: &Args) -> Result<String, error::Error> {
+    let direction = manual_relay::parse_direction(&args.flag_direction)?;
+    let tx_hash: H256 = args.flag_tx
+        .trim_left_matches("0x")
+        .parse()
+        .map_err(|_| format!("invalid transaction hash {:?}", args.flag_tx))?;
+
+    let (config, mut event_loop, main_transport, side_transport) = setup(args)?;
+
+    info!("Loading database from {:?}", args.arg_database);
+    let database = TomlFileDatabase::from_path(&args.arg_database)?;
+    let state = database.read();
+
+    let relay = ManualRelay {
+        main: bridge::MainContract::new(main_transport, &config, &state),
+        side: bridge::SideContract::new(side_transport, &config, &state),
+    };
+    let outcomes = event_loop.run(relay.relay(direction, tx_hash))?;
+
+    let lines = outcomes
+        .iter()
+        .map(|outcome| {
+            let log = match outcome.log_index {
+                Some(index) => format!("log {}", index),
+                None => "log".to_string(),
+            };
+            match outcome.target_tx_hash {
+                Some(target_tx_hash) => format!("{}: relayed in transaction {:?}", log, target_tx_hash),
+                None => format!("{}: already relayed, nothing to do", log),
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(lines.join("\n"))
+}
+
+fn rewind(args: &Args) -> Result<String, error::Error> {
+    let directions = match args.flag_direction.as_str() {
+        "all" => Direction::ALL.to_vec(),
+        direction => vec![manual_relay::parse_direction(direction)?],
+    };
+    let target = match (args.flag_block, args.flag_timestamp) {
+        // main and side block numbers are unrelated
+        (Some(_), None) if directions.len() > 1 => {
+            return Err("`--block` needs a single `--direction`. rewind `all` with `--timestamp`".into())
+        }
+        (Some(block), None) => Target::Block(block),
+        (None, Some(timestamp)) => Target::Timestamp(timestamp),
+        _ => unreachable!("docopt requires exactly one of `--block` and `--timestamp`; qed"),
+    };
+
+    let (config, mut event_loop, main_transport, side_transport) = setup(args)?;
+
+    info!("Loading database from {:?}", args.arg_database);
+    let mut database = TomlFileDatabase::from_path(&args.arg_database)?;
+    let state = database.read();
+
+    let planner = Rewind {
+        main: bridge::MainContract::new(main_transport, &config, &state),
+        side: bridge::SideContract::new(side_transport, &config, &state),
+    };
+    let rewinds = event_loop.run(planner.plan(&state, &directions, target))?;
+
+    let backup = database.backup()?;
+    database.write(&rewind::apply(&state, &rewinds))?;
+
+    let mut lines = rewinds.iter().map(|rewind| rewind.to_string()).collect::<Vec<_>>();
+    lines.push(format!("previous state backed up to {:?}", backup));
+    Ok(lines.join("\n"))
+}
+
+fn check(args: &Args) -> Result<String, error::Error> {
+    let (config, mut event_loop, main_transport, side_transport) = setup(args)?;
+
+    info!("Loading database from {:?}", args.arg_database);
+    let state = TomlFileDatabase::from_path(&args.arg_database)
+        .map(|database| database.read())
+        .map_err(|err| err.to_string());
+
+    let preflight = Preflight {
+        config,
+        state,
+        main_transport,
+        side_transport,
+    };
+    let report = event_loop
+        .run(preflight.run())
+        .expect("`Preflight::run` captures all errors in `Report`; qed");
+
+    if report.passed() {
+        Ok(report.to_string())
+    } else {
+        Err(report.to_string().into())
+    }
+}
+
+/// `value` if set. otherwise asks for it on stdin
+fn value_or_prompt(value: &Option<String>, question: &str) -> Result<String, error::Error> {
+    if let Some(ref value) = *value {
+        return Ok(value.clone());
+    }
+    print!("{}: ", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return Err(format!("{} is required", question).into());
+    }
+    Ok(answer.to_string())
+}
+
+fn parse_address(address: &str) -> Result<Address, error::Error> {
+    address
+        .trim()
+        .trim_left_matches("0x")
+        .parse()
+        .map_err(|_| format!("invalid address {:?}", address).into())
+}
+
+fn connect(url: &str, event_loop: &Core) -> Result<Http, error::Error> {
+    Http::with_event_loop(url, &event_loop.handle(), MAX_PARALLEL_REQUESTS)
+        .chain_err(|| format!("Cannot connect to susy node at {}", url))
+}
+
+fn init(args: &Args) -> Result<String, error::Error> {
+    let config_path = &args.arg_config[0];
+    for path in &[config_path, &args.arg_database] {
+        if path.exists() && !args.flag_force {
+            return Err(format!("{:?} already exists. pass `--force` to overwrite it", path).into());
+        }
+    }
+
+    let manifest = match args.flag_manifest {
+        Some(ref path) => Some(DeploymentManifest::load(path)?),
+        None => None,
+    };
+    let manifest_authorities = manifest.as_ref().map(|manifest| manifest.main.constructor_args.clone());
+
+    let main_http = value_or_prompt(&args.flag_main_http, "url of the main node")?;
+    let side_http = value_or_prompt(&args.flag_side_http, "url of the side node")?;
+    let address = parse_address(&value_or_prompt(&args.flag_address, "address of this authority")?)?;
+    let authorities = match (&args.flag_authorities, &manifest_authorities) {
+        (&None, &Some(ref authority_set)) => authority_set.accounts.clone(),
+        _ => value_or_prompt(&args.flag_authorities, "comma separated addresses of all authorities")?
+            .split(',')
+            .map(parse_address)
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    let required_signatures = args.flag_required_signatures
+        .or_else(|| manifest_authorities.as_ref().map(|authority_set| authority_set.required_signatures))
+        .unwrap_or_else(|| init::default_required_signatures(authorities.len()));
+    let options = InitOptions {
+        address,
+        main_http,
+        side_http,
+        required_signatures,
+        authorities,
+        main_contract_bin: args.flag_main_bin.clone(),
+        side_contract_bin: args.flag_side_bin.clone(),
+    };
+
+    let request_timeout = Duration::from_secs(INIT_REQUEST_TIMEOUT);
+    let mut event_loop = Core::new().unwrap();
+    let main_transport = connect(&options.main_http, &event_loop)?;
+    let side_transport = connect(&options.side_http, &event_loop)?;
+
+    info!("Querying defaults from both nodes");
+    let (main, side) = event_loop.run(
+        init::chain_defaults(&main_transport, request_timeout)
+            .join(init::chain_defaults(&side_transport, request_timeout)),
+    )?;
+
+    let rendered = init::render_config(&options, &main, &side);
+    let config = Config::load_from_str(&rendered).chain_err(|| "Generated config is invalid")?;
+    fs::write(config_path, rendered).chain_err(|| format!("Cannot write config to {:?}", config_path))?;
+    let mut written = vec![format!("wrote config to {:?}", config_path)];
+
+    match (&args.flag_main_contract, &args.flag_side_contract, manifest) {
+        (_, _, Some(manifest)) => {
+            info!("Verifying the contracts of the deployment manifest");
+            let state = manifest.state();
+            let verify = Verify {
+                config: config.clone(),
+                main: bridge::MainContract::new(main_transport, &config, &state),
+                side: bridge::SideContract::new(side_transport, &config, &state),
+            };
+            let verified = event_loop
+                .run(verify.run())
+                .expect("`Verify::run` captures all errors in `Report`; qed");
+            let report = Report {
+                checks: manifest
+                    .checks(&config, &main.network_id, &side.network_id)
+                    .into_iter()
+                    .chain(verified.checks)
+                    .collect(),
+            };
+            if !report.passed() {
+                return Err(format!("{}\ndeployment manifest doesn't match. database not written", report).into());
+            }
+            let file = fs::File::create(&args.arg_database)
+                .chain_err(|| format!("Cannot write database to {:?}", args.arg_database))?;
+            state.write(file)?;
+            written.push(format!("{}", report));
+            written.push(format!("wrote verified database to {:?}", args.arg_database));
+        }
+        (&Some(ref main_contract), &Some(ref side_contract), None) => {
+            let main_contract = parse_address(main_contract)?;
+            let side_contract = parse_address(side_contract)?;
+            info!("Looking up the deployment blocks of the contracts");
+            let (main_deployed_at, side_deployed_at) = event_loop.run(
+                init::deployed_at_block(main_transport, main_contract, request_timeout)
+                    .join(init::deployed_at_block(side_transport, side_contract, request_timeout)),
+            )?;
+            let state = init::initial_state(main_contract, main_deployed_at, side_contract, side_deployed_at);
+            let file = fs::File::create(&args.arg_database)
+                .chain_err(|| format!("Cannot write database to {:?}", args.arg_database))?;
+            state.write(file)?;
+            written.push(format!(
+                "wrote database to {:?}. main contract deployed at block {}, side contract at block {}",
+                args.arg_database, main_deployed_at, side_deployed_at
+            ));
+        }
+        _ => written.push("pass `--main-contract` and `--side-contract` or `--manifest` to write the database as well".into()),
+    }
+
+    written.push(format!(
+        "review the config and run `susy-bridge check --config {:?} --database {:?}`",
+        config_path, args.arg_database
+    ));
+    Ok(written.join("\n"))
+}
+
+fn run(args: &Args) -> Result<String, error::Error> {
+    let (config, mut event_loop, main_transport, side_transport) = setup(args)?;
+
     info!("Loading database from {:?}", args.arg_database);
     let mut database = TomlFileDatabase::from_path(&args.arg_database)?;
 
     info!("Reading initial state from database");
     let initial_state = database.read();
 
+    let quarantine_file = quarantine::file_path(&args.arg_database);
+    info!("Loading quarantined logs from {:?}", quarantine_file);
+    quarantine::persist_to(quarantine_file)?;
+
     let main_contract = bridge::MainContract::new(main_transport.clone(), &config, &initial_state);
     event_loop
         .run(main_contract.is_main_contract())
@@ -144,7 +602,7 @@ Options:
         )
         })?;
 
-    let side_contract = bridge::SideContract::new(side_transport.clone(), &config, &initial_state);
+    let mut side_contract = bridge::SideContract::new(side_transport.clone(), &config, &initial_state);
     event_loop
         .run(side_contract.is_side_contract())
         .chain_err(|| {
@@ -155,15 +613,264 @@ Options:
         )
         })?;
 
+    info!("Verifying contract code against `main.contract.bin` and `side.contract.bin` if given");
+    event_loop
+        .run(verify::verify_code(&main_transport, &config.main, initial_state.main_contract_address))
+        .chain_err(|| "main contract doesn't match `main.contract.bin`")?;
+    event_loop
+        .run(verify::verify_code(&side_transport, &config.side, initial_state.side_contract_address))
+        .chain_err(|| "side contract doesn't match `side.contract.bin`")?;
+
+    info!("Reading authorities from both contracts");
+    let configured_authorities = AuthoritySet::from_config(&config.authorities);
+    let on_chain_authorities = event_loop
+        .run(authority_set::on_chain_authorities(&main_contract, &side_contract))
+        .chain_err(|| "reading the authorities of the contracts failed")?;
+    for drift in on_chain_authorities.drift(&configured_authorities) {
+        error!("authorities differ. {}", drift);
+    }
+    side_contract.required_signatures = on_chain_authorities.side.required_signatures;
+    // latest authorities of the contracts. they decide which signatures count
+    let on_chain_authorities = Rc::new(RefCell::new(on_chain_authorities));
+
+    // latest persisted state. shared with the http endpoints
+    let shared_state = Rc::new(RefCell::new(initial_state.clone()));
+
+    let tracker = config.tracker.as_ref().map(|tracker_config| {
+        let main_after = initial_state.last_main_to_side_sign_at_block;
+        let side_after = cmp::min(
+            initial_state.last_side_to_main_sign_at_block,
+            initial_state.last_side_to_main_signatures_at_block,
+        );
+        info!("Starting message tracker");
+        let tracker = Rc::new(RefCell::new(MessageTracker::new(
+            tracker_config.stuck_after_blocks,
+            main_after,
+            side_after,
+        )));
+        event_loop.handle().spawn(tracker::track(
+            tracker.clone(),
+            main_contract.message_lifecycle_log_stream(main_after),
+            side_contract.message_lifecycle_log_stream(side_after),
+        ));
+        tracker
+    });
+
+    let participation = config.participation.as_ref().map(|participation_config| {
+        info!("Starting authority participation monitor");
+        let authorities = on_chain_authorities.borrow().side.clone();
+        let monitor = Rc::new(RefCell::new(ParticipationMonitor::new(
+            authorities.accounts,
+            authorities.required_signatures,
+            participation_config,
+        )));
+        let tracker = tracker
+            .as_ref()
+            .expect("`[participation]` requires `[tracker]` which is checked when loading the config; qed");
+        participation::watch(
+            &mut tracker.borrow_mut(),
+            ParticipationLookup {
+                main: main_contract.clone(),
+                side: side_contract.clone(),
+            },
+            monitor.clone(),
+            event_loop.handle(),
+        );
+        monitor
+    });
+
+    // replaced on reload
+    let health = Rc::new(RefCell::new(HealthCheck::new(main_transport.clone(), side_transport.clone(), &config)));
+
+    if let Some(ref health_config) = config.health {
+        info!("Starting health endpoints on {}", health_config.addr);
+        let health = health.clone();
+        let state = shared_state.clone();
+        let tracker = tracker.clone();
+        let participation = participation.clone();
+        let server = http_server::serve(
+            &health_config.addr,
+            &event_loop.handle(),
+            move |request| -> Box<Future<Item = Response, Error = ()>> {
+                if request.path == "/metrics" {
+                    let mut families = Vec::new();
+                    if let Some(ref tracker) = tracker {
+                        families.extend(tracker.borrow().metrics());
+                    }
+                    if let Some(ref participation) = participation {
+                        families.extend(participation.borrow().metrics());
+                    }
+                    return Box::new(future::ok(Response::text(200, metrics::render(&families))));
+                }
+                health.borrow().respond(&request, &state.borrow())
+            },
+        )?;
+        event_loop.handle().spawn(server);
+    }
+
+    // replaced on reload
+    let manual_relay = Rc::new(RefCell::new(ManualRelay {
+        main: main_contract.clone(),
+        side: side_contract.clone(),
+    }));
+
     let bridge_stream = bridge::Bridge::new(initial_state, main_contract, side_contract);
+    let shutdown = bridge_stream.shutdown_handle();
+    let reload = bridge_stream.reload_handle();
+
+    if let Some(ref admin_config) = config.admin {
+        info!("Starting admin API on {}", admin_config.addr);
+        let admin = AdminApi {
+            health: health.clone(),
+            relay: manual_relay.clone(),
+            state: shared_state.clone(),
+            pauses: Direction::ALL
+                .iter()
+                .map(|&direction| (direction, bridge_stream.pause_handle(direction)))
+                .collect(),
+            token: admin_config.token.clone(),
+        };
+        let server = http_server::serve(&admin_config.addr, &event_loop.handle(), move |request| {
+            admin.respond(request)
+        })?;
+        event_loop.handle().spawn(server);
+    }
+
+    let balance_thresholds = config.balance.as_ref().map(|balance_config| {
+        info!("Starting authority balance monitor");
+        let monitor = BalanceMonitor::new(
+            main_transport.clone(),
+            side_transport.clone(),
+            config.address,
+            config.main.request_timeout,
+            config.side.request_timeout,
+            balance_config,
+            &bridge_stream,
+        );
+        let thresholds = monitor.thresholds_handle();
+        event_loop.handle().spawn(monitor.run());
+        thresholds
+    });
+
+    // replaced on reload
+    let current_config = Rc::new(RefCell::new(config.clone()));
+
+    {
+        info!("Watching the authorities of the contracts");
+        let main_transport = main_transport.clone();
+        let side_transport = side_transport.clone();
+        let current_config = current_config.clone();
+        let state = shared_state.clone();
+        let reload = reload.clone();
+        let manual_relay = manual_relay.clone();
+        let participation = participation.clone();
+        let watch = AuthorityWatch {
+            main: main_contract.clone(),
+            side: side_contract.clone(),
+            configured: configured_authorities,
+            poll_interval: Duration::from_secs(AUTHORITIES_POLL_INTERVAL),
+            current: on_chain_authorities.clone(),
+            on_change: Rc::new(move |authorities: &OnChainAuthorities| {
+                let (main, side) = contracts(&main_transport, &side_transport, &current_config.borrow(), &state.borrow(), authorities);
+                reload.reload(main.clone(), side.clone());
+                *manual_relay.borrow_mut() = ManualRelay { main, side };
+                if let Some(ref participation) = participation {
+                    participation
+                        .borrow_mut()
+                        .set_authorities(authorities.side.accounts.clone(), authorities.side.required_signatures);
+                }
+            }),
+        };
+        event_loop.handle().spawn(watch.run());
+    }
+
+    let config_path = args.arg_config.clone();
+    let reload_state = shared_state.clone();
+    let reloads = Signal::new(SIGHUP)
+        .flatten_stream()
+        .map_err(|err| error!("listening for SIGHUP failed: {}", err))
+        .for_each(move |_| -> Result<(), ()> {
+            info!("Received SIGHUP. Reloading config from {:?}", config_path);
+            let new_config = match Config::load_layered(&config_path, env::vars())
+                .and_then(|new_config| current_config.borrow().check_reloadable(&new_config).map(|_| new_config))
+            {
+                Ok(new_config) => new_config,
+                Err(err) => {
+                    error!("Rejected config reload: {}", err);
+                    return Ok(());
+                }
+            };
+
+            let (main, side) = contracts(
+                &main_transport,
+                &side_transport,
+                &new_config,
+                &reload_state.borrow(),
+                &on_chain_authorities.borrow(),
+            );
+            reload.reload(main.clone(), side.clone());
+            *manual_relay.borrow_mut() = ManualRelay { main, side };
+            *health.borrow_mut() = HealthCheck::new(main_transport.clone(), side_transport.clone(), &new_config);
+            if let (Some(thresholds), Some(balance_config)) = (balance_thresholds.as_ref(), new_config.balance.as_ref()) {
+                thresholds.set(balance_config);
+            }
+            *current_config.borrow_mut() = new_config;
+            info!("Reloaded config from {:?}", config_path);
+            Ok(())
+        });
+    event_loop.handle().spawn(reloads);
+
     info!("Started polling logs");
-    let persisted_bridge_stream = bridge_stream.and_then(|state| {
+    let persisted_bridge_stream = Supervised::new(bridge_stream, Backoff::default()).and_then(|state| {
         database.write(&state)?;
+        *shared_state.borrow_mut() = state;
         // info!("state change: {}", state);
         Ok(())
     });
 
-    event_loop.run(persisted_bridge_stream.last())?;
+    let handle = event_loop.handle();
+    let shutdown_timeout = Duration::from_secs(args.flag_shutdown_timeout);
+    let shutdown_timed_out = shutdown_signal().and_then(move |signal| {
+        info!(
+            "Received signal {}. Waiting up to {:?} for relays in flight to complete",
+            signal, shutdown_timeout
+        );
+        shutdown.shutdown();
+        future::result(Timeout::new(shutdown_timeout, &handle))
+            .flatten()
+            .from_err()
+    });
+
+    // the bridge stream only ends after a shutdown
+    match event_loop.run(persisted_bridge_stream.last().select2(shutdown_timed_out)) {
+        Ok(Either::A(_)) => Ok("Shut down after all relays in flight completed".into()),
+        Ok(Either::B(_)) => Err(ErrorKind::ShutdownTimedOut.into()),
+        Err(Either::A((err, _))) | Err(Either::B((err, _))) => Err(err),
+    }
+}
+
+/// contracts configured by `config` that collect the signatures the side contract requires
+fn contracts(
+    main_transport: &Http,
+    side_transport: &Http,
+    config: &Config,
+    state: &State,
+    authorities: &OnChainAuthorities,
+) -> (bridge::MainContract<Http>, bridge::SideContract<Http>) {
+    let mut side = bridge::SideContract::new(side_transport.clone(), config, state);
+    side.required_signatures = authorities.side.required_signatures;
+    (bridge::MainContract::new(main_transport.clone(), config, state), side)
+}
 
-    Ok("Done".into())
+/// `Future` resolving to the first SIGINT or SIGTERM received by the process
+fn shutdown_signal() -> Box<Future<Item = i32, Error = error::Error>> {
+    let signals = Signal::new(SIGINT)
+        .flatten_stream()
+        .select(Signal::new(SIGTERM).flatten_stream());
+    Box::new(
+        signals
+            .into_future()
+            .map_err(|(err, _)| error::Error::from(err))
+            .and_then(|(signal, _)| signal.ok_or_else(|| "signal stream ended".into())),
+    )
 }
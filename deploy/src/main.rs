@@ -26,22 +26,38 @@ extern crate tokio_core;
 extern crate susyweb;
 
 use docopt::Docopt;
+use futures::Future;
 use std::path::PathBuf;
 use std::{env, fs};
 use tokio_core::reactor::Core;
+use susyweb::api::{Namespace, Net};
 use susyweb::transports::http::Http;
 
-use bridge::config::Config;
-use bridge::database::State;
-use bridge::deploy::{DeployMain, DeploySide};
+use bridge::config::{Config, DeployConfig};
+use bridge::check::Report;
+use bridge::deploy::{attach, estimate_main_deployment, estimate_side_deployment, Attach, ContractProgress, DeployMain,
+                     DeployProgress, DeploySide, OnSent};
+use bridge::database::{Database, State, TomlFileDatabase};
+use bridge::helpers::with_timeout;
+use bridge::init;
+use bridge::manifest::DeploymentManifest;
+use bridge::relay_log::{Chain, Direction};
+use bridge::verify::Verify;
 use bridge::error::{self, ResultExt};
 
 const MAX_PARALLEL_REQUESTS: usize = 10;
 
 #[derive(Debug, Deserialize)]
 pub struct Args {
-    arg_config: PathBuf,
+    cmd_verify: bool,
+    arg_config: Vec<PathBuf>,
     arg_database: PathBuf,
+    flag_progress: PathBuf,
+    flag_manifest: PathBuf,
+    flag_dry_run: bool,
+    flag_only: Option<String>,
+    flag_attach_main: Option<String>,
+    flag_attach_side: Option<String>,
 }
 
 fn main() {
@@ -75,11 +91,39 @@ Susy-bridge
     Commit: {}
 
 Usage:
-    susy-bridge-deploy --config <config> --database <database>
+    susy-bridge-deploy (--config <config>)... --database <database> [--progress <progress>] [--manifest <manifest>] [--dry-run] [--only <contract>] [--attach-main <contract>] [--attach-side <contract>]
+    susy-bridge-deploy verify (--config <config>)... --database <database>
     susy-bridge-deploy -h | --help
 
+Commands:
+    verify               Verify the code and authorities of the contracts in the database.
+                         Also done after every deployment.
+
+Config:
+    Every further `--config` overrides the values of the files before it.
+    Settings only needed for deployment, `main.contract`, `side.contract`
+    and `[deploy]`, can be kept in their own file passed after the bridge config.
+
 Options:
     -h, --help           Display help message and exit.
+    --progress <progress>
+                         File the deployment progress is saved to after every step.
+                         A rerun resumes from it [default: deployment_progress.json].
+    --manifest <manifest>
+                         File the deployment manifest is written to. Other authorities
+                         pass it to `susy-bridge init --manifest`
+                         [default: deployment_manifest.json].
+    --dry-run            Estimate the gas and cost of the deployments a real run with
+                         the same `--only` and `--attach-...` would send and check
+                         the balance of `address`. Sends no transactions.
+    --only <contract>    Deploy only the `main` or the `side` contract. The other one is
+                         taken from `--attach-...` or the existing database.
+    --attach-main <contract>
+                         Use the existing main contract with this address or created
+                         by this transaction instead of deploying one.
+    --attach-side <contract>
+                         Use the existing side contract with this address or created
+                         by this transaction instead of deploying one.
 "#,
         env!("CARGO_PKG_VERSION"),
         env!("GIT_HASH")
@@ -91,7 +135,8 @@ Options:
         .map_err(|e| e.to_string())?;
 
     info!(target: "susy-bridge-deploy", "Loading config");
-    let config = Config::load(args.arg_config)?;
+    let config = Config::load_layered(&args.arg_config, env::vars())?;
+    let deploy_config = DeployConfig::load_layered(&args.arg_config, env::vars())?;
 
     info!(target: "susy-bridge-deploy", "Starting event loop");
     let mut event_loop = Core::new().unwrap();
@@ -118,27 +163,210 @@ Options:
             MAX_PARALLEL_REQUESTS,
         ).chain_err(|| format!("Cannot connect to side at {}", config.side.http))?;
 
-    info!(target: "susy-bridge-deploy", "Deploying MainBridge contract");
-    let main_deployed = event_loop.run(DeployMain::new(config.clone(), main_transport))?;
-    info!(target: "susy-bridge-deploy", "Successfully deployed MainBridge contract");
+    if args.cmd_verify {
+        info!(target: "susy-bridge-deploy", "Loading database");
+        let state = TomlFileDatabase::from_path(&args.arg_database)?.read();
+        let report = verify(&config, &state, &mut event_loop, main_transport, side_transport)?;
+        return Ok(report);
+    }
+
+    let (deploy_main, deploy_side) = match args.flag_only.as_ref().map(String::as_str) {
+        None => (true, true),
+        Some("main") => (true, false),
+        Some("side") => (false, true),
+        Some(other) => return Err(format!("--only must be `main` or `side`, got {:?}", other).into()),
+    };
+
+    let mut progress = DeployProgress::load(&args.flag_progress)?;
+
+    // a contract neither deployed nor attached is taken from the existing database
+    let existing = if args.arg_database.exists() {
+        Some(TomlFileDatabase::from_path(&args.arg_database)?.read())
+    } else {
+        None
+    };
+
+    if let (true, Some(existing)) = (args.flag_only.is_some(), existing.as_ref()) {
+        // the contract in the database is the one to replace. a contract deployed
+        // by an interrupted run isn't in the database yet and is kept
+        if deploy_main && progress.main.address() == Some(existing.main_contract_address) {
+            progress.main = ContractProgress::NotDeployed;
+        }
+        if deploy_side && progress.side.address() == Some(existing.side_contract_address) {
+            progress.side = ContractProgress::NotDeployed;
+        }
+        if !args.flag_dry_run {
+            progress.save(&args.flag_progress)?;
+        }
+    }
+
+    if args.flag_dry_run {
+        // only the contracts a real run would deploy
+        let estimate_main = deploy_main && args.flag_attach_main.is_none() && progress.main.address().is_none();
+        let estimate_side = deploy_side && args.flag_attach_side.is_none() && progress.side.address().is_none();
+        let mut checks = Vec::new();
+        if estimate_main {
+            info!(target: "susy-bridge-deploy", "Estimating MainBridge deployment cost");
+            checks.extend(event_loop.run(estimate_main_deployment(&config, &deploy_config, &main_transport))?.checks());
+        }
+        if estimate_side {
+            info!(target: "susy-bridge-deploy", "Estimating SideBridge deployment cost");
+            checks.extend(event_loop.run(estimate_side_deployment(&config, &deploy_config, &side_transport))?.checks());
+        }
+        let report = Report { checks };
+        return if report.passed() {
+            Ok(report.to_string())
+        } else {
+            Err(report.to_string().into())
+        };
+    }
+
+    if let Some(ref contract) = args.flag_attach_main {
+        info!(target: "susy-bridge-deploy", "Attaching to MainBridge contract {}", contract);
+        let contract = contract.parse::<Attach>()?;
+        progress.main = event_loop.run(attach(main_transport.clone(), config.main.request_timeout, contract))?;
+        progress.save(&args.flag_progress)?;
+    }
+
+    if let Some(ref contract) = args.flag_attach_side {
+        info!(target: "susy-bridge-deploy", "Attaching to SideBridge contract {}", contract);
+        let contract = contract.parse::<Attach>()?;
+        progress.side = event_loop.run(attach(side_transport.clone(), config.side.request_timeout, contract))?;
+        progress.save(&args.flag_progress)?;
+    }
+
+    match progress.main {
+        ContractProgress::Deployed { address, .. } => {
+            info!(target: "susy-bridge-deploy", "MainBridge contract already deployed to {:?}", address)
+        }
+        _ if !deploy_main => info!(target: "susy-bridge-deploy", "Skipping MainBridge contract"),
+        _ => {
+            info!(target: "susy-bridge-deploy", "Deploying MainBridge contract");
+            let on_sent = save_pending(progress.clone(), args.flag_progress.clone(), |progress, pending| progress.main = pending);
+            let main_deployed = event_loop.run(
+                DeployMain::new(config.clone(), &deploy_config, main_transport.clone())?
+                    .resume(progress.main.pending())
+                    .on_sent(on_sent),
+            )?;
+            info!(target: "susy-bridge-deploy", "Successfully deployed MainBridge contract");
+            progress.set_deployed(&main_deployed);
+            progress.save(&args.flag_progress)?;
 
-    main_deployed.dump_info(format!(
-        "deployment-main-{}",
-        main_deployed.contract_address
-    ))?;
+            main_deployed.dump_info(format!(
+                "deployment-main-{}",
+                main_deployed.contract_address
+            ))?;
+        }
+    }
 
-    info!(target: "susy-bridge-deploy", "Deploying SideBridge contract");
-    let side_deployed = event_loop.run(DeploySide::new(config.clone(), side_transport))?;
-    info!(target: "susy-bridge-deploy", "Successfully deployed SideBridge contract");
+    match progress.side {
+        ContractProgress::Deployed { address, .. } => {
+            info!(target: "susy-bridge-deploy", "SideBridge contract already deployed to {:?}", address)
+        }
+        _ if !deploy_side => info!(target: "susy-bridge-deploy", "Skipping SideBridge contract"),
+        _ => {
+            info!(target: "susy-bridge-deploy", "Deploying SideBridge contract");
+            let on_sent = save_pending(progress.clone(), args.flag_progress.clone(), |progress, pending| progress.side = pending);
+            let side_deployed = event_loop.run(
+                DeploySide::new(config.clone(), &deploy_config, side_transport.clone())?
+                    .resume(progress.side.pending())
+                    .on_sent(on_sent),
+            )?;
+            info!(target: "susy-bridge-deploy", "Successfully deployed SideBridge contract");
+            progress.set_deployed(&side_deployed);
+            progress.save(&args.flag_progress)?;
 
-    side_deployed.dump_info(format!(
-        "deployment-side-{}",
-        side_deployed.contract_address
-    ))?;
+            side_deployed.dump_info(format!(
+                "deployment-side-{}",
+                side_deployed.contract_address
+            ))?;
+        }
+    }
 
-    let state = State::from_transaction_receipts(&main_deployed.receipt, &side_deployed.receipt);
+    let missing = || -> error::Error {
+        format!(
+            "both contracts are needed for {}. deploy the missing one or pass --attach-main/--attach-side",
+            args.arg_database.display()
+        ).into()
+    };
+    let (main_address, main_block) = match (&progress.main, &existing) {
+        (&ContractProgress::Deployed { address, block, .. }, _) => (address, block),
+        (_, &Some(ref existing)) => (existing.main_contract_address, existing.main_deployed_at_block),
+        _ => return Err(missing()),
+    };
+    let (side_address, side_block) = match (&progress.side, &existing) {
+        (&ContractProgress::Deployed { address, block, .. }, _) => (address, block),
+        (_, &Some(ref existing)) => (existing.side_contract_address, existing.side_deployed_at_block),
+        _ => return Err(missing()),
+    };
+    let mut state = init::initial_state(main_address, main_block, side_address, side_block);
+    if let Some(ref existing) = existing {
+        keep_checkpoints(&mut state, existing);
+    }
     info!(target: "susy-bridge-deploy", "\n\n{}\n", state);
-    state.write(fs::File::create(args.arg_database)?)?;
+    // written before verifying so that the deployed contracts aren't lost if verification fails
+    state.write(fs::File::create(&args.arg_database)?)?;
+    let report = verify(&config, &state, &mut event_loop, main_transport.clone(), side_transport.clone())
+        .chain_err(|| {
+            format!(
+                "the contracts were written to {} but their verification failed. rerun `susy-bridge-deploy verify` once fixed",
+                args.arg_database.display()
+            )
+        })?;
+    info!(target: "susy-bridge-deploy", "Verified deployed contracts\n{}", report);
+
+    let network_ids = event_loop.run(
+        with_timeout(Net::new(&main_transport).version(), config.main.request_timeout)
+            .join(with_timeout(Net::new(&side_transport).version(), config.side.request_timeout)),
+    )?;
+    let transaction_hashes = (progress.main.transaction_hash(), progress.side.transaction_hash());
+    let manifest = DeploymentManifest::new(&config, &state, network_ids, transaction_hashes)?;
+    manifest.save(&args.flag_manifest)?;
+    info!(target: "susy-bridge-deploy", "Wrote deployment manifest to {:?}", args.flag_manifest);
 
     Ok("Done".into())
 }
+
+/// copies the checkpoints of the contracts in `existing` that `state` still uses
+fn keep_checkpoints(state: &mut State, existing: &State) {
+    for direction in Direction::ALL.iter().cloned() {
+        let unchanged = match direction.source_chain() {
+            Chain::Main => state.main_contract_address == existing.main_contract_address,
+            Chain::Side => state.side_contract_address == existing.side_contract_address,
+        };
+        if unchanged {
+            state.set_relay_checkpoint(direction, existing.relay_checkpoint(direction));
+        }
+    }
+}
+
+/// saves `progress` with the deployment transaction set to pending by `set` once it was sent
+fn save_pending<F>(progress: DeployProgress, path: PathBuf, set: F) -> OnSent
+where
+    F: Fn(&mut DeployProgress, ContractProgress) + 'static,
+{
+    Box::new(move |transaction_hash| {
+        let mut progress = progress.clone();
+        set(&mut progress, ContractProgress::Pending { transaction_hash });
+        progress.save(&path)
+    })
+}
+
+/// verifies the contracts in `state`. fails if any check fails
+fn verify(config: &Config, state: &State, event_loop: &mut Core, main_transport: Http, side_transport: Http) -> Result<String, error::Error> {
+    info!(target: "susy-bridge-deploy", "Verifying deployed contracts");
+    let verify = Verify {
+        config: config.clone(),
+        main: bridge::MainContract::new(main_transport, config, state),
+        side: bridge::SideContract::new(side_transport, config, state),
+    };
+    let report = event_loop
+        .run(verify.run())
+        .expect("`Verify::run` captures all errors in `Report`; qed");
+
+    if report.passed() {
+        Ok(report.to_string())
+    } else {
+        Err(report.to_string().into())
+    }
+}